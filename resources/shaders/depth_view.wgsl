@@ -0,0 +1,34 @@
+// Debug view of the depth buffer, drawn over the whole frame in grayscale.
+
+struct DepthViewUniform {
+    znear: f32,
+    zfar: f32,
+}
+// Bound as a float texture rather than a depth one, which not every backend can load from.
+// Swapped for `texture_multisampled_2d<f32>` when the depth buffer is multisampled;
+// `textureLoad`'s last argument then picks the sample instead of the mip level.
+@group(0) @binding(0)
+var depth_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> depth_view: DepthViewUniform;
+
+// One triangle covering the screen.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let depth = textureLoad(depth_texture, vec2<i32>(position.xy), 0).r;
+
+    // Undoes the perspective projection's depth mapping, giving the distance along the view.
+    let near = depth_view.znear;
+    let far = depth_view.zfar;
+    let distance = near * far / (far - depth * (far - near));
+
+    // On a log scale, so detail up close and far away both show. The sky is white.
+    let gray = log(distance / near) / log(far / near);
+    return vec4<f32>(vec3<f32>(gray), 1.0);
+}
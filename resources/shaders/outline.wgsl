@@ -0,0 +1,42 @@
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+    // Corner of the unit cube, each component 0 or 1.
+    @location(0) corner: vec3<f32>,
+    @location(1) box_min: vec3<f32>,
+    @location(2) box_max: vec3<f32>,
+}
+
+struct OutlineUniform {
+    // Subtracted from the normalized depth, so the same bias holds at any distance.
+    depth_bias: f32,
+}
+@group(1) @binding(0)
+var<uniform> outline: OutlineUniform;
+
+fn box_corner(in: VertexInput) -> vec4<f32> {
+    let position = in.box_min + in.corner * (in.box_max - in.box_min);
+    return camera.view_proj * vec4<f32>(position, 1.0);
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> @builtin(position) vec4<f32> {
+    return box_corner(in);
+}
+
+// Pulled towards the camera so outlines don't flicker against the faces they're drawn on.
+@vertex
+fn vs_outline(in: VertexInput) -> @builtin(position) vec4<f32> {
+    var position = box_corner(in);
+    position.z -= outline.depth_bias * position.w;
+    return position;
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(0.0, 0.0, 0.0, 0.8);
+}
@@ -8,23 +8,80 @@ struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
     @location(2) normal: vec3<f32>,
+    @location(3) tile: vec2<f32>,
+    @location(4) light: f32,
+    @location(5) seam: f32,
+    @location(6) block_flags: u32,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) tile: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) light: f32,
+    @location(4) seam: f32,
+    @location(5) world_position: vec3<f32>,
+    @location(6) @interpolate(flat) block_flags: u32,
+}
+
+// Packed `CompactVertex`, see mesh.rs for the bit layout.
+struct CompactVertexInput {
+    @location(0) packed: vec2<u32>,
+    @location(1) chunk_origin: vec3<f32>,
 }
 
+// Must match `LIQUID_DROP` in mesh.rs.
+const LIQUID_DROP: f32 = 0.125;
+
+// Same order as `FaceDirection::ALL`.
+const FACE_NORMALS: array<vec3<f32>, 6> = array<vec3<f32>, 6>(
+    vec3<f32>(0.0, 0.0, 1.0),
+    vec3<f32>(0.0, 0.0, -1.0),
+    vec3<f32>(1.0, 0.0, 0.0),
+    vec3<f32>(-1.0, 0.0, 0.0),
+    vec3<f32>(0.0, 1.0, 0.0),
+    vec3<f32>(0.0, -1.0, 0.0),
+);
+
 @vertex
 fn vs_main(
     model: VertexInput,
 ) -> VertexOutput {
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
+    out.tile = model.tile;
+    out.normal = model.normal;
+    out.light = model.light;
+    out.seam = model.seam;
+    out.block_flags = model.block_flags;
+    out.world_position = model.position;
     out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
     return out;
 }
 
+@vertex
+fn vs_compact(
+    model: CompactVertexInput,
+) -> VertexOutput {
+    let a = model.packed.x;
+    let b = model.packed.y;
+    var local = vec3<f32>(f32(extractBits(a, 0u, 5u)), f32(extractBits(a, 5u, 5u)), f32(extractBits(a, 10u, 5u)));
+    local.y -= f32(extractBits(a, 29u, 1u)) * LIQUID_DROP;
+    let position = model.chunk_origin + local;
+
+    var out: VertexOutput;
+    out.tex_coords = vec2<f32>(f32(extractBits(a, 15u, 5u)), f32(extractBits(a, 20u, 5u)));
+    out.tile = vec2<f32>(f32(extractBits(b, 0u, 4u)), f32(extractBits(b, 4u, 4u))) * ATLAS_TILE_SIZE;
+    out.normal = FACE_NORMALS[extractBits(a, 25u, 3u)];
+    out.light = f32(extractBits(b, 8u, 4u)) / MAX_LIGHT;
+    out.seam = f32(extractBits(a, 28u, 1u));
+    out.block_flags = extractBits(b, 12u, 2u);
+    out.world_position = position;
+    out.clip_position = camera.view_proj * vec4<f32>(position, 1.0);
+    return out;
+}
+
 // Fragment shader
 
 @group(0) @binding(0)
@@ -32,7 +89,101 @@ var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
+// Must match `MAX_POINT_LIGHTS` in lighting.rs.
+const MAX_POINT_LIGHTS: u32 = 8u;
+
+struct PointLight {
+    position: vec3<f32>,
+    range: f32,
+    color: vec3<f32>,
+}
+
+struct LightingUniform {
+    sun_direction: vec3<f32>,
+    ambient: f32,
+    highlight_seams: u32,
+    speed_factor: f32,
+    sun_intensity: f32,
+    highlight_blocks: u32,
+    fog_color: vec3<f32>,
+    fog_start: f32,
+    camera_position: vec3<f32>,
+    fog_end: f32,
+    point_light_count: u32,
+    point_lights: array<PointLight, MAX_POINT_LIGHTS>,
+};
+@group(2) @binding(0)
+var<uniform> lighting: LightingUniform;
+
+// Must match the tile count passed to TextureAtlas::new.
+const ATLAS_TILE_SIZE: f32 = 1.0 / 16.0;
+// Each tile fills the middle half of its atlas cell; see TextureAtlas.
+const ATLAS_TILE_PADDING: f32 = ATLAS_TILE_SIZE / 4.0;
+const ATLAS_TILE_INTERIOR: f32 = ATLAS_TILE_SIZE / 2.0;
+
+// Must match `MAX_LIGHT` in game/lighting.rs.
+const MAX_LIGHT: f32 = 15.0;
+
+// Each light level below full is 20% darker than the one above it.
+const LIGHT_FALLOFF: f32 = 0.8;
+const MIN_BRIGHTNESS: f32 = 0.05;
+
+// Fraction the fog distances shrink by at full speed.
+const SPEED_FOG_STRENGTH: f32 = 0.4;
+
+const SEAM_COLOR: vec3<f32> = vec3<f32>(1.0, 0.0, 1.0);
+
+// Bits of `block_flags`, and the `BlockHighlight` modes picking one of them. Must match
+// `BLOCK_NON_SOLID` and `BLOCK_TRANSPARENT` in mesh.rs.
+const BLOCK_NON_SOLID: u32 = 1u;
+const BLOCK_TRANSPARENT: u32 = 2u;
+const NON_SOLID_COLOR: vec3<f32> = vec3<f32>(0.0, 1.0, 1.0);
+const TRANSPARENT_COLOR: vec3<f32> = vec3<f32>(1.0, 0.5, 0.0);
+
+// Light added by the point lights, fading quadratically to nothing at each light's range.
+fn point_lighting(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    var total = vec3<f32>(0.0);
+    for (var i = 0u; i < lighting.point_light_count; i++) {
+        let light = lighting.point_lights[i];
+        let offset = light.position - position;
+        let distance = length(offset);
+        let falloff = clamp(1.0 - distance / light.range, 0.0, 1.0);
+        let facing = max(dot(normal, offset / max(distance, 0.0001)), 0.0);
+        total += light.color * facing * falloff * falloff;
+    }
+    return total;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    // tex_coords are in tiles, so merged faces repeat the tile instead of stretching it. The
+    // wrap makes the coordinates jump at each repeat, so the mip level is picked from the
+    // unwrapped ones instead; otherwise those edges would show up as lines of the smallest mip.
+    let uv = in.tile + ATLAS_TILE_PADDING + fract(in.tex_coords) * ATLAS_TILE_INTERIOR;
+    let ddx = dpdx(in.tex_coords) * ATLAS_TILE_INTERIOR;
+    let ddy = dpdy(in.tex_coords) * ATLAS_TILE_INTERIOR;
+    let color = textureSampleGrad(t_diffuse, s_diffuse, uv, ddx, ddy);
+
+    let normal = normalize(in.normal);
+    let diffuse = max(dot(normal, -lighting.sun_direction) * lighting.sun_intensity, lighting.ambient);
+    let brightness = max(pow(LIGHT_FALLOFF, (1.0 - in.light) * 15.0), MIN_BRIGHTNESS);
+    var rgb = color.rgb * (diffuse * brightness + point_lighting(in.world_position, normal));
+
+    let distance = length(in.world_position - lighting.camera_position);
+    let fog_scale = 1.0 - SPEED_FOG_STRENGTH * lighting.speed_factor;
+    let fog_start = lighting.fog_start * fog_scale;
+    let fog_end = lighting.fog_end * fog_scale;
+    let fog = clamp((distance - fog_start) / (fog_end - fog_start), 0.0, 1.0);
+    rgb = mix(rgb, lighting.fog_color, fog);
+
+    if (lighting.highlight_seams != 0u && in.seam > 0.5) {
+        rgb = mix(rgb, SEAM_COLOR, 0.7);
+    }
+
+    if ((in.block_flags & lighting.highlight_blocks) != 0u) {
+        let tint = select(TRANSPARENT_COLOR, NON_SOLID_COLOR, lighting.highlight_blocks == BLOCK_NON_SOLID);
+        rgb = mix(rgb, tint, 0.6);
+    }
+
+    return vec4<f32>(rgb, color.a);
 }
\ No newline at end of file
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 use winit::application::ApplicationHandler;
-use winit::event::{DeviceEvent, DeviceId, ElementState, KeyEvent, MouseButton, WindowEvent};
+use winit::event::{DeviceEvent, DeviceId, ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, EventLoop};
 use winit::keyboard::PhysicalKey;
 use winit::window::Window;
@@ -18,6 +18,12 @@ impl App {
     }
 }
 
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ApplicationHandler<State> for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         #[allow(unused_mut)]
@@ -30,14 +36,14 @@ impl ApplicationHandler<State> for App {
     }
 
     #[allow(unused_mut)]
-    fn user_event(&mut self, event_loop: &ActiveEventLoop, mut event: State) {
+    fn user_event(&mut self, _event_loop: &ActiveEventLoop, mut event: State) {
         self.state = Some(event);
     }
 
     fn device_event(
         &mut self,
-        event_loop: &ActiveEventLoop,
-        device_id: DeviceId,
+        _event_loop: &ActiveEventLoop,
+        _device_id: DeviceId,
         event: DeviceEvent
     ) {
         let state = self.state.as_mut().unwrap();
@@ -52,23 +58,33 @@ impl ApplicationHandler<State> for App {
     ) {
         let state = self.state.as_mut().unwrap();
 
-        state.debug_ui.handle_input(&state.window, &event);
+        state.handle_window_event(&event);
 
         match event {
-            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::CloseRequested => {
+                state.save_settings();
+                event_loop.exit();
+            }
             WindowEvent::Resized(size) => state.resize(size.width, size.height),
             WindowEvent::RedrawRequested => {
+                #[cfg(feature = "gamepad")]
+                state.poll_gamepads();
                 state.update();
                 match state.render() {
                     Ok(_) => {}
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
-                        let size = state.window.inner_size();
-                        state.resize(size.width, size.height);
+                        if let Some(size) = state.window().map(|window| window.inner_size()) {
+                            state.resize(size.width, size.height);
+                        }
                     }
                     Err(e) => {
                         log::error!("Unable to render {}", e);
                     }
                 }
+                if state.quit_requested() {
+                    state.save_settings();
+                    event_loop.exit();
+                }
             }
             WindowEvent::KeyboardInput {
                 event:
@@ -79,21 +95,30 @@ impl ApplicationHandler<State> for App {
                 },
                 ..
             } => state.handle_key(event_loop, code, key_state.is_pressed()),
+            WindowEvent::MouseWheel { delta, .. } if !state.is_ui_open() => {
+                let scroll = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32,
+                };
+
+                // Scrolling down moves to the next slot.
+                if scroll < 0.0 {
+                    state.cycle_hotbar(1);
+                } else if scroll > 0.0 {
+                    state.cycle_hotbar(-1);
+                }
+            }
             WindowEvent::MouseInput {
                 button,
                 state: mouse_state,
                 ..
             } => match button {
+                _ if state.is_ui_open() => {}
                 MouseButton::Left => {
-                    if mouse_state == ElementState::Pressed {
-                            state.break_block();
-                    }
                     state.mouse_pressed = mouse_state == ElementState::Pressed;
                 }
-                MouseButton::Right => {
-                    if mouse_state == ElementState::Pressed {
-                        state.place_block();
-                    }
+                MouseButton::Right if mouse_state == ElementState::Pressed => {
+                    state.place_block();
                 }
                 _ => {}
             }
@@ -0,0 +1,127 @@
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+use winit::keyboard::KeyCode;
+use crate::game::brush::BrushShape;
+use crate::game::chunk::VoxelType;
+use crate::game::game_mode::GameMode;
+use crate::input::key_bindings::Action;
+use crate::rendering::lighting::BlockHighlight;
+
+const MAX_LOG_LINES: usize = 100;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Teleport { x: f32, y: f32, z: f32 },
+    Give(VoxelType),
+    Seed(u32),
+    GameMode(GameMode),
+    RenderDistance(u32),
+    RayOffset { right: f32, up: f32, forward: f32 },
+    DebugRay,
+    BatchDraws,
+    FrustumCulling,
+    Light { color: [f32; 3], range: f32 },
+    ClearLights,
+    ShowDepth,
+    HighlightBlocks(BlockHighlight),
+    Remesh,
+    Brush { shape: BrushShape, radius: Option<u32> },
+    Bind { action: Action, key: KeyCode },
+    Heal(Option<f32>),
+}
+
+impl Command {
+    pub fn parse(line: &str) -> anyhow::Result<Command> {
+        let mut args = line.split_whitespace();
+        let Some(name) = args.next() else {
+            anyhow::bail!("Empty command");
+        };
+        let args: Vec<&str> = args.collect();
+
+        let command = match (name, args.as_slice()) {
+            ("tp", [x, y, z]) => Command::Teleport {
+                x: x.parse()?,
+                y: y.parse()?,
+                z: z.parse()?,
+            },
+            ("give", [block]) => Command::Give(parse_name(block, "block")?),
+            ("seed", [value]) => Command::Seed(value.parse()?),
+            ("gamemode", [mode]) => Command::GameMode(parse_name(mode, "game mode")?),
+            ("render_distance", [distance]) => Command::RenderDistance(distance.parse()?),
+            ("ray_offset", [right, up, forward]) => Command::RayOffset {
+                right: right.parse()?,
+                up: up.parse()?,
+                forward: forward.parse()?,
+            },
+            ("debug_ray", []) => Command::DebugRay,
+            ("batch_draws", []) => Command::BatchDraws,
+            ("frustum_culling", []) => Command::FrustumCulling,
+            ("light", [r, g, b, range]) => Command::Light {
+                color: [r.parse()?, g.parse()?, b.parse()?],
+                range: range.parse()?,
+            },
+            ("clear_lights", []) => Command::ClearLights,
+            ("show_depth", []) => Command::ShowDepth,
+            ("highlight_blocks", [mode]) => Command::HighlightBlocks(parse_name(mode, "highlight mode")?),
+            ("remesh", []) => Command::Remesh,
+            ("brush", [shape, rest @ ..]) if rest.len() <= 1 => Command::Brush {
+                shape: parse_name(shape, "brush shape")?,
+                radius: rest.first().map(|radius| radius.parse()).transpose()?,
+            },
+            ("heal", []) => Command::Heal(None),
+            ("heal", [amount]) => Command::Heal(Some(amount.parse()?)),
+            ("bind", [action, key]) => Command::Bind {
+                action: parse_name(action, "action")?,
+                key: parse_name(key, "key")?,
+            },
+            ("tp", _) => anyhow::bail!("Usage: tp <x> <y> <z>"),
+            ("give", _) => anyhow::bail!("Usage: give <block>"),
+            ("seed", _) => anyhow::bail!("Usage: seed <value>"),
+            ("gamemode", _) => anyhow::bail!("Usage: gamemode <survival|creative>"),
+            ("render_distance", _) => anyhow::bail!("Usage: render_distance <chunks>"),
+            ("ray_offset", _) => anyhow::bail!("Usage: ray_offset <right> <up> <forward>"),
+            ("debug_ray", _) => anyhow::bail!("Usage: debug_ray"),
+            ("batch_draws", _) => anyhow::bail!("Usage: batch_draws"),
+            ("frustum_culling", _) => anyhow::bail!("Usage: frustum_culling"),
+            ("light", _) => anyhow::bail!("Usage: light <r> <g> <b> <range>"),
+            ("clear_lights", _) => anyhow::bail!("Usage: clear_lights"),
+            ("show_depth", _) => anyhow::bail!("Usage: show_depth"),
+            ("highlight_blocks", _) => anyhow::bail!("Usage: highlight_blocks <off|non_solid|transparent>"),
+            ("remesh", _) => anyhow::bail!("Usage: remesh"),
+            ("brush", _) => anyhow::bail!("Usage: brush <single|cube|sphere|line> [radius]"),
+            ("heal", _) => anyhow::bail!("Usage: heal [amount]"),
+            ("bind", _) => anyhow::bail!("Usage: bind <action> <key>, e.g. bind forward KeyK"),
+            _ => anyhow::bail!("Unknown command '{}'", name),
+        };
+
+        Ok(command)
+    }
+}
+
+// Block names, game modes, brush shapes, highlight modes and actions are the same snake_case
+// names used in settings.json. Keys use winit's `KeyCode` names, like KeyW or ArrowUp.
+fn parse_name<'a, T: Deserialize<'a>>(name: &'a str, what: &str) -> anyhow::Result<T> {
+    let deserializer: serde::de::value::StrDeserializer<serde::de::value::Error> = name.into_deserializer();
+    T::deserialize(deserializer).map_err(|_| anyhow::anyhow!("Unknown {} '{}'", what, name))
+}
+
+// Text input and scrollback for the in-game command console.
+#[derive(Default)]
+pub struct Console {
+    pub open: bool,
+    pub input: String,
+    log: Vec<String>,
+}
+
+impl Console {
+    pub fn log(&mut self, line: impl Into<String>) {
+        self.log.push(line.into());
+        if self.log.len() > MAX_LOG_LINES {
+            self.log.remove(0);
+        }
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.log
+    }
+}
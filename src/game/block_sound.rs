@@ -0,0 +1,34 @@
+// Pitch for the place or break sound of the block at `position`: normal pitch (1.0) moved by up
+// to `variation` either way (0.1 = ±10%). It's picked from the position rather than at random,
+// so a block always sounds the same but a run of placed blocks doesn't play one note over and
+// over.
+pub fn block_sound_pitch(position: (i32, i32, i32), variation: f32) -> f32 {
+    let (x, y, z) = position;
+    // splitmix64's finalizer over the packed coordinates.
+    let mut h = (x as u32 as u64) ^ ((y as u32 as u64) << 21) ^ ((z as u32 as u64) << 42);
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^= h >> 31;
+
+    // From -1 to 1.
+    let unit = (h >> 40) as f32 / (1u64 << 23) as f32 - 1.0;
+    1.0 + unit * variation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pitch_stays_in_range_and_varies_between_blocks() {
+        let positions = (0..64).map(|i| (i, 64 - i / 2, -i * 3)).collect::<Vec<_>>();
+        let pitches = positions.iter().map(|&position| block_sound_pitch(position, 0.1)).collect::<Vec<_>>();
+
+        assert!(pitches.iter().all(|pitch| (0.9..=1.1).contains(pitch)), "{:?}", pitches);
+        assert!(pitches.windows(2).any(|pair| pair[0] != pair[1]));
+        for (&position, &pitch) in positions.iter().zip(&pitches) {
+            assert_eq!(block_sound_pitch(position, 0.1), pitch);
+            assert_eq!(block_sound_pitch(position, 0.0), 1.0);
+        }
+    }
+}
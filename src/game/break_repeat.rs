@@ -0,0 +1,76 @@
+// Paces breaking blocks while the break button is held: one block as soon as it's pressed,
+// then another every `interval` seconds for as long as it stays held.
+pub struct BreakRepeat {
+    interval: f32,
+    // Seconds until the next break. Carries over any overshoot from the previous frame, so the
+    // rate doesn't depend on the frame rate.
+    cooldown: f32,
+}
+
+impl BreakRepeat {
+    pub fn new(interval: f32) -> Self {
+        Self {
+            interval,
+            cooldown: 0.0,
+        }
+    }
+
+    // Advances the clock by `dt` while the button is held and returns whether a block should
+    // break this frame. At most one block breaks per frame, so a long frame doesn't bank breaks.
+    pub fn tick(&mut self, dt: f32) -> bool {
+        self.cooldown -= dt;
+        if self.cooldown > 0.0 {
+            return false;
+        }
+
+        self.cooldown = (self.cooldown + self.interval).max(0.0);
+        true
+    }
+
+    // Releasing the button makes the next press break immediately.
+    pub fn reset(&mut self) {
+        self.cooldown = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Holds the button for `ticks` frames of `dt` seconds each and counts the breaks.
+    fn breaks_while_held(interval: f32, dt: f32, ticks: usize) -> usize {
+        let mut repeat = BreakRepeat::new(interval);
+        (0..ticks).filter(|_| repeat.tick(dt)).count()
+    }
+
+    #[test]
+    fn holding_breaks_once_per_interval_plus_the_first() {
+        let interval = 0.25;
+        let dt = 1.0 / 64.0;
+
+        for seconds in [0.1, 1.0 - dt, 1.1, 2.6, 10.0 - dt] {
+            let ticks = (seconds / dt) as usize;
+            let held = ticks as f32 * dt;
+            let expected = (held / interval).floor() as usize + 1;
+
+            assert_eq!(breaks_while_held(interval, dt, ticks), expected, "held for {}s", held);
+        }
+    }
+
+    #[test]
+    fn pacing_does_not_drift_with_frame_rate() {
+        // 1/60 isn't exact in floating point, so resetting the cooldown instead of carrying the
+        // rounding over could take an extra frame per break.
+        assert_eq!(breaks_while_held(0.25, 1.0 / 60.0, 590), 40);
+    }
+
+    #[test]
+    fn release_makes_next_press_break_immediately() {
+        let mut repeat = BreakRepeat::new(0.25);
+        assert!(repeat.tick(0.01));
+        assert!(!repeat.tick(0.01));
+
+        repeat.reset();
+        assert!(repeat.tick(0.01));
+    }
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+pub const MAX_BRUSH_RADIUS: u32 = 4;
+
+// Which blocks around the targeted one breaking and placing affect.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BrushShape {
+    // Just the targeted block.
+    #[default]
+    Single,
+    // Every block within the radius along each axis, so radius 1 is 3x3x3.
+    Cube,
+    Sphere,
+    // A straight run of blocks leading away from the target, as long as a cube is wide.
+    Line,
+}
+
+impl BrushShape {
+    pub const ALL: [BrushShape; 4] = [BrushShape::Single, BrushShape::Cube, BrushShape::Sphere, BrushShape::Line];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|shape| *shape == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    // The blocks the brush covers when centered on `center`, with `center` first. Lines run
+    // along `direction`, which should be a unit axis such as a hit face's normal.
+    pub fn cells(self, center: (i32, i32, i32), direction: (i32, i32, i32), radius: u32) -> Vec<(i32, i32, i32)> {
+        let (x, y, z) = center;
+        let r = radius as i32;
+
+        match self {
+            BrushShape::Single => vec![center],
+            BrushShape::Line => (0..=2 * r)
+                .map(|i| (x + direction.0 * i, y + direction.1 * i, z + direction.2 * i))
+                .collect(),
+            BrushShape::Cube | BrushShape::Sphere => {
+                // Half a block past the radius, so small spheres aren't just a cross.
+                let max_squared = (radius as f32 + 0.5).powi(2);
+                let mut cells = vec![center];
+                for dz in -r..=r {
+                    for dy in -r..=r {
+                        for dx in -r..=r {
+                            let inside = self == BrushShape::Cube || ((dx * dx + dy * dy + dz * dz) as f32) <= max_squared;
+                            if inside && (dx, dy, dz) != (0, 0, 0) {
+                                cells.push((x + dx, y + dy, z + dz));
+                            }
+                        }
+                    }
+                }
+                cells
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shapes_cover_the_expected_blocks() {
+        let center = (5, -3, 7);
+        let up = (0, 1, 0);
+
+        assert_eq!(BrushShape::Single.cells(center, up, 3), vec![center]);
+        assert_eq!(BrushShape::Cube.cells(center, up, 1).len(), 27);
+        assert_eq!(BrushShape::Cube.cells(center, up, 0), vec![center]);
+        assert_eq!(BrushShape::Sphere.cells(center, up, 1).len(), 19);
+        assert_eq!(BrushShape::Line.cells(center, up, 2), vec![(5, -3, 7), (5, -2, 7), (5, -1, 7), (5, 0, 7), (5, 1, 7)]);
+
+        for shape in BrushShape::ALL {
+            assert_eq!(shape.cells(center, up, 2)[0], center);
+        }
+    }
+}
@@ -1,9 +1,15 @@
 use cgmath::prelude::*;
 
-
-
+// The one camera the game renders from and moves relative to. `State` keeps it at the player's
+// eyes (or flies it freely while detached) and relies on:
+// - `get_view_matrix`, through `Projection`, for the view-projection uniform and the frustum.
+// - `get_direction`, `get_right` and `get_up` to aim the block targeting ray.
+// - `get_forward_horizontal` and `get_right` (in `PlayerController`) for walking directions,
+//   and `get_direction` for flying.
 pub struct Camera {
     pub position: cgmath::Point3<f32>,
+    // Radians. Yaw 0 looks along +x and increases towards +z; pitch 0 is level and positive
+    // looks up.
     pub yaw: f32,
     pub pitch: f32,
 }
@@ -18,9 +24,7 @@ impl Camera {
     }
 
     pub fn get_view_matrix(&self) -> cgmath::Matrix4<f32> {
-        let direction = self.get_direction();
-        let target = self.position + direction;
-        cgmath::Matrix4::look_at_rh(self.position, target, cgmath::Vector3::unit_y())
+        cgmath::Matrix4::look_to_rh(self.position, self.get_direction(), self.get_up())
     }
 
     pub fn get_direction(&self) -> cgmath::Vector3<f32> {
@@ -35,8 +39,10 @@ impl Camera {
         cgmath::Vector3::new(self.yaw.cos(), 0.0, self.yaw.sin()).normalize()
     }
 
+    // Derived from the yaw-only right vector, so it stays well defined looking straight up or
+    // down and turns upside down smoothly when pitch goes past vertical.
     pub fn get_up(&self) -> cgmath::Vector3<f32> {
-        cgmath::Vector3::unit_y()
+        self.get_right().cross(self.get_direction()).normalize()
     }
 
     pub fn get_right(&self) -> cgmath::Vector3<f32> {
@@ -1,3 +1,9 @@
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::sync::{Arc, OnceLock};
+use serde::{Deserialize, Serialize};
+use crate::rendering::texture_atlas::FaceDirection;
+
 pub const CHUNK_SIZE: usize = 16;
 pub const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
 
@@ -5,7 +11,7 @@ pub fn get_chunk_index(x: usize, y: usize, z: usize) -> usize {
     x + y * CHUNK_SIZE + z * CHUNK_SIZE * CHUNK_SIZE
 }
 
-#[derive(Copy, Clone, Eq, Hash, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Ord, PartialOrd)]
 pub struct ChunkPos {
     pub x: i32,
     pub y: i32,
@@ -27,30 +33,604 @@ impl ChunkPos {
     }
 }
 
-#[derive(Copy, Clone)]
+// Chunks are meshed and drawn in horizontal slabs this many blocks tall, so an edit only
+// rebuilds the slabs around it. Must be a multiple of the coarsest LOD step.
+pub const SECTION_HEIGHT: usize = 8;
+pub const SECTIONS_PER_CHUNK: usize = CHUNK_SIZE / SECTION_HEIGHT;
+
+// A set of a chunk's sections, with bit `i` for section `i`.
+pub type SectionMask = u8;
+pub const ALL_SECTIONS: SectionMask = SectionMask::MAX >> (SectionMask::BITS as usize - SECTIONS_PER_CHUNK);
+
+// The sections holding the chunk-local rows `y - 1..=y + 1`: those with faces that depend on
+// what's at row `y`.
+pub fn sections_around(y: usize) -> SectionMask {
+    let bottom = y.saturating_sub(1) / SECTION_HEIGHT;
+    let top = (y + 1).min(CHUNK_SIZE - 1) / SECTION_HEIGHT;
+    (bottom..=top).fold(0, |mask, section| mask | 1 << section)
+}
+
+// One section of a chunk, counted from the bottom.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Ord, PartialOrd)]
+pub struct SectionPos {
+    pub chunk: ChunkPos,
+    pub section: usize,
+}
+
+impl SectionPos {
+    pub fn new(chunk: ChunkPos, section: usize) -> Self {
+        Self { chunk, section }
+    }
+
+    // The sections of `chunk` in `mask`, bottom first.
+    pub fn in_mask(chunk: ChunkPos, mask: SectionMask) -> impl Iterator<Item = SectionPos> {
+        (0..SECTIONS_PER_CHUNK)
+            .filter(move |section| mask & 1 << section != 0)
+            .map(move |section| Self::new(chunk, section))
+    }
+
+    // The chunk-local rows the section covers.
+    pub fn ys(&self) -> Range<usize> {
+        self.section * SECTION_HEIGHT..(self.section + 1) * SECTION_HEIGHT
+    }
+
+    // The corner of the section's bounding box with the lowest world coordinates.
+    pub fn min_corner(&self) -> [f32; 3] {
+        let size = CHUNK_SIZE as i32;
+        [
+            (self.chunk.x * size) as f32,
+            (self.chunk.y * size + self.ys().start as i32) as f32,
+            (self.chunk.z * size) as f32,
+        ]
+    }
+
+    // The section's bounding box extent along each axis.
+    pub fn size() -> [f32; 3] {
+        [CHUNK_SIZE as f32, SECTION_HEIGHT as f32, CHUNK_SIZE as f32]
+    }
+}
+
+// The discriminants are the on-disk representation, so existing values must never change.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum VoxelType {
-    Air,
-    Grass,
-    Dirt,
-    Stone,
+    Air = 0,
+    Grass = 1,
+    Dirt = 2,
+    Stone = 3,
+    Glass = 4,
+    Water = 5,
+    Glowstone = 6,
+    Wood = 7,
+    Leaves = 8,
+    Sand = 9,
+    CoalOre = 10,
+    IronOre = 11,
+}
+
+impl VoxelType {
+    pub fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub fn id(self) -> BlockId {
+        BlockId(self as u16)
+    }
+
+    pub fn def(self) -> &'static BlockDef {
+        BlockRegistry::global()
+            .get(self.id())
+            .unwrap_or_else(|| panic!("No block definition for {:?}", self))
+    }
+
+    pub fn is_solid(self) -> bool {
+        self.def().solid
+    }
+
+    // Transparent blocks let neighboring faces show through and are drawn in the blended pass.
+    pub fn is_transparent(self) -> bool {
+        self.def().transparent
+    }
+
+    pub fn hardness(self) -> f32 {
+        self.def().hardness
+    }
+
+    pub fn light_emission(self) -> u8 {
+        self.def().light
+    }
+
+    // Liquids can be swum through and can't be targeted; their surface is drawn a little low.
+    pub fn is_liquid(self) -> bool {
+        self.def().liquid
+    }
+
+    // The block's color in the UI (hotbar, minimap, debug overlays), so they all agree.
+    pub fn ui_color(self) -> egui::Color32 {
+        let [r, g, b, a] = self.def().color;
+        egui::Color32::from_rgba_unmultiplied(r, g, b, a)
+    }
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(VoxelType::Air),
+            1 => Some(VoxelType::Grass),
+            2 => Some(VoxelType::Dirt),
+            3 => Some(VoxelType::Stone),
+            4 => Some(VoxelType::Glass),
+            5 => Some(VoxelType::Water),
+            6 => Some(VoxelType::Glowstone),
+            7 => Some(VoxelType::Wood),
+            8 => Some(VoxelType::Leaves),
+            9 => Some(VoxelType::Sand),
+            10 => Some(VoxelType::CoalOre),
+            11 => Some(VoxelType::IronOre),
+            _ => None,
+        }
+    }
+}
+
+// A block's index in the registry, the same as its `VoxelType` discriminant.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct BlockId(pub u16);
+
+// Atlas tile coordinates for a block's faces. `all` is the fallback for any face not given.
+#[derive(Deserialize)]
+struct BlockTextures {
+    all: Option<[u32; 2]>,
+    side: Option<[u32; 2]>,
+    top: Option<[u32; 2]>,
+    bottom: Option<[u32; 2]>,
+}
+
+#[derive(Deserialize)]
+struct BlockDefEntry {
+    id: u16,
+    name: String,
+    solid: bool,
+    transparent: bool,
+    #[serde(default)]
+    liquid: bool,
+    #[serde(default)]
+    hardness: f32,
+    #[serde(default)]
+    light: u8,
+    #[serde(default = "BlockDefEntry::default_color")]
+    color: [u8; 4],
+    textures: BlockTextures,
+}
+
+impl BlockDefEntry {
+    fn default_color() -> [u8; 4] {
+        [128, 128, 128, 255]
+    }
+}
+
+pub struct BlockDef {
+    pub name: String,
+    // Indexed by `FaceDirection as usize`.
+    pub atlas_coords: [[u32; 2]; 6],
+    pub solid: bool,
+    pub transparent: bool,
+    pub liquid: bool,
+    // Seconds the break button has to be held to break the block in survival. 0 breaks
+    // instantly.
+    pub hardness: f32,
+    // Light level (0-15) the block gives off. For now only held blocks light anything, through
+    // a point light around the player.
+    pub light: u8,
+    // Unmultiplied RGBA used to represent the block in the UI.
+    pub color: [u8; 4],
+}
+
+impl BlockDef {
+    pub fn atlas_coords(&self, face: FaceDirection) -> [u32; 2] {
+        self.atlas_coords[face as usize]
+    }
+}
+
+pub const BLOCKS_PATH: &str = "resources/blocks/blocks.json";
+
+pub struct BlockRegistry {
+    defs: Vec<Option<BlockDef>>,
+}
+
+impl BlockRegistry {
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let entries: Vec<BlockDefEntry> = serde_json::from_str(json)?;
+        let mut defs: Vec<Option<BlockDef>> = Vec::new();
+
+        for entry in entries {
+            let textures = &entry.textures;
+            let side = textures.side.or(textures.all);
+            let top = textures.top.or(textures.all);
+            let bottom = textures.bottom.or(textures.all);
+
+            let (Some(side), Some(top), Some(bottom)) = (side, top, bottom) else {
+                anyhow::bail!("Block '{}' is missing textures for some faces", entry.name);
+            };
+
+            let index = entry.id as usize;
+            if defs.len() <= index {
+                defs.resize_with(index + 1, || None);
+            }
+            if let Some(existing) = &defs[index] {
+                anyhow::bail!("Duplicate block id {} ('{}' and '{}')", entry.id, existing.name, entry.name);
+            }
+
+            defs[index] = Some(BlockDef {
+                name: entry.name,
+                // North, South, East, West, Top, Bottom
+                atlas_coords: [side, side, side, side, top, bottom],
+                solid: entry.solid,
+                transparent: entry.transparent,
+                liquid: entry.liquid,
+                hardness: entry.hardness,
+                light: entry.light,
+                color: entry.color,
+            });
+        }
+
+        let registry = Self { defs };
+        if let Some(missing) = (0..=u8::MAX).map_while(VoxelType::from_u8).find(|voxel| registry.get(voxel.id()).is_none()) {
+            anyhow::bail!("No block definition for {:?}", missing);
+        }
+        Ok(registry)
+    }
+
+    // The block table, read from `BLOCKS_PATH` at startup so textures, solidity and the rest
+    // can be changed without rebuilding. Falls back to the copy built into the binary if the
+    // file is missing or invalid. New block types still need a `VoxelType` variant.
+    pub fn global() -> &'static BlockRegistry {
+        static REGISTRY: OnceLock<BlockRegistry> = OnceLock::new();
+        REGISTRY.get_or_init(|| {
+            let built_in = || {
+                BlockRegistry::from_json(include_str!("../../resources/blocks/blocks.json"))
+                    .expect("Invalid built-in block registry")
+            };
+            match std::fs::read_to_string(BLOCKS_PATH) {
+                Ok(json) => BlockRegistry::from_json(&json).unwrap_or_else(|e| {
+                    log::warn!("Invalid block file {}, using the built-in blocks: {}", BLOCKS_PATH, e);
+                    built_in()
+                }),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => built_in(),
+                Err(e) => {
+                    log::warn!("Failed to read block file {}, using the built-in blocks: {}", BLOCKS_PATH, e);
+                    built_in()
+                }
+            }
+        })
+    }
+
+    pub fn get(&self, id: BlockId) -> Option<&BlockDef> {
+        self.defs.get(id.0 as usize)?.as_ref()
+    }
+}
+
+// Palette + run-length storage. `run_ends[i]` is the exclusive end index of run `i`, whose
+// voxel is `palette[run_palette[i]]`.
+#[derive(Clone)]
+struct CompressedVoxels {
+    palette: Vec<VoxelType>,
+    run_ends: Vec<u16>,
+    run_palette: Vec<u8>,
+}
+
+impl CompressedVoxels {
+    fn from_runs(runs: &[(u16, VoxelType)]) -> Self {
+        let mut palette = Vec::new();
+        let mut run_ends = Vec::with_capacity(runs.len());
+        let mut run_palette = Vec::with_capacity(runs.len());
+
+        let mut end = 0;
+        for &(length, voxel) in runs {
+            let palette_index = match palette.iter().position(|&v| v == voxel) {
+                Some(index) => index,
+                None => {
+                    palette.push(voxel);
+                    palette.len() - 1
+                }
+            };
+
+            end += length;
+            run_ends.push(end);
+            run_palette.push(palette_index as u8);
+        }
+
+        Self { palette, run_ends, run_palette }
+    }
+
+    fn get(&self, index: usize) -> VoxelType {
+        let run = self.run_ends.partition_point(|&end| end as usize <= index);
+        self.palette[self.run_palette[run] as usize]
+    }
+
+    fn runs(&self) -> Vec<(u16, VoxelType)> {
+        let mut start = 0;
+        self.run_ends.iter().zip(&self.run_palette).map(|(&end, &palette_index)| {
+            let run = (end - start, self.palette[palette_index as usize]);
+            start = end;
+            run
+        }).collect()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.palette.capacity() * std::mem::size_of::<VoxelType>()
+            + self.run_ends.capacity() * std::mem::size_of::<u16>()
+            + self.run_palette.capacity()
+    }
 }
 
+#[derive(Clone)]
+enum ChunkStorage {
+    Dense(Box<[VoxelType; CHUNK_VOLUME]>),
+    Compressed(CompressedVoxels),
+}
+
+#[derive(Clone)]
 pub struct Chunk {
-    voxels: [VoxelType; CHUNK_VOLUME],
+    storage: ChunkStorage,
+
+    // Flood-filled light level (0-15) per voxel, maintained by `game::lighting`.
+    light: Box<[u8; CHUNK_VOLUME]>,
+
+    // Set once the chunk differs from what the generator produced. Only modified chunks
+    // need to be saved; the rest can be regenerated.
+    modified: bool,
+
+    // Hash of the voxels and light, kept up to date by every edit, so chunks (and the meshes
+    // built from them) can be compared without looking at every voxel.
+    content_hash: u64,
+}
+
+// Each voxel's contribution to the content hash. Contributions are XORed together, so an
+// edit swaps one out without rehashing the rest. Air and darkness contribute nothing.
+fn voxel_hash(index: usize, voxel: VoxelType) -> u64 {
+    entry_hash(index, voxel.to_u8(), 0)
+}
+
+fn light_hash(index: usize, level: u8) -> u64 {
+    entry_hash(index, level, 1)
+}
+
+fn entry_hash(index: usize, value: u8, kind: u64) -> u64 {
+    if value == 0 {
+        return 0;
+    }
+
+    // splitmix64
+    let mut h = (index as u64) << 16 | (value as u64) << 8 | kind;
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
 }
 
 impl Chunk {
     pub fn new() -> Self {
+        Self::filled(VoxelType::Air)
+    }
+
+    pub fn filled(voxel: VoxelType) -> Self {
+        Self::from_runs(&[(CHUNK_VOLUME as u16, voxel)], false)
+    }
+
+    fn from_runs(runs: &[(u16, VoxelType)], modified: bool) -> Self {
+        let mut content_hash = 0;
+        let mut start = 0;
+        for &(length, voxel) in runs {
+            for index in start..start + length as usize {
+                content_hash ^= voxel_hash(index, voxel);
+            }
+            start += length as usize;
+        }
+
         Self {
-            voxels: [VoxelType::Air; CHUNK_VOLUME],
+            storage: ChunkStorage::Compressed(CompressedVoxels::from_runs(runs)),
+            light: Box::new([0; CHUNK_VOLUME]),
+            modified,
+            content_hash,
         }
     }
 
     pub fn set_voxel(&mut self, x: usize, y: usize, z: usize, voxel_type: VoxelType) {
-        self.voxels[get_chunk_index(x, y, z)] = voxel_type;
+        let index = get_chunk_index(x, y, z);
+
+        if let ChunkStorage::Compressed(compressed) = &self.storage {
+            if compressed.get(index) == voxel_type {
+                return;
+            }
+
+            // Edits happen on the dense form. The world packs edited chunks again once a batch
+            // of edits is done, in `World::take_dirty_chunks`.
+            let mut voxels = Box::new([VoxelType::Air; CHUNK_VOLUME]);
+            let mut start = 0;
+            for (length, voxel) in compressed.runs() {
+                voxels[start..start + length as usize].fill(voxel);
+                start += length as usize;
+            }
+            self.storage = ChunkStorage::Dense(voxels);
+        }
+
+        if let ChunkStorage::Dense(voxels) = &mut self.storage {
+            self.content_hash ^= voxel_hash(index, voxels[index]) ^ voxel_hash(index, voxel_type);
+            voxels[index] = voxel_type;
+        }
     }
 
     pub fn get_voxel(&self, x: usize, y: usize, z: usize) -> Option<VoxelType> {
-        Some(self.voxels[get_chunk_index(x, y, z)])
+        let index = get_chunk_index(x, y, z);
+
+        match &self.storage {
+            ChunkStorage::Dense(voxels) => Some(voxels[index]),
+            ChunkStorage::Compressed(compressed) => Some(compressed.get(index)),
+        }
+    }
+
+    pub fn get_light(&self, x: usize, y: usize, z: usize) -> u8 {
+        self.light[get_chunk_index(x, y, z)]
+    }
+
+    pub fn set_light(&mut self, x: usize, y: usize, z: usize, level: u8) {
+        let index = get_chunk_index(x, y, z);
+        self.content_hash ^= light_hash(index, self.light[index]) ^ light_hash(index, level);
+        self.light[index] = level;
+    }
+
+    pub fn content_hash(&self) -> u64 {
+        self.content_hash
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    pub fn mark_modified(&mut self) {
+        self.modified = true;
+    }
+
+    // Returns the voxel type if the whole chunk is made of a single type.
+    pub fn is_uniform(&self) -> Option<VoxelType> {
+        match &self.storage {
+            ChunkStorage::Compressed(compressed) if compressed.palette.len() == 1 => Some(compressed.palette[0]),
+            ChunkStorage::Compressed(_) => None,
+            ChunkStorage::Dense(voxels) => {
+                let first = voxels[0];
+                voxels.iter().all(|&v| v == first).then_some(first)
+            }
+        }
+    }
+
+    pub fn is_dense(&self) -> bool {
+        matches!(self.storage, ChunkStorage::Dense(_))
+    }
+
+    // Switches to palette + RLE storage if that is smaller than the dense array.
+    pub fn compress(&mut self) {
+        if let ChunkStorage::Dense(_) = &self.storage {
+            let compressed = CompressedVoxels::from_runs(&self.runs());
+            if compressed.memory_usage() < CHUNK_VOLUME / 2 {
+                self.storage = ChunkStorage::Compressed(compressed);
+            }
+        }
+    }
+
+    // Approximate heap + inline size of this chunk's voxel and light storage, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + CHUNK_VOLUME + match &self.storage {
+            ChunkStorage::Dense(_) => std::mem::size_of::<[VoxelType; CHUNK_VOLUME]>(),
+            ChunkStorage::Compressed(compressed) => compressed.memory_usage(),
+        }
+    }
+
+    fn runs(&self) -> Vec<(u16, VoxelType)> {
+        match &self.storage {
+            ChunkStorage::Compressed(compressed) => compressed.runs(),
+            ChunkStorage::Dense(voxels) => {
+                let mut runs: Vec<(u16, VoxelType)> = Vec::new();
+
+                for &voxel in voxels.iter() {
+                    match runs.last_mut() {
+                        Some((length, run_voxel)) if *run_voxel == voxel => *length += 1,
+                        _ => runs.push((1, voxel)),
+                    }
+                }
+
+                runs
+            }
+        }
+    }
+
+    // Writes the voxels as (run length, voxel id) pairs in index order.
+    pub fn write_rle(&self, writer: &mut impl Write) -> io::Result<()> {
+        let runs = self.runs();
+
+        writer.write_all(&(runs.len() as u32).to_le_bytes())?;
+        for (length, voxel) in runs {
+            writer.write_all(&length.to_le_bytes())?;
+            writer.write_all(&[voxel.to_u8()])?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_rle(reader: &mut impl Read) -> io::Result<Self> {
+        let mut u32_bytes = [0u8; 4];
+        reader.read_exact(&mut u32_bytes)?;
+        let run_count = u32::from_le_bytes(u32_bytes);
+
+        let mut runs = Vec::with_capacity(run_count as usize);
+        let mut total = 0;
+        for _ in 0..run_count {
+            let mut run = [0u8; 3];
+            reader.read_exact(&mut run)?;
+
+            let length = u16::from_le_bytes([run[0], run[1]]);
+            let voxel = VoxelType::from_u8(run[2]).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("Unknown voxel id {}", run[2]))
+            })?;
+
+            total += length as usize;
+            if total > CHUNK_VOLUME {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Chunk runs exceed chunk volume"));
+            }
+
+            runs.push((length, voxel));
+        }
+
+        if total != CHUNK_VOLUME {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Chunk runs do not cover chunk volume"));
+        }
+
+        Ok(Self::from_runs(&runs, true))
+    }
+}
+
+// Snapshots of the chunks sharing a face with some chunk, indexed by `FaceDirection`.
+#[derive(Clone, Default)]
+pub struct ChunkNeighbors {
+    chunks: [Option<Arc<Chunk>>; 6],
+}
+
+impl ChunkNeighbors {
+    // Builds the set by calling `get` with each face's chunk offset.
+    pub fn from_fn(mut get: impl FnMut((i32, i32, i32)) -> Option<Arc<Chunk>>) -> Self {
+        Self {
+            chunks: FaceDirection::ALL.map(|face| get(face.offset())),
+        }
+    }
+
+    pub fn get(&self, face: FaceDirection) -> Option<&Chunk> {
+        self.chunks[face as usize].as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewriting_a_voxel_keeps_the_content_hash() {
+        let mut chunk = Chunk::filled(VoxelType::Stone);
+        chunk.set_voxel(3, 4, 5, VoxelType::Dirt);
+        let hash = chunk.content_hash();
+
+        chunk.set_voxel(3, 4, 5, VoxelType::Dirt);
+        chunk.set_voxel(0, 0, 0, VoxelType::Stone);
+        assert_eq!(chunk.content_hash(), hash);
+
+        chunk.set_voxel(3, 4, 5, VoxelType::Grass);
+        assert_ne!(chunk.content_hash(), hash);
+        chunk.set_voxel(3, 4, 5, VoxelType::Dirt);
+        assert_eq!(chunk.content_hash(), hash);
+    }
+    #[test]
+    fn block_files_must_define_every_block() {
+        let json = std::fs::read_to_string(BLOCKS_PATH).unwrap();
+        assert!(BlockRegistry::from_json(&json).is_ok());
+
+        let air_only = r#"[{ "id": 0, "name": "air", "solid": false, "transparent": true, "textures": { "all": [0, 0] } }]"#;
+        let error = BlockRegistry::from_json(air_only).err().unwrap();
+        assert_eq!(error.to_string(), "No block definition for Grass");
     }
 }
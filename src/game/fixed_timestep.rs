@@ -0,0 +1,94 @@
+// Runs the simulation in steps of a fixed length however long frames take, so physics comes
+// out the same at any frame rate. Time left over from a frame carries over to the next, and
+// rendering can interpolate between the last two steps by `alpha`.
+pub struct FixedTimestep {
+    step: f32,
+    // Steps one frame may run before the rest of its time is dropped, so a long stall (like a
+    // minimized or dragged window) doesn't turn into a burst of catching up.
+    max_steps: u32,
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    pub fn new(step: f32, max_steps: u32) -> Self {
+        Self {
+            step,
+            max_steps,
+            accumulator: 0.0,
+        }
+    }
+
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+
+    // Adds a frame's `dt` seconds and returns how many steps to run for it.
+    pub fn advance(&mut self, dt: f32) -> u32 {
+        self.accumulator += dt;
+        let steps = ((self.accumulator / self.step) as u32).min(self.max_steps);
+        self.accumulator -= steps as f32 * self.step;
+        if steps == self.max_steps {
+            self.accumulator %= self.step;
+        }
+        steps
+    }
+
+    // How far the time left over is into the next step, from 0 to 1.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.step).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::chunk::{ChunkPos, VoxelType};
+    use crate::game::player::{Player, PlayerEvent};
+    use crate::game::world::{GenerationFeatures, World};
+
+    // Drops a player moving sideways onto flat ground, running frames `dt` seconds long, and
+    // returns where it lands.
+    fn landing_position(dt: f32) -> cgmath::Point3<f32> {
+        let mut world = World::new();
+        world.set_generation_features(GenerationFeatures { noise_terrain: false, ..Default::default() });
+        world.load_region(ChunkPos::new(0, 0, 0), ChunkPos::new(1, 0, 0));
+        for x in 0..32 {
+            for z in 0..16 {
+                world.set_voxel(x, 0, z, VoxelType::Stone);
+            }
+        }
+
+        let mut player = Player::new((2.5, 12.0, 8.5).into());
+        player.velocity.x = 3.0;
+        let mut timestep = FixedTimestep::new(1.0 / 60.0, 5);
+
+        for _ in 0..(5.0 / dt) as usize {
+            for _ in 0..timestep.advance(dt) {
+                player.update(&mut world, timestep.step());
+                if player.take_events().any(|event| matches!(event, PlayerEvent::Land { .. })) {
+                    return player.position;
+                }
+            }
+        }
+        panic!("never landed at {} fps", 1.0 / dt);
+    }
+
+    #[test]
+    fn landing_does_not_depend_on_frame_rate() {
+        assert_eq!(landing_position(1.0 / 30.0), landing_position(1.0 / 120.0));
+    }
+
+    #[test]
+    fn long_frames_catch_up_at_most_the_cap() {
+        let mut timestep = FixedTimestep::new(0.25, 3);
+
+        assert_eq!(timestep.advance(0.1), 0);
+        assert!((timestep.alpha() - 0.4).abs() < 1e-6);
+        assert_eq!(timestep.advance(0.4), 2);
+
+        // A ten second stall runs three steps and drops the rest.
+        assert_eq!(timestep.advance(10.0), 3);
+        assert!(timestep.alpha() <= 1.0);
+        assert_eq!(timestep.advance(0.0), 0);
+    }
+}
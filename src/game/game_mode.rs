@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameMode {
+    // Blocks take time to break (their hardness in the block registry).
+    #[default]
+    Survival,
+    // Blocks break instantly.
+    Creative,
+}
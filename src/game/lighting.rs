@@ -0,0 +1,192 @@
+use std::collections::VecDeque;
+use crate::game::chunk::{ChunkPos, CHUNK_SIZE};
+use crate::game::world::World;
+
+pub const MAX_LIGHT: u8 = 15;
+
+const NEIGHBOR_OFFSETS: [(i32, i32, i32); 6] = [
+    (1, 0, 0),
+    (-1, 0, 0),
+    (0, 1, 0),
+    (0, -1, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+
+type VoxelPos = (i32, i32, i32);
+
+fn offset((x, y, z): VoxelPos, (dx, dy, dz): (i32, i32, i32)) -> VoxelPos {
+    (x + dx, y + dy, z + dz)
+}
+
+fn light_at(world: &World, (x, y, z): VoxelPos) -> u8 {
+    world.get_light(x, y, z).unwrap_or(0)
+}
+
+fn lets_light_through(world: &World, (x, y, z): VoxelPos) -> bool {
+    world.get_voxel(x, y, z).is_some_and(|voxel| voxel.is_transparent())
+}
+
+// Sky light travels straight down without fading; every other step loses one level.
+fn propagated_level(level: u8, direction: (i32, i32, i32)) -> u8 {
+    if direction == (0, -1, 0) && level == MAX_LIGHT {
+        MAX_LIGHT
+    } else {
+        level.saturating_sub(1)
+    }
+}
+
+// Breadth-first flood fill outwards from already-lit voxels.
+fn propagate(world: &mut World, mut queue: VecDeque<VoxelPos>) {
+    while let Some(pos) = queue.pop_front() {
+        let level = light_at(world, pos);
+
+        for direction in NEIGHBOR_OFFSETS {
+            let neighbor = offset(pos, direction);
+            if !lets_light_through(world, neighbor) {
+                continue;
+            }
+
+            let new_level = propagated_level(level, direction);
+            if new_level > light_at(world, neighbor) {
+                world.set_light(neighbor.0, neighbor.1, neighbor.2, new_level);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+}
+
+// Darkens everything that was lit through the given voxels, returning the still-lit voxels
+// bordering the darkened area so the caller can flood light back in from them.
+fn remove_light(world: &mut World, starts: Vec<(VoxelPos, u8)>) -> VecDeque<VoxelPos> {
+    let mut queue = VecDeque::new();
+    let mut refill = VecDeque::new();
+
+    for (pos, level) in starts {
+        world.set_light(pos.0, pos.1, pos.2, 0);
+        queue.push_back((pos, level));
+    }
+
+    while let Some((pos, level)) = queue.pop_front() {
+        for direction in NEIGHBOR_OFFSETS {
+            let neighbor = offset(pos, direction);
+            let neighbor_level = light_at(world, neighbor);
+            if neighbor_level == 0 {
+                continue;
+            }
+
+            let lit_by_pos = neighbor_level < level
+                || (direction == (0, -1, 0) && level == MAX_LIGHT && neighbor_level == MAX_LIGHT);
+
+            if lit_by_pos {
+                world.set_light(neighbor.0, neighbor.1, neighbor.2, 0);
+                queue.push_back((neighbor, neighbor_level));
+            } else {
+                refill.push_back(neighbor);
+            }
+        }
+    }
+
+    refill
+}
+
+// Computes light for a freshly inserted chunk: sky light falls down each open column, light
+// already in neighboring chunks spreads in, and columns below that this chunk now shades lose
+// their sky light. Columns under an unloaded chunk are assumed open to the sky.
+pub fn light_new_chunk(world: &mut World, pos: ChunkPos) {
+    let size = CHUNK_SIZE as i32;
+    let base = (pos.x * size, pos.y * size, pos.z * size);
+    let mut queue = VecDeque::new();
+
+    for z in 0..size {
+        for x in 0..size {
+            let above = (base.0 + x, base.1 + size, base.2 + z);
+            let open_to_sky = match world.get_voxel(above.0, above.1, above.2) {
+                None => true,
+                Some(voxel) => voxel.is_transparent() && light_at(world, above) == MAX_LIGHT,
+            };
+
+            if !open_to_sky {
+                continue;
+            }
+
+            for y in (0..size).rev() {
+                let voxel = (base.0 + x, base.1 + y, base.2 + z);
+                if !lets_light_through(world, voxel) {
+                    break;
+                }
+
+                world.set_light(voxel.0, voxel.1, voxel.2, MAX_LIGHT);
+                queue.push_back(voxel);
+            }
+        }
+    }
+
+    // Pull in light from loaded neighbors across each face.
+    for z in 0..size {
+        for y in 0..size {
+            for x in 0..size {
+                let on_border = x == 0 || y == 0 || z == 0 || x == size - 1 || y == size - 1 || z == size - 1;
+                if !on_border {
+                    continue;
+                }
+
+                for direction in NEIGHBOR_OFFSETS {
+                    let (lx, ly, lz) = offset((x, y, z), direction);
+                    let outside = [lx, ly, lz].iter().any(|c| !(0..size).contains(c));
+                    let neighbor = (base.0 + lx, base.1 + ly, base.2 + lz);
+
+                    if outside && light_at(world, neighbor) > 1 {
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+        }
+    }
+
+    propagate(world, queue);
+
+    // The chunk below may have been lit assuming open sky through where this chunk now is.
+    let mut shaded = Vec::new();
+    for z in 0..size {
+        for x in 0..size {
+            let bottom = (base.0 + x, base.1, base.2 + z);
+            let below = (base.0 + x, base.1 - 1, base.2 + z);
+
+            if light_at(world, below) == MAX_LIGHT && light_at(world, bottom) < MAX_LIGHT {
+                shaded.push((below, MAX_LIGHT));
+            }
+        }
+    }
+
+    if !shaded.is_empty() {
+        let refill = remove_light(world, shaded);
+        propagate(world, refill);
+    }
+}
+
+// Updates light after the voxel at `pos` switched between letting light through and not.
+pub fn on_opacity_changed(world: &mut World, pos: VoxelPos) {
+    if lets_light_through(world, pos) {
+        let above = offset(pos, (0, 1, 0));
+        if world.get_voxel(above.0, above.1, above.2).is_none() {
+            world.set_light(pos.0, pos.1, pos.2, MAX_LIGHT);
+        }
+
+        let mut queue = VecDeque::from([pos]);
+        for direction in NEIGHBOR_OFFSETS {
+            let neighbor = offset(pos, direction);
+            if light_at(world, neighbor) > 0 {
+                queue.push_back(neighbor);
+            }
+        }
+
+        propagate(world, queue);
+    } else {
+        let level = light_at(world, pos);
+        if level > 0 {
+            let refill = remove_light(world, vec![(pos, level)]);
+            propagate(world, refill);
+        }
+    }
+}
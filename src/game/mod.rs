@@ -1,7 +1,16 @@
+pub mod block_sound;
+pub mod break_repeat;
+pub mod brush;
 pub mod chunk;
+pub mod fixed_timestep;
 pub mod world;
+pub mod lighting;
+pub mod ores;
 pub mod camera;
+pub mod game_mode;
 pub mod player;
 pub mod raycast;
+pub mod terrain;
+pub mod time_of_day;
 
-pub use raycast::{raycast_voxel, RaycastHit};
\ No newline at end of file
+pub use raycast::{raycast_voxel_where, RaycastHit};
\ No newline at end of file
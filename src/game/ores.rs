@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+use crate::game::chunk::{Chunk, ChunkPos, VoxelType, CHUNK_SIZE};
+
+// Where and how much of one ore generates.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct OreVein {
+    // Average veins started per chunk inside the depth range. Fractions give a chance of one
+    // more.
+    pub veins_per_chunk: f32,
+    // World heights veins may start between, inclusive.
+    pub min_y: i32,
+    pub max_y: i32,
+    // Ore blocks per vein, at most `CHUNK_SIZE` so a vein never reaches past the chunks next
+    // to the one it starts in.
+    pub size: u32,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OreVeins {
+    pub coal: OreVein,
+    pub iron: OreVein,
+}
+
+impl Default for OreVeins {
+    fn default() -> Self {
+        Self {
+            coal: OreVein {
+                veins_per_chunk: 2.0,
+                min_y: -64,
+                max_y: 24,
+                size: 10,
+            },
+            iron: OreVein {
+                veins_per_chunk: 1.0,
+                min_y: -64,
+                max_y: 0,
+                size: 6,
+            },
+        }
+    }
+}
+
+impl OreVeins {
+    fn all(&self) -> [(VoxelType, OreVein); 2] {
+        [(VoxelType::CoalOre, self.coal), (VoxelType::IronOre, self.iron)]
+    }
+}
+
+// splitmix64, seeded from a hash of the chunk and ore a vein belongs to.
+struct VeinRng(u64);
+
+impl VeinRng {
+    fn new(seed: u32, cell: ChunkPos, ore: usize) -> Self {
+        let mut rng = Self(seed as u64);
+        for value in [cell.x, cell.y, cell.z, ore as i32] {
+            rng.0 ^= rng.next() ^ value as u32 as u64;
+        }
+        rng
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut h = self.0;
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        h ^ (h >> 31)
+    }
+
+    fn below(&mut self, n: u32) -> i32 {
+        (self.next() % n as u64) as i32
+    }
+
+    fn chance(&mut self, probability: f32) -> bool {
+        let unit = (self.next() >> 40) as f32 / (1u64 << 24) as f32;
+        unit < probability
+    }
+}
+
+// Replaces stone in `chunk` with the parts of nearby ore veins that fall inside it. Each vein
+// is a random walk that depends only on the seed and the chunk it starts in, so a vein
+// crossing a chunk border comes out the same whichever chunk loads first.
+pub fn place_ores(chunk: &mut Chunk, pos: ChunkPos, seed: u32, veins: &OreVeins) {
+    let size = CHUNK_SIZE as i32;
+    let base = [pos.x * size, pos.y * size, pos.z * size];
+
+    for (ore_index, (ore, vein)) in veins.all().into_iter().enumerate() {
+        let vein_size = vein.size.min(CHUNK_SIZE as u32);
+        if vein_size == 0 || vein.veins_per_chunk <= 0.0 {
+            continue;
+        }
+
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let cell = ChunkPos::new(pos.x + dx, pos.y + dy, pos.z + dz);
+                    let cell_bottom = cell.y * size;
+                    if cell_bottom > vein.max_y || cell_bottom + size <= vein.min_y {
+                        continue;
+                    }
+
+                    let mut rng = VeinRng::new(seed, cell, ore_index);
+                    let whole = vein.veins_per_chunk.floor();
+                    let count = whole as u32 + rng.chance(vein.veins_per_chunk - whole) as u32;
+
+                    for _ in 0..count {
+                        let mut at = [
+                            cell.x * size + rng.below(CHUNK_SIZE as u32),
+                            cell_bottom + rng.below(CHUNK_SIZE as u32),
+                            cell.z * size + rng.below(CHUNK_SIZE as u32),
+                        ];
+                        if !(vein.min_y..=vein.max_y).contains(&at[1]) {
+                            continue;
+                        }
+
+                        for _ in 0..vein_size {
+                            let local: [i32; 3] = std::array::from_fn(|i| at[i] - base[i]);
+                            if local.iter().all(|c| (0..size).contains(c)) {
+                                let (x, y, z) = (local[0] as usize, local[1] as usize, local[2] as usize);
+                                if chunk.get_voxel(x, y, z) == Some(VoxelType::Stone) {
+                                    chunk.set_voxel(x, y, z, ore);
+                                }
+                            }
+
+                            let step = rng.below(6);
+                            at[(step / 2) as usize] += if step % 2 == 0 { 1 } else { -1 };
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,21 @@
-use cgmath::Zero;
+use cgmath::{InnerSpace, Zero};
+use serde::{Deserialize, Serialize};
 use crate::game::chunk::VoxelType;
 use crate::game::world::World;
 
 const GRAVITY: f32 = 3.0 * -9.81;
+// Fraction of gravity that still applies in water.
+const WATER_GRAVITY_SCALE: f32 = 0.25;
+// Upward acceleration when fully underwater, in m/s². Balances the reduced gravity at about
+// 60% submerged, which keeps the head above the surface.
+const BUOYANCY: f32 = 12.0;
+// How quickly vertical speed dies down in water, in 1/s.
+const WATER_DRAG: f32 = 3.0;
+pub const MAX_HEALTH: f32 = 20.0;
+const STEP_HEIGHT: f32 = 0.6;
+// How fast the view catches up after stepping up, in m/s.
+const STEP_SMOOTHING_SPEED: f32 = 4.0;
+const FOOTSTEP_DISTANCE: f32 = 1.7;
 
 enum Axis {
     X,
@@ -10,12 +23,87 @@ enum Axis {
     Z,
 }
 
+// Things that happened to the player during an update, for sounds/effects to react to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PlayerEvent {
+    // Hit a ceiling while moving up at the given speed.
+    HeadBump { speed: f32 },
+    // Landed on the ground while falling at the given speed.
+    Land { speed: f32 },
+    // Lost the given amount of health.
+    Damage { amount: f32 },
+    // Walked another `footstep_distance` along the ground.
+    Footstep,
+}
+
+// How much landings hurt. Impact speeds are converted back into the height fallen, so both
+// settings are in blocks.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FallDamage {
+    // Falls up to this high are harmless.
+    pub safe_distance: f32,
+    // Health lost per block fallen beyond the safe distance.
+    pub damage_per_block: f32,
+}
+
+impl Default for FallDamage {
+    fn default() -> Self {
+        Self {
+            safe_distance: 3.0,
+            damage_per_block: 1.0,
+        }
+    }
+}
+
+impl FallDamage {
+    pub fn damage(&self, impact_speed: f32) -> f32 {
+        let distance = impact_speed * impact_speed / (2.0 * -GRAVITY);
+        (distance - self.safe_distance).max(0.0) * self.damage_per_block
+    }
+}
+
 pub struct Player {
     pub position: cgmath::Point3<f32>,
     pub velocity: cgmath::Vector3<f32>,
     pub width: f32,
     pub height: f32,
     pub is_on_ground: bool,
+
+    // Fraction of upward speed kept (as downward speed) after hitting a ceiling. 0 stops dead.
+    pub ceiling_restitution: f32,
+
+    // Flying ignores gravity; noclip additionally skips collisions so the player can pass
+    // through terrain. Noclip only applies while flying.
+    pub flying: bool,
+    pub noclip: bool,
+    // Skips collisions without flying, so gravity drops the player through the terrain. For
+    // inspecting what's underground.
+    pub ghost: bool,
+
+    // Fraction of the player's height that is underwater, from 0 (dry) to 1 (fully submerged).
+    // Updated at the start of every update.
+    pub submersion: f32,
+
+    // Between 0 and `MAX_HEALTH`. Nothing happens at 0 yet.
+    pub health: f32,
+    pub fall_damage: FallDamage,
+    // Ignores all damage, as in creative mode.
+    pub invulnerable: bool,
+
+    // Tallest ledge walking into it climbs automatically, in meters. 0 disables stepping up.
+    pub step_height: f32,
+    // How far below `position` the view should be drawn while it eases up after a step, so
+    // climbing doesn't jerk the camera. Always <= 0.
+    step_offset: f32,
+
+    // Ground distance between footsteps, in meters. Steps come at a fixed distance, so their
+    // cadence follows speed and sprinting steps faster. 0 turns footsteps off.
+    pub footstep_distance: f32,
+    // Distance walked since the last footstep.
+    step_distance: f32,
+
+    events: Vec<PlayerEvent>,
 }
 
 impl Player {
@@ -26,122 +114,473 @@ impl Player {
             width: 0.5,
             height: 1.8,
             is_on_ground: false,
+            ceiling_restitution: 0.0,
+            flying: false,
+            noclip: false,
+            ghost: false,
+            submersion: 0.0,
+            health: MAX_HEALTH,
+            fall_damage: FallDamage::default(),
+            invulnerable: false,
+            step_height: STEP_HEIGHT,
+            step_offset: 0.0,
+            footstep_distance: FOOTSTEP_DISTANCE,
+            step_distance: 0.0,
+            events: Vec::new(),
         }
     }
 
-    fn resolve_collisions(&mut self, world: &World, axis: Axis) {
-        let min_x_voxel = (self.position.x - (self.width / 2.0)).floor() as i32;
-        let max_x_voxel = (self.position.x + (self.width / 2.0)).floor() as i32;
-        let min_y_voxel = (self.position.y - (self.height / 2.0)).floor() as i32;
-        let max_y_voxel = (self.position.y + (self.height / 2.0)).floor() as i32;
-        let min_z_voxel = (self.position.z - (self.width / 2.0)).floor() as i32;
-        let max_z_voxel = (self.position.z + (self.width / 2.0)).floor() as i32;
-
+    // Moves the player `delta` along one axis, stopping just short of the first solid voxel
+    // the box would sweep into, so no speed can carry it through a wall between frames.
+    // Voxels the box already overlaps are ignored rather than pushed out of.
+    fn move_axis(&mut self, world: &World, axis: Axis, delta: f32) {
         const EPSILON: f32 = 0.001;
 
-        for z in min_z_voxel..=max_z_voxel {
-            for y in min_y_voxel..=max_y_voxel {
-                for x in min_x_voxel..=max_x_voxel {
-                    if matches!(world.get_voxel(x, y, z), Some(VoxelType::Air) | None) {
+        if delta == 0.0 {
+            return;
+        }
+
+        let a = match axis {
+            Axis::X => 0,
+            Axis::Y => 1,
+            Axis::Z => 2,
+        };
+
+        let position = [self.position.x, self.position.y, self.position.z];
+        let half_extents = [self.width / 2.0, self.height / 2.0, self.width / 2.0];
+        let p_min: [f32; 3] = std::array::from_fn(|i| position[i] - half_extents[i]);
+        let p_max: [f32; 3] = std::array::from_fn(|i| position[i] + half_extents[i]);
+
+        // Voxels covered by the box over the whole move.
+        let mut range: [(i32, i32); 3] = std::array::from_fn(|i| (p_min[i].floor() as i32, p_max[i].floor() as i32));
+        range[a] = if delta > 0.0 {
+            (p_max[a].floor() as i32, (p_max[a] + delta).floor() as i32)
+        } else {
+            ((p_min[a] + delta).floor() as i32, p_min[a].floor() as i32)
+        };
+
+        let mut allowed = delta;
+
+        for z in range[2].0..=range[2].1 {
+            for y in range[1].0..=range[1].1 {
+                for x in range[0].0..=range[0].1 {
+                    if !world.get_voxel(x, y, z).is_some_and(VoxelType::is_solid) {
                         continue;
                     }
 
-                    let mut p_min_x = self.position.x - (self.width / 2.0);
-                    let mut p_max_x = self.position.x + (self.width / 2.0);
-                    let mut p_min_y = self.position.y - (self.height / 2.0);
-                    let mut p_max_y = self.position.y + (self.height / 2.0);
-                    let mut p_min_z = self.position.z - (self.width / 2.0);
-                    let mut p_max_z = self.position.z + (self.width / 2.0);
-
-                    let v_min_x = x as f32;
-                    let v_max_x = (x + 1) as f32;
-                    let v_min_y = y as f32;
-                    let v_max_y = (y + 1) as f32;
-                    let v_min_z = z as f32;
-                    let v_max_z = (z + 1) as f32;
-
-                    let x_overlap = p_min_x < v_max_x && p_max_x > v_min_x;
-                    let y_overlap = p_min_y < v_max_y && p_max_y > v_min_y;
-                    let z_overlap = p_min_z < v_max_z && p_max_z > v_min_z;
-
-                    if x_overlap && y_overlap && z_overlap {
-                        let pen_x = (p_max_x - v_min_x).min(v_max_x - p_min_x);
-                        let pen_y = (p_max_y - v_min_y).min(v_max_y - p_min_y);
-                        let pen_z = (p_max_z - v_min_z).min(v_max_z - p_min_z);
-
-                        match axis {
-                            Axis::X => {
-                                if pen_x <= pen_y && pen_x <= pen_z {
-                                    let pen_from_right = p_max_x - v_min_x; // Overlap on block's left face
-                                    let pen_from_left = v_max_x - p_min_x;  // Overlap on block's right face
-
-                                    // Push back from the side with the smallest overlap
-                                    if pen_from_right < pen_from_left {
-                                        self.position.x -= pen_from_right + EPSILON; // Push left
-                                    } else {
-                                        self.position.x += pen_from_left + EPSILON;  // Push right
-                                    }
-
-                                    self.velocity.x = 0.0;
-
-                                    // Recalculate AABB for next check in loop
-                                    p_min_x = self.position.x - (self.width / 2.0);
-                                    p_max_x = self.position.x + (self.width / 2.0);
-                                }
-                            }
-                            Axis::Y => {
-                                if pen_y <= pen_x && pen_y <= pen_z {
-                                    let pen_from_top = p_max_y - v_min_y;
-                                    let pen_from_bottom = v_max_y - p_min_y;
-
-                                    if pen_from_top < pen_from_bottom {
-                                        self.position.y -= pen_from_top + EPSILON; // Push down
-                                    } else {
-                                        self.position.y += pen_from_bottom + EPSILON; // Push up
-                                        self.is_on_ground = true;
-                                    }
-
-                                    self.velocity.y = 0.0;
-
-                                    p_min_y = self.position.y - (self.height / 2.0);
-                                    p_max_y = self.position.y + (self.height / 2.0);
-                                }
-                            }
-                            Axis::Z => {
-                                if pen_z <= pen_x && pen_z <= pen_y {
-                                    let pen_from_front = p_max_z - v_min_z;
-                                    let pen_from_back = v_max_z - p_min_z;
-
-                                    if pen_from_front < pen_from_back {
-                                        self.position.z -= pen_from_front + EPSILON; // Push "back"
-                                    } else {
-                                        self.position.z += pen_from_back + EPSILON;  // Push "forward"
-                                    }
-
-                                    self.velocity.z = 0.0;
-
-                                    p_min_z = self.position.z - (self.width / 2.0);
-                                    p_max_z = self.position.z + (self.width / 2.0);
-                                }
-                            }
+                    let v_min = [x as f32, y as f32, z as f32];
+                    let overlaps_other_axes = (0..3)
+                        .filter(|&i| i != a)
+                        .all(|i| p_min[i] < v_min[i] + 1.0 && p_max[i] > v_min[i]);
+
+                    if !overlaps_other_axes {
+                        continue;
+                    }
+
+                    if delta > 0.0 {
+                        let gap = v_min[a] - p_max[a];
+                        if gap >= -EPSILON {
+                            allowed = allowed.min((gap - EPSILON).max(0.0));
+                        }
+                    } else {
+                        let gap = p_min[a] - (v_min[a] + 1.0);
+                        if gap >= -EPSILON {
+                            allowed = allowed.max(-(gap - EPSILON).max(0.0));
                         }
                     }
                 }
             }
         }
+
+        match axis {
+            Axis::X => self.position.x += allowed,
+            Axis::Y => self.position.y += allowed,
+            Axis::Z => self.position.z += allowed,
+        }
+
+        if allowed == delta {
+            return;
+        }
+
+        match axis {
+            Axis::X => self.velocity.x = 0.0,
+            Axis::Y => {
+                if delta > 0.0 {
+                    self.events.push(PlayerEvent::HeadBump { speed: self.velocity.y });
+
+                    // Never keep upward velocity after a bump, or the player would be pressed
+                    // against the ceiling.
+                    self.velocity.y = -self.velocity.y * self.ceiling_restitution;
+                } else {
+                    self.is_on_ground = true;
+                    self.velocity.y = 0.0;
+                }
+            }
+            Axis::Z => self.velocity.z = 0.0,
+        }
+    }
+
+    // Whether moving along `direction` would run into a single block with room to stand on
+    // top of it, i.e. something the player could hop onto.
+    pub fn is_facing_step(&self, world: &World, direction: cgmath::Vector3<f32>) -> bool {
+        const PROBE_DISTANCE: f32 = 0.1;
+
+        let horizontal = cgmath::Vector3::new(direction.x, 0.0, direction.z);
+        if horizontal.is_zero() {
+            return false;
+        }
+
+        let ahead = self.position + horizontal.normalize() * (self.width / 2.0 + PROBE_DISTANCE);
+        let (ax, az) = (ahead.x.floor() as i32, ahead.z.floor() as i32);
+        let (px, pz) = (self.position.x.floor() as i32, self.position.z.floor() as i32);
+        let feet = (self.position.y - self.height / 2.0 + 0.01).floor() as i32;
+        let headroom = self.height.ceil() as i32;
+
+        let is_solid = |x, y, z| world.get_voxel(x, y, z).is_some_and(VoxelType::is_solid);
+
+        is_solid(ax, feet, az)
+            && (1..=headroom).all(|dy| !is_solid(ax, feet + dy, az))
+            && !is_solid(px, feet + headroom, pz)
+    }
+
+    // The solid voxel the player is standing on: the one under the middle of their feet, or
+    // failing that whichever solid voxel under the footprint it overlaps most, so standing on
+    // the edge of a block still reports it.
+    pub fn ground_block(&self, world: &World) -> Option<(i32, i32, i32, VoxelType)> {
+        // How far below the feet to look, enough to cover the gap collision leaves.
+        const PROBE_DEPTH: f32 = 0.05;
+
+        let y = (self.position.y - self.height / 2.0 - PROBE_DEPTH).floor() as i32;
+        let half_width = self.width / 2.0;
+        let (min_x, max_x) = (self.position.x - half_width, self.position.x + half_width);
+        let (min_z, max_z) = (self.position.z - half_width, self.position.z + half_width);
+
+        let solid_at = |x: i32, z: i32| world.get_voxel(x, y, z)
+            .filter(|voxel| voxel.is_solid())
+            .map(|voxel| (x, y, z, voxel));
+
+        if let Some(block) = solid_at(self.position.x.floor() as i32, self.position.z.floor() as i32) {
+            return Some(block);
+        }
+
+        let overlap = |x: i32, z: i32| {
+            let width = max_x.min(x as f32 + 1.0) - min_x.max(x as f32);
+            let depth = max_z.min(z as f32 + 1.0) - min_z.max(z as f32);
+            width * depth
+        };
+
+        (min_x.floor() as i32..=max_x.floor() as i32)
+            .flat_map(|x| (min_z.floor() as i32..=max_z.floor() as i32).map(move |z| (x, z)))
+            .filter_map(|(x, z)| solid_at(x, z).map(|block| (block, overlap(x, z))))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(block, _)| block)
+    }
+
+    pub fn take_damage(&mut self, amount: f32) {
+        if self.invulnerable || amount <= 0.0 {
+            return;
+        }
+
+        let amount = amount.min(self.health);
+        self.health -= amount;
+        self.events.push(PlayerEvent::Damage { amount });
+    }
+
+    pub fn heal(&mut self, amount: f32) {
+        self.health = (self.health + amount.max(0.0)).min(MAX_HEALTH);
+    }
+
+    // Moves the player's center to `position` and stops them, so they fall from rest. Returns
+    // false and leaves the player where they are if the chunk at `position` isn't loaded, as
+    // they would fall through it.
+    pub fn teleport(&mut self, world: &World, position: cgmath::Point3<f32>) -> bool {
+        let (x, y, z) = (position.x.floor() as i32, position.y.floor() as i32, position.z.floor() as i32);
+        if world.get_voxel(x, y, z).is_none() {
+            return false;
+        }
+
+        self.position = position;
+        self.velocity = cgmath::Vector3::new(0.0, 0.0, 0.0);
+        self.is_on_ground = false;
+        true
+    }
+
+    pub fn is_in_water(&self) -> bool {
+        self.submersion > 0.0
+    }
+
+    // Measured along the column through the player's center.
+    fn measure_submersion(&self, world: &World) -> f32 {
+        let feet = self.position.y - self.height / 2.0;
+        let head = feet + self.height;
+        let (x, z) = (self.position.x.floor() as i32, self.position.z.floor() as i32);
+
+        let depth: f32 = (feet.floor() as i32..=head.floor() as i32)
+            .filter(|&y| world.get_voxel(x, y, z).is_some_and(VoxelType::is_liquid))
+            .map(|y| head.min(y as f32 + 1.0) - feet.max(y as f32))
+            .sum();
+
+        (depth / self.height).clamp(0.0, 1.0)
+    }
+
+    pub fn set_flying(&mut self, flying: bool) {
+        self.flying = flying;
+        if !flying {
+            // Start falling from rest rather than keeping the last climb or descent speed.
+            self.noclip = false;
+            self.velocity.y = 0.0;
+        }
+    }
+
+    // Vertical offset to apply to the view, which lags behind the body after a step up.
+    pub fn step_offset(&self) -> f32 {
+        self.step_offset
     }
 
     pub fn update(&mut self, world: &mut World, dt: f32) {
+        let was_on_ground = self.is_on_ground;
         self.is_on_ground = false;
-        self.velocity.y += GRAVITY * dt;
+        self.step_offset = (self.step_offset + STEP_SMOOTHING_SPEED * dt).min(0.0);
+        self.submersion = self.measure_submersion(world);
+        if self.flying {
+            // Flying ignores gravity and water alike.
+        } else if self.is_in_water() && !self.ghost {
+            self.velocity.y += (GRAVITY * WATER_GRAVITY_SCALE + BUOYANCY * self.submersion) * dt;
+            self.velocity.y *= (-WATER_DRAG * dt).exp();
+        } else {
+            self.velocity.y += GRAVITY * dt;
+        }
 
+        let start = self.position;
+        let start_velocity = self.velocity;
         let desired_movement = self.velocity * dt;
 
-        self.position.x += desired_movement.x;
-        self.resolve_collisions(world, Axis::X);
-        self.position.y += desired_movement.y;
-        self.resolve_collisions(world, Axis::Y);
-        self.position.z += desired_movement.z;
-        self.resolve_collisions(world, Axis::Z);
+        if (self.flying && self.noclip) || self.ghost {
+            self.position += desired_movement;
+            return;
+        }
+
+        self.move_by(world, desired_movement);
+
+        // Blocked moves zero the velocity along that axis.
+        let blocked = (self.velocity.x == 0.0 && start_velocity.x != 0.0)
+            || (self.velocity.z == 0.0 && start_velocity.z != 0.0);
+        if blocked && was_on_ground && !self.flying && desired_movement.y <= 0.0 && self.step_height > 0.0 {
+            self.try_step_up(world, start, start_velocity, desired_movement);
+        }
+
+        if self.is_on_ground && !was_on_ground {
+            self.land(-start_velocity.y);
+        }
+
+        if self.is_on_ground && !self.flying {
+            self.walk(cgmath::Vector2::new(self.position.x - start.x, self.position.z - start.z).magnitude());
+        }
+    }
+
+    fn walk(&mut self, distance: f32) {
+        if self.footstep_distance <= 0.0 {
+            return;
+        }
+
+        self.step_distance += distance;
+        while self.step_distance >= self.footstep_distance {
+            self.step_distance -= self.footstep_distance;
+            self.events.push(PlayerEvent::Footstep);
+        }
+    }
+
+    fn land(&mut self, speed: f32) {
+        self.events.push(PlayerEvent::Land { speed });
+        if !self.flying && !self.is_in_water() {
+            self.take_damage(self.fall_damage.damage(speed));
+        }
+    }
+
+    fn move_by(&mut self, world: &World, movement: cgmath::Vector3<f32>) {
+        // Axes are resolved one at a time so blocked movement along one still slides along the
+        // others.
+        self.move_axis(world, Axis::X, movement.x);
+        self.move_axis(world, Axis::Y, movement.y);
+        self.move_axis(world, Axis::Z, movement.z);
     }
-}
\ No newline at end of file
+
+    // Redoes a horizontally blocked move from `start` lifted by up to `step_height`, then drops
+    // back down. The step is kept only if it gets further and ends standing on something, so
+    // walls taller than a step still stop the player.
+    fn try_step_up(
+        &mut self,
+        world: &World,
+        start: cgmath::Point3<f32>,
+        start_velocity: cgmath::Vector3<f32>,
+        movement: cgmath::Vector3<f32>,
+    ) {
+        let blocked_position = self.position;
+        let blocked_velocity = self.velocity;
+        let blocked_on_ground = self.is_on_ground;
+        let event_count = self.events.len();
+
+        self.position = start;
+        self.velocity = start_velocity;
+        self.is_on_ground = false;
+
+        self.move_axis(world, Axis::Y, self.step_height);
+        let lifted = self.position.y - start.y;
+        self.move_axis(world, Axis::X, movement.x);
+        self.move_axis(world, Axis::Z, movement.z);
+        self.move_axis(world, Axis::Y, movement.y - lifted);
+
+        let horizontal_distance = |p: cgmath::Point3<f32>| cgmath::Vector2::new(p.x - start.x, p.z - start.z).magnitude();
+        let climbed = self.position.y - blocked_position.y;
+
+        let got_further = horizontal_distance(self.position) > horizontal_distance(blocked_position) + 1e-4;
+
+        // Bumping a low ceiling while probing isn't a real head bump.
+        self.events.truncate(event_count);
+
+        if self.is_on_ground && climbed > 0.0 && got_further {
+            self.step_offset -= climbed;
+        } else {
+            self.position = blocked_position;
+            self.velocity = blocked_velocity;
+            self.is_on_ground = blocked_on_ground;
+        }
+    }
+
+    pub fn take_events(&mut self) -> impl Iterator<Item = PlayerEvent> + '_ {
+        self.events.drain(..)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::chunk::ChunkPos;
+
+    // Chunks (0, -1, -1) to (0, 0, 0), flattened to stone up to y = 0 so the generated terrain
+    // doesn't get in the way.
+    fn flat_world() -> World {
+        let mut world = World::new();
+        world.load_region(ChunkPos::new(0, -1, -1), ChunkPos::new(0, 0, 0));
+        for x in 0..16 {
+            for y in -16..16 {
+                for z in -16..16 {
+                    world.set_voxel(x, y, z, if y <= 0 { VoxelType::Stone } else { VoxelType::Air });
+                }
+            }
+        }
+        world
+    }
+
+    #[test]
+    fn fast_player_stops_at_thin_wall() {
+        let mut world = flat_world();
+
+        // A one block thick wall across the player's path at x = 5.
+        for y in 1..4 {
+            for z in -3..3 {
+                world.set_voxel(5, y, z, VoxelType::Stone);
+            }
+        }
+
+        let mut player = Player::new((2.0, 1.0 + 0.9 + 0.002, 0.0).into());
+        player.velocity.x = 100.0;
+
+        // 10 m per step at this speed, far more than the wall is thick.
+        player.update(&mut world, 0.1);
+
+        assert!(player.position.x + player.width / 2.0 <= 5.0, "tunneled to x = {}", player.position.x);
+        assert!(player.position.x + player.width / 2.0 > 4.9, "stopped early at x = {}", player.position.x);
+        assert_eq!(player.velocity.x, 0.0);
+    }
+
+    #[test]
+    fn fast_jump_into_a_ceiling_bumps_and_falls() {
+        let mut world = flat_world();
+
+        // A one block thick ceiling whose underside is 0.2 above the player's head.
+        for x in 0..8 {
+            for z in -4..4 {
+                world.set_voxel(x, 3, z, VoxelType::Stone);
+            }
+        }
+
+        let mut player = Player::new((4.5, 1.0 + 0.9 + 0.002, 0.5).into());
+        player.velocity.y = 40.0;
+
+        // 4 m per step at this speed, far more than the gap to the ceiling.
+        player.update(&mut world, 0.1);
+
+        assert!(player.take_events().any(|event| matches!(event, PlayerEvent::HeadBump { .. })));
+        assert!(player.velocity.y <= 0.0, "still moving up at {} m/s", player.velocity.y);
+        assert!(player.position.y + player.height / 2.0 <= 3.0, "head inside the ceiling at y = {}", player.position.y);
+
+        // Nothing keeps the player pressed against the ceiling.
+        let bumped = player.position.y;
+        for _ in 0..3 {
+            player.update(&mut world, 1.0 / 60.0);
+        }
+        assert!(player.position.y < bumped, "stuck at y = {}", player.position.y);
+        assert!(!player.take_events().any(|event| matches!(event, PlayerEvent::HeadBump { .. })));
+    }
+
+    #[test]
+    fn player_floats_in_water() {
+        let mut world = flat_world();
+
+        // A pool 7 blocks deep, with its surface level with the ground.
+        for x in 0..8 {
+            for y in -6..=0 {
+                for z in -4..4 {
+                    world.set_voxel(x, y, z, VoxelType::Water);
+                }
+            }
+        }
+
+        let mut player = Player::new((4.5, 5.0, 0.5).into());
+        for _ in 0..600 {
+            player.update(&mut world, 1.0 / 60.0);
+        }
+
+        let feet = player.position.y - player.height / 2.0;
+        let head = feet + player.height;
+        assert!(feet > -2.0, "sank to y = {}", feet);
+        assert!(head > 1.0, "head is underwater at y = {}", head);
+        assert!(player.is_in_water());
+        assert!(player.velocity.y.abs() < 0.5, "still bobbing at {} m/s", player.velocity.y);
+    }
+
+    #[test]
+    fn sprinting_steps_faster_than_walking() {
+        let footsteps = |speed: f32| {
+            let mut world = flat_world();
+            let mut player = Player::new((1.0, 1.0 + 0.9 + 0.002, 0.0).into());
+            let mut count = 0;
+            for _ in 0..90 {
+                player.velocity.x = speed;
+                player.update(&mut world, 1.0 / 60.0);
+                count += player.take_events().filter(|event| *event == PlayerEvent::Footstep).count();
+            }
+            count
+        };
+
+        let walking = footsteps(4.3);
+        let sprinting = footsteps(4.3 * 1.6);
+        assert!(walking > 0);
+        assert!(sprinting > walking, "{} steps sprinting, {} walking", sprinting, walking);
+    }
+
+    #[test]
+    fn teleporting_needs_a_loaded_chunk() {
+        let world = flat_world();
+        let mut player = Player::new((8.5, 2.0, 0.5).into());
+        player.velocity = (3.0, -10.0, 0.0).into();
+
+        assert!(player.teleport(&world, (8.5, 10.0, 8.5).into()));
+        assert_eq!(player.position, (8.5, 10.0, 8.5).into());
+        assert_eq!(player.velocity, (0.0, 0.0, 0.0).into());
+
+        assert!(!player.teleport(&world, (8.5, 100.0, 8.5).into()));
+        assert_eq!(player.position, (8.5, 10.0, 8.5).into());
+    }
+}
@@ -1,20 +1,27 @@
-use cgmath::prelude::*;
 use crate::game::chunk::VoxelType;
 use crate::game::world::World;
+use crate::rendering::texture_atlas::FaceDirection;
 
 pub struct RaycastHit {
     pub position: (i32, i32, i32),
     pub normal: (i32, i32, i32),
     pub distance: f32,
+    // World-space point where the ray enters the hit voxel.
+    pub hit_point: cgmath::Point3<f32>,
+    // The face of the hit voxel the ray entered through.
+    pub face: FaceDirection,
 }
 
-pub fn raycast_voxel(
+// Finds the first voxel along the ray that `is_target` accepts, passing through the rest, so
+// targeting can aim through glass at the block behind it.
+pub fn raycast_voxel_where(
     world: &World,
     origin: cgmath::Point3<f32>,
     direction: cgmath::Vector3<f32>,
     max_distance: f32,
+    is_target: impl Fn(VoxelType) -> bool,
 ) -> Option<RaycastHit> {
-    let mut current_pos = origin;
+    let current_pos = origin;
 
     // Step size for each axis
     let step_x = if direction.x > 0.0 { 1 } else { -1 };
@@ -55,20 +62,24 @@ pub fn raycast_voxel(
         f32::MAX
     };
 
-    let mut last_normal = (0, 0, 0);
+    // If the origin voxel is already solid no boundary has been crossed yet, so report the face
+    // pointing back along the ray's dominant axis.
+    let mut last_normal = dominant_axis_normal(-direction);
     let mut distance = 0.0;
     
     // Step through voxels
     for _ in 0..100 {
-        // Check if current voxel is solid
-        if let Some(voxel) = world.get_voxel(voxel_x, voxel_y, voxel_z) {
-            if !matches!(voxel, VoxelType::Air) {
-                return Some(RaycastHit {
-                    position: (voxel_x, voxel_y, voxel_z),
-                    normal: last_normal,
-                    distance,
-                });
-            }
+        // Check if current voxel is a target
+        if let Some(voxel) = world.get_voxel(voxel_x, voxel_y, voxel_z)
+            && is_target(voxel)
+        {
+            return Some(RaycastHit {
+                position: (voxel_x, voxel_y, voxel_z),
+                normal: last_normal,
+                distance,
+                hit_point: origin + direction * distance,
+                face: FaceDirection::from_offset(last_normal).expect("hit normals are unit axis offsets"),
+            });
         }
 
         // Step to next voxel boundary
@@ -95,4 +106,42 @@ pub fn raycast_voxel(
     }
     
     None
+}
+
+fn dominant_axis_normal(direction: cgmath::Vector3<f32>) -> (i32, i32, i32) {
+    let (ax, ay, az) = (direction.x.abs(), direction.y.abs(), direction.z.abs());
+
+    if ax >= ay && ax >= az {
+        (direction.x.signum() as i32, 0, 0)
+    } else if ay >= az {
+        (0, direction.y.signum() as i32, 0)
+    } else {
+        (0, 0, direction.z.signum() as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::chunk::ChunkPos;
+
+    #[test]
+    fn hit_on_first_voxel_has_unit_normal() {
+        let mut world = World::new();
+        world.load_region(ChunkPos::new(0, -1, 0), ChunkPos::new(0, 0, 0));
+
+        // Start inside the grass layer at y = 0, looking down and slightly sideways.
+        let origin = cgmath::Point3::new(4.5, 0.5, 4.5);
+        let direction = cgmath::Vector3::new(0.2, -1.0, 0.1);
+
+        let hit = raycast_voxel_where(&world, origin, direction, 5.0, |voxel| voxel != VoxelType::Air)
+            .expect("ray should hit the ground");
+        let (nx, ny, nz) = hit.normal;
+
+        assert_eq!(hit.position, (4, 0, 4));
+        assert_eq!(nx.abs() + ny.abs() + nz.abs(), 1);
+        assert_eq!(hit.normal, (0, 1, 0));
+        assert_eq!(hit.face, FaceDirection::Top);
+        assert_eq!(hit.hit_point, origin);
+    }
 }
\ No newline at end of file
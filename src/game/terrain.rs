@@ -0,0 +1,115 @@
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use crate::game::chunk::VoxelType;
+
+// Blocks per cycle of the climate maps that pick biomes, and of the hills within them.
+const CLIMATE_SCALE: f64 = 512.0;
+const HILL_SCALE: f64 = 96.0;
+const HILL_OCTAVES: usize = 4;
+
+// Climate values over which one biome fades into the next. Perlin noise mostly stays within
+// ±0.5, so these put roughly a quarter of the world in each of deserts and mountains.
+const MOUNTAIN_COLD: (f64, f64) = (-0.1, -0.3);
+const DESERT_HOT: (f64, f64) = (0.1, 0.3);
+const DESERT_DRY: (f64, f64) = (0.1, -0.1);
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Mountains,
+}
+
+impl Biome {
+    const ALL: [Biome; 3] = [Biome::Plains, Biome::Desert, Biome::Mountains];
+
+    // Average surface height and how far hills rise above and dip below it.
+    fn shape(self) -> (f64, f64) {
+        match self {
+            Biome::Plains => (2.0, 4.0),
+            Biome::Desert => (3.0, 3.0),
+            Biome::Mountains => (12.0, 20.0),
+        }
+    }
+
+    // The top block and the blocks in the few layers under it.
+    pub fn surface_blocks(self) -> (VoxelType, VoxelType) {
+        match self {
+            Biome::Plains => (VoxelType::Grass, VoxelType::Dirt),
+            Biome::Desert => (VoxelType::Sand, VoxelType::Sand),
+            Biome::Mountains => (VoxelType::Stone, VoxelType::Stone),
+        }
+    }
+}
+
+// The generated shape of one column of the world.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Column {
+    // Y of the topmost solid voxel.
+    pub height: i32,
+    // The biome with the most say in this column, which picks its blocks.
+    pub biome: Biome,
+}
+
+// Terrain height and biomes, as a pure function of the seed and the column.
+pub struct Terrain {
+    seed: u32,
+    temperature: Perlin,
+    humidity: Perlin,
+    hills: Fbm<Perlin>,
+}
+
+impl Terrain {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            temperature: Perlin::new(seed),
+            humidity: Perlin::new(seed.wrapping_add(1)),
+            hills: Fbm::<Perlin>::new(seed.wrapping_add(2)).set_octaves(HILL_OCTAVES),
+        }
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    // How much each biome (in `Biome::ALL` order) shapes the column, summing to 1. The climate
+    // maps are continuous, so the weights and the heights blended from them change gradually
+    // across biome borders instead of stepping.
+    fn biome_weights(&self, wx: i32, wz: i32) -> [f64; 3] {
+        let point = [wx as f64 / CLIMATE_SCALE, wz as f64 / CLIMATE_SCALE];
+        let temperature = self.temperature.get(point);
+        let humidity = self.humidity.get(point);
+
+        let mountains = smoothstep(MOUNTAIN_COLD, temperature);
+        let desert = smoothstep(DESERT_HOT, temperature) * smoothstep(DESERT_DRY, humidity);
+        [1.0 - mountains - desert, desert, mountains]
+    }
+
+    // With `biomes` off every column is plains.
+    pub fn column(&self, wx: i32, wz: i32, biomes: bool) -> Column {
+        let weights = if biomes { self.biome_weights(wx, wz) } else { [1.0, 0.0, 0.0] };
+        let hills = self.hills.get([wx as f64 / HILL_SCALE, wz as f64 / HILL_SCALE]);
+
+        let height = Biome::ALL.iter()
+            .zip(weights)
+            .map(|(biome, weight)| {
+                let (base, amplitude) = biome.shape();
+                weight * (base + amplitude * hills)
+            })
+            .sum::<f64>();
+
+        let biome = Biome::ALL.iter()
+            .zip(weights)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(biome, _)| *biome)
+            .unwrap_or(Biome::Plains);
+
+        Column { height: height.floor() as i32, biome }
+    }
+}
+
+// 0 at `edges.0`, 1 at `edges.1` and smooth in between. The edges may be in either order.
+fn smoothstep(edges: (f64, f64), x: f64) -> f64 {
+    let t = ((x - edges.0) / (edges.1 - edges.0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
@@ -0,0 +1,57 @@
+use cgmath::InnerSpace;
+
+// Real seconds per in-game day at speed 1.
+const DAY_LENGTH: f32 = 600.0;
+// Tilts the sun's path towards +z so it never passes exactly overhead.
+const SUN_TILT: f32 = 0.3;
+
+// The in-game clock. Purely visual for now: it only drives the sun and sky.
+pub struct TimeOfDay {
+    // Fraction of the day: 0.0 is midnight, 0.25 sunrise, 0.5 noon and 0.75 sunset.
+    time: f32,
+    // Multiplier on how fast time passes; 0 stops the clock.
+    pub speed: f32,
+    // Debug option for consistent shading: the sun stays straight overhead at full daylight
+    // whatever the clock says.
+    pub lock_noon: bool,
+}
+
+impl TimeOfDay {
+    pub fn new(time: f32) -> Self {
+        Self {
+            time: time.rem_euclid(1.0),
+            speed: 1.0,
+            lock_noon: false,
+        }
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time.rem_euclid(1.0);
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.set_time(self.time + dt * self.speed / DAY_LENGTH);
+    }
+
+    // Points from the sun into the world. The sun rises in the +x direction and sets in -x.
+    pub fn sun_direction(&self) -> cgmath::Vector3<f32> {
+        if self.lock_noon {
+            return -cgmath::Vector3::unit_y();
+        }
+
+        let angle = (self.time - 0.25) * std::f32::consts::TAU;
+        -cgmath::Vector3::new(angle.cos(), angle.sin(), SUN_TILT).normalize()
+    }
+
+    // How much daylight there is, from 0.0 at night to 1.0 during the day, fading over dawn
+    // and dusk.
+    pub fn daylight(&self) -> f32 {
+        let sun_height = -self.sun_direction().y;
+        let t = ((sun_height + 0.1) / 0.3).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
@@ -1,56 +1,262 @@
-use std::collections::{HashMap, HashSet};
-use crate::game::chunk::{Chunk, ChunkPos, VoxelType, CHUNK_SIZE};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::game::chunk::{self, Chunk, ChunkNeighbors, ChunkPos, SectionMask, VoxelType, ALL_SECTIONS, CHUNK_SIZE};
+use crate::game::lighting;
+use crate::game::ores::{self, OreVeins};
+use crate::game::terrain::{Biome, Column, Terrain};
+use crate::rendering::texture_atlas::FaceDirection;
+
+const SAVE_MAGIC: &[u8; 4] = b"VXW3";
+// Saves from before the seed was stored, which are still loaded with `DEFAULT_SEED`.
+const SAVE_MAGIC_V2: &[u8; 4] = b"VXW2";
+// Saves from before player data was stored, which are still loaded.
+const SAVE_MAGIC_V1: &[u8; 4] = b"VXW1";
+// Layers kept below the surface by the surface-only preview: the grass and the dirt under it.
+const PREVIEW_CRUST_DEPTH: i32 = 4;
+// Air at or below this height is filled with water, making lakes in the valleys.
+const SEA_LEVEL: i32 = -1;
+// Worlds are generated from this seed unless the `seed` command picks another.
+const DEFAULT_SEED: u32 = 0x5eed;
+
+// One grass column in this many grows a tree.
+const TREE_CHANCE: u64 = 80;
+const TREE_MIN_TRUNK: i32 = 4;
+const TREE_MAX_TRUNK: i32 = 6;
+// Leaves reach this many blocks out from the trunk, so chunks also check trunks this far
+// outside their own columns for leaves that hang over.
+const TREE_CANOPY_RADIUS: i32 = 2;
+
+// Splits a world voxel coordinate into its chunk and the local position inside it.
+fn split_world_pos(wx: i32, wy: i32, wz: i32) -> (ChunkPos, (usize, usize, usize)) {
+    let chunk_pos = ChunkPos::new(
+        wx.div_euclid(CHUNK_SIZE as i32),
+        wy.div_euclid(CHUNK_SIZE as i32),
+        wz.div_euclid(CHUNK_SIZE as i32),
+    );
+
+    let local_x = wx.rem_euclid(CHUNK_SIZE as i32) as usize;
+    let local_y = wy.rem_euclid(CHUNK_SIZE as i32) as usize;
+    let local_z = wz.rem_euclid(CHUNK_SIZE as i32) as usize;
+
+    (chunk_pos, (local_x, local_y, local_z))
+}
+
+// Terrain generation features that can be switched off one at a time to isolate them while
+// debugging generation.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenerationFeatures {
+    pub trees: bool,
+    pub ores: bool,
+    // Off, the whole world is plains.
+    pub biomes: bool,
+    // Preview mode for iterating on terrain shape: only a thin crust at the surface is
+    // generated and everything below it is left empty.
+    pub surface_only: bool,
+    // Off, the noise terrain (and the ores and trees in it) isn't generated, leaving empty
+    // space apart from `flat_floor`.
+    pub noise_terrain: bool,
+    // A stone layer at this height across the whole world, cutting through the terrain. With
+    // `noise_terrain` off it makes a clean plane for building tests.
+    pub flat_floor: Option<i32>,
+}
+
+impl Default for GenerationFeatures {
+    fn default() -> Self {
+        Self {
+            trees: true,
+            ores: true,
+            biomes: true,
+            surface_only: false,
+            noise_terrain: true,
+            flat_floor: None,
+        }
+    }
+}
+
+// Player state stored alongside the world, so loading a save restores the inventory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayerData {
+    pub hotbar: Vec<VoxelType>,
+    pub selected_slot: usize,
+}
+
+impl PlayerData {
+    // Layout: hotbar length, the hotbar's voxel ids, then the selected slot.
+    fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&(self.hotbar.len() as u32).to_le_bytes())?;
+        for voxel in &self.hotbar {
+            writer.write_all(&[voxel.to_u8()])?;
+        }
+        writer.write_all(&(self.selected_slot as u32).to_le_bytes())
+    }
+
+    fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let mut u32_bytes = [0u8; 4];
+        reader.read_exact(&mut u32_bytes)?;
+        let mut ids = vec![0u8; u32::from_le_bytes(u32_bytes) as usize];
+        reader.read_exact(&mut ids)?;
+
+        let hotbar = ids.into_iter()
+            .map(|id| VoxelType::from_u8(id).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("Unknown voxel id {} in hotbar", id))
+            }))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        reader.read_exact(&mut u32_bytes)?;
+        let selected_slot = u32::from_le_bytes(u32_bytes) as usize;
+
+        Ok(Self { hotbar, selected_slot })
+    }
+}
 
 pub struct World {
-    chunks: HashMap<ChunkPos, Chunk>,
-    dirty_chunks: HashSet<ChunkPos>,
+    // Chunks are shared with the mesh workers; edits copy a chunk only while a worker holds it.
+    chunks: HashMap<ChunkPos, Arc<Chunk>>,
+    // The sections of each chunk that need remeshing.
+    dirty_chunks: HashMap<ChunkPos, SectionMask>,
+    generation: GenerationFeatures,
+    ore_veins: OreVeins,
+    terrain: Terrain,
 }
 
 impl World {
     pub fn new() -> Self {
+        Self::with_seed(DEFAULT_SEED)
+    }
+
+    // An empty world whose chunks are generated from `seed`.
+    pub fn with_seed(seed: u32) -> Self {
         Self {
             chunks: HashMap::new(),
-            dirty_chunks: HashSet::new(),
+            dirty_chunks: HashMap::new(),
+            generation: GenerationFeatures::default(),
+            ore_veins: OreVeins::default(),
+            terrain: Terrain::new(seed),
         }
     }
 
+    pub fn seed(&self) -> u32 {
+        self.terrain.seed()
+    }
+
+    pub fn generation_features(&self) -> GenerationFeatures {
+        self.generation
+    }
+
+    // Only affects chunks generated from now on.
+    pub fn set_generation_features(&mut self, features: GenerationFeatures) {
+        self.generation = features;
+    }
+
+    pub fn ore_veins(&self) -> OreVeins {
+        self.ore_veins
+    }
+
+    // Only affects chunks generated from now on.
+    pub fn set_ore_veins(&mut self, veins: OreVeins) {
+        self.ore_veins = veins;
+    }
+
     pub fn get_chunk(&self, pos: ChunkPos) -> Option<&Chunk> {
-        self.chunks.get(&pos)
+        self.chunks.get(&pos).map(|chunk| chunk.as_ref())
+    }
+
+    // A cheap shared handle to the chunk, for handing to background work.
+    pub fn get_chunk_arc(&self, pos: ChunkPos) -> Option<Arc<Chunk>> {
+        self.chunks.get(&pos).cloned()
     }
 
     pub fn get_chunk_mut(&mut self, pos: ChunkPos) -> Option<&mut Chunk> {
-        self.chunks.get_mut(&pos)
+        self.chunks.get_mut(&pos).map(Arc::make_mut)
+    }
+
+    // The loaded chunks sharing a face with `pos`.
+    pub fn neighbors(&self, pos: ChunkPos) -> ChunkNeighbors {
+        ChunkNeighbors::from_fn(|(dx, dy, dz)| {
+            self.get_chunk_arc(ChunkPos::new(pos.x + dx, pos.y + dy, pos.z + dz))
+        })
     }
 
     pub fn load_chunk(&mut self, pos: ChunkPos) {
         if !self.chunks.contains_key(&pos) {
             let chunk = self.generate_chunk(pos);
-            self.chunks.insert(pos, chunk);
-            self.dirty_chunks.insert(pos);
+            self.chunks.insert(pos, Arc::new(chunk));
+            self.mark_dirty(pos, ALL_SECTIONS);
+
+            // Neighbors drew the faces they share with this chunk while it wasn't loaded, and
+            // can now hide those it covers. Those above and below only share their end section.
+            for (dx, dy, dz) in FaceDirection::ALL.map(|face| face.offset()) {
+                let neighbor = ChunkPos::new(pos.x + dx, pos.y + dy, pos.z + dz);
+                let sections = match dy {
+                    1 => 1,
+                    -1 => 1 << (chunk::SECTIONS_PER_CHUNK - 1),
+                    _ => ALL_SECTIONS,
+                };
+                if self.chunks.contains_key(&neighbor) {
+                    self.mark_dirty(neighbor, sections);
+                }
+            }
+            lighting::light_new_chunk(self, pos);
         }
     }
 
-    fn unload_chunk(&mut self, pos: ChunkPos) {
-        self.chunks.remove(&pos);
+    // Loads every chunk in the inclusive box between `min` and `max`. Chunks are generated in
+    // a fixed coordinate order so timings are comparable between runs.
+    pub fn load_region(&mut self, min: ChunkPos, max: ChunkPos) {
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    self.load_chunk(ChunkPos::new(x, y, z));
+                }
+            }
+        }
     }
 
     fn generate_chunk(&mut self, pos: ChunkPos) -> Chunk {
         let mut chunk = Chunk::new();
 
+        if self.generation.noise_terrain {
+            self.generate_terrain(&mut chunk, pos);
+        }
+        if let Some(floor) = self.generation.flat_floor {
+            Self::place_floor(&mut chunk, pos, floor);
+        }
+        if self.generation.noise_terrain && self.generation.trees {
+            self.place_trees(&mut chunk, pos);
+        }
+
+        chunk.compress();
+        chunk
+    }
+
+    fn generate_terrain(&self, chunk: &mut Chunk, pos: ChunkPos) {
         for z in 0..CHUNK_SIZE {
-            for y in 0..CHUNK_SIZE {
-                for x in 0..CHUNK_SIZE {
-                    let wx = pos.x * CHUNK_SIZE as i32 + x as i32;
+            for x in 0..CHUNK_SIZE {
+                let wx = pos.x * CHUNK_SIZE as i32 + x as i32;
+                let wz = pos.z * CHUNK_SIZE as i32 + z as i32;
+                let Column { height: surface, biome } = self.column(wx, wz);
+                let (top, below_top) = biome.surface_blocks();
+
+                for y in 0..CHUNK_SIZE {
                     let wy = pos.y * CHUNK_SIZE as i32 + y as i32;
-                    let wz = pos.z * CHUNK_SIZE as i32 + z as i32;
+                    if self.generation.surface_only && wy <= surface - PREVIEW_CRUST_DEPTH {
+                        continue;
+                    }
 
                     let voxel =
-                        if wy < -3 {
+                        if wy < surface - 3 {
                             VoxelType::Stone
-                        } else if wy < 0 {
-                            VoxelType::Dirt
-                        } else if wy == 0 {
-                            VoxelType::Grass
+                        } else if wy < surface {
+                            below_top
+                        } else if wy == surface {
+                            top
+                        } else if wy <= SEA_LEVEL {
+                            VoxelType::Water
                         } else {
                             VoxelType::Air
                         };
@@ -60,43 +266,597 @@ impl World {
             }
         }
 
-        chunk
+        if self.generation.ores {
+            ores::place_ores(chunk, pos, self.terrain.seed(), &self.ore_veins);
+        }
     }
 
-    pub fn get_voxel(&self, wx: i32, wy: i32, wz: i32) -> Option<VoxelType> {
-        let chunk_pos = ChunkPos::new(
-            wx.div_euclid(CHUNK_SIZE as i32),
-            wy.div_euclid(CHUNK_SIZE as i32),
-            wz.div_euclid(CHUNK_SIZE as i32),
-        );
+    fn place_floor(chunk: &mut Chunk, pos: ChunkPos, floor: i32) {
+        let y = floor - pos.y * CHUNK_SIZE as i32;
+        if !(0..CHUNK_SIZE as i32).contains(&y) {
+            return;
+        }
+
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                chunk.set_voxel(x, y as usize, z, VoxelType::Stone);
+            }
+        }
+    }
+
+    fn column(&self, wx: i32, wz: i32) -> Column {
+        self.terrain.column(wx, wz, self.generation.biomes)
+    }
+
+    // The biome that generated (or would generate) the column at `wx`, `wz`.
+    pub fn biome_at(&self, wx: i32, wz: i32) -> Biome {
+        self.column(wx, wz).biome
+    }
+
+    // A well-mixed hash of a column (splitmix64), so neighboring columns get unrelated values.
+    fn column_hash(&self, wx: i32, wz: i32) -> u64 {
+        let mut h = self.terrain.seed() as u64 ^ ((wx as u32 as u64) << 32 | wz as u32 as u64);
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        h ^ (h >> 31)
+    }
+
+    // Trunk height of the tree growing from the column at `wx`, `wz`, and the ground it grows
+    // from, if there is one. Trees only grow on grass, above the water.
+    fn tree_at(&self, wx: i32, wz: i32) -> Option<(i32, i32)> {
+        let hash = self.column_hash(wx, wz);
+        let column = self.column(wx, wz);
+        let on_grass = column.biome.surface_blocks().0 == VoxelType::Grass && column.height > SEA_LEVEL;
+        (on_grass && hash.is_multiple_of(TREE_CHANCE)).then(|| {
+            let range = (TREE_MAX_TRUNK - TREE_MIN_TRUNK + 1) as u64;
+            (TREE_MIN_TRUNK + ((hash / TREE_CHANCE) % range) as i32, column.height)
+        })
+    }
+
+    // Writes the parts of nearby trees that fall inside the chunk. Each tree depends only on
+    // its column, so a tree crossing a chunk border comes out whole whichever chunk loads
+    // first, without carrying writes between chunks.
+    fn place_trees(&self, chunk: &mut Chunk, pos: ChunkPos) {
+        let size = CHUNK_SIZE as i32;
+        let base = (pos.x * size, pos.y * size, pos.z * size);
+
+        let mut set = |wx: i32, wy: i32, wz: i32, voxel: VoxelType| {
+            let local = (wx - base.0, wy - base.1, wz - base.2);
+            let inside = [local.0, local.1, local.2].iter().all(|c| (0..size).contains(c));
+            if !inside {
+                return;
+            }
+
+            let (x, y, z) = (local.0 as usize, local.1 as usize, local.2 as usize);
+            // Leaves fill air only; trunks replace leaves from overlapping trees.
+            let current = chunk.get_voxel(x, y, z);
+            if current == Some(VoxelType::Air) || (voxel == VoxelType::Wood && current == Some(VoxelType::Leaves)) {
+                chunk.set_voxel(x, y, z, voxel);
+            }
+        };
+
+        let reach = TREE_CANOPY_RADIUS;
+        for tz in base.2 - reach..base.2 + size + reach {
+            for tx in base.0 - reach..base.0 + size + reach {
+                let Some((trunk, ground)) = self.tree_at(tx, tz) else {
+                    continue;
+                };
+                let top = ground + trunk;
+
+                // Two wide layers around the top of the trunk, then two narrow ones above.
+                for y in top - 1..=top + 2 {
+                    let radius = if y <= top { TREE_CANOPY_RADIUS } else { 1 };
+                    for dz in -radius..=radius {
+                        for dx in -radius..=radius {
+                            let corner = dx.abs() == radius && dz.abs() == radius;
+                            if !corner || (radius == 1 && y == top + 1) {
+                                set(tx + dx, y, tz + dz, VoxelType::Leaves);
+                            }
+                        }
+                    }
+                }
+
+                for y in ground + 1..=top {
+                    set(tx, y, tz, VoxelType::Wood);
+                }
+            }
+        }
+    }
+
+    // Regenerates every chunk that hasn't been edited, e.g. after changing the generation
+    // features. Edited chunks are kept as they are.
+    pub fn regenerate(&mut self) {
+        let mut positions = self.chunks.iter()
+            .filter(|(_, chunk)| !chunk.is_modified())
+            .map(|(pos, _)| *pos)
+            .collect::<Vec<_>>();
+        positions.sort();
+
+        for pos in &positions {
+            self.chunks.remove(pos);
+        }
+        for pos in positions {
+            self.load_chunk(pos);
+        }
+    }
 
-        let local_x = wx.rem_euclid(CHUNK_SIZE as i32) as usize;
-        let local_y = wy.rem_euclid(CHUNK_SIZE as i32) as usize;
-        let local_z = wz.rem_euclid(CHUNK_SIZE as i32) as usize;
+    pub fn chunk_positions(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    pub fn is_dirty(&self, pos: ChunkPos) -> bool {
+        self.dirty_chunks.contains_key(&pos)
+    }
+
+    fn mark_dirty(&mut self, pos: ChunkPos, sections: SectionMask) {
+        *self.dirty_chunks.entry(pos).or_default() |= sections;
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    // Approximate memory used by voxel storage across all loaded chunks, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.chunks.values().map(|chunk| chunk.memory_usage()).sum()
+    }
+
+    pub fn get_voxel(&self, wx: i32, wy: i32, wz: i32) -> Option<VoxelType> {
+        let (chunk_pos, (local_x, local_y, local_z)) = split_world_pos(wx, wy, wz);
 
         self.chunks.get(&chunk_pos)?.get_voxel(local_x, local_y, local_z)
     }
 
-    pub fn set_voxel(&mut self, wx: i32, wy: i32, wz: i32, voxel: VoxelType) {
-        let chunk_pos = ChunkPos::new(
-            wx.div_euclid(CHUNK_SIZE as i32),
-            wy.div_euclid(CHUNK_SIZE as i32),
-            wz.div_euclid(CHUNK_SIZE as i32),
-        );
+    // Y of the topmost solid voxel in the column at (wx, wz), among loaded chunks.
+    pub fn highest_solid(&self, wx: i32, wz: i32) -> Option<i32> {
+        let (column, _) = split_world_pos(wx, 0, wz);
+        let chunk_ys = self.chunks.keys()
+            .filter(|pos| pos.x == column.x && pos.z == column.z)
+            .map(|pos| pos.y)
+            .collect::<Vec<_>>();
+        let top = (chunk_ys.iter().max()? + 1) * CHUNK_SIZE as i32 - 1;
+        let bottom = chunk_ys.iter().min()? * CHUNK_SIZE as i32;
 
-        let local_x = wx.rem_euclid(CHUNK_SIZE as i32) as usize;
-        let local_y = wy.rem_euclid(CHUNK_SIZE as i32) as usize;
-        let local_z = wz.rem_euclid(CHUNK_SIZE as i32) as usize;
+        (bottom..=top).rev().find(|&wy| self.get_voxel(wx, wy, wz).is_some_and(VoxelType::is_solid))
+    }
+
+    // Like `set_voxel`, but never loads chunks: returns false and does nothing if the voxel's
+    // chunk isn't loaded.
+    pub fn set_loaded_voxel(&mut self, wx: i32, wy: i32, wz: i32, voxel: VoxelType) -> bool {
+        if self.get_voxel(wx, wy, wz).is_none() {
+            return false;
+        }
+
+        self.set_voxel(wx, wy, wz, voxel);
+        true
+    }
+
+    pub fn set_voxel(&mut self, wx: i32, wy: i32, wz: i32, voxel: VoxelType) {
+        let (chunk_pos, (local_x, local_y, local_z)) = split_world_pos(wx, wy, wz);
 
         self.load_chunk(chunk_pos);
 
-        if let Some(chunk) = self.chunks.get_mut(&chunk_pos) {
+        let Some(old) = self.get_voxel(wx, wy, wz) else {
+            return;
+        };
+
+        if let Some(chunk) = self.get_chunk_mut(chunk_pos) {
+            if old != voxel {
+                chunk.mark_modified();
+            }
+
             chunk.set_voxel(local_x, local_y, local_z, voxel);
-            self.dirty_chunks.insert(chunk_pos);
+            self.mark_dirty(chunk_pos, chunk::sections_around(local_y));
+        }
+
+        // Neighboring chunks decide whether to draw the faces they share with this voxel, so
+        // they're remeshed too when it sits on their border. Only the sections next to it are.
+        let last = CHUNK_SIZE - 1;
+        for (local, offset) in [(local_x, (1, 0, 0)), (local_y, (0, 1, 0)), (local_z, (0, 0, 1))] {
+            let step = match local {
+                0 => -1,
+                l if l == last => 1,
+                _ => continue,
+            };
+            let neighbor_chunk = ChunkPos::new(
+                chunk_pos.x + offset.0 * step,
+                chunk_pos.y + offset.1 * step,
+                chunk_pos.z + offset.2 * step,
+            );
+            let across_y = match (offset.1, step) {
+                (0, _) => local_y,
+                (_, -1) => last,
+                _ => 0,
+            };
+            if self.chunks.contains_key(&neighbor_chunk) {
+                self.mark_dirty(neighbor_chunk, chunk::sections_around(across_y));
+            }
+        }
+
+        if old.is_transparent() != voxel.is_transparent() {
+            lighting::on_opacity_changed(self, (wx, wy, wz));
+        }
+    }
+
+    // Light level (0-15) at a world position, if its chunk is loaded.
+    pub fn get_light(&self, wx: i32, wy: i32, wz: i32) -> Option<u8> {
+        let (chunk_pos, (local_x, local_y, local_z)) = split_world_pos(wx, wy, wz);
+
+        Some(self.chunks.get(&chunk_pos)?.get_light(local_x, local_y, local_z))
+    }
+
+    // Sets the light level at a world position and queues every chunk whose faces sample it
+    // for remeshing. Does nothing if the chunk isn't loaded.
+    pub fn set_light(&mut self, wx: i32, wy: i32, wz: i32, level: u8) {
+        let (chunk_pos, (local_x, local_y, local_z)) = split_world_pos(wx, wy, wz);
+
+        let Some(chunk) = self.get_chunk_mut(chunk_pos) else {
+            return;
+        };
+        chunk.set_light(local_x, local_y, local_z, level);
+
+        for (dx, dy, dz) in [(0, 0, 0), (1, 0, 0), (-1, 0, 0), (0, 1, 0), (0, -1, 0), (0, 0, 1), (0, 0, -1)] {
+            let (neighbor_chunk, (_, neighbor_y, _)) = split_world_pos(wx + dx, wy + dy, wz + dz);
+            if self.chunks.contains_key(&neighbor_chunk) {
+                self.mark_dirty(neighbor_chunk, 1 << (neighbor_y / chunk::SECTION_HEIGHT));
+            }
+        }
+    }
+
+    // Saves every modified chunk; untouched chunks are regenerated when loaded again.
+    // Layout: magic, seed, chunk count, then per chunk its position followed by its run-length
+    // encoded voxels, and finally a flag byte followed by the player data if there is any.
+    pub fn save(&self, path: &Path, player: Option<&PlayerData>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        let mut positions = self.chunks.iter()
+            .filter(|(_, chunk)| chunk.is_modified())
+            .map(|(pos, _)| *pos)
+            .collect::<Vec<_>>();
+        positions.sort();
+
+        writer.write_all(SAVE_MAGIC)?;
+        writer.write_all(&self.seed().to_le_bytes())?;
+        writer.write_all(&(positions.len() as u32).to_le_bytes())?;
+
+        for pos in positions {
+            writer.write_all(&pos.x.to_le_bytes())?;
+            writer.write_all(&pos.y.to_le_bytes())?;
+            writer.write_all(&pos.z.to_le_bytes())?;
+            self.chunks[&pos].write_rle(&mut writer)?;
+        }
+
+        match player {
+            Some(player) => {
+                writer.write_all(&[1])?;
+                player.write(&mut writer)?;
+            }
+            None => writer.write_all(&[0])?,
+        }
+
+        writer.flush()
+    }
+
+    // Loads the modified chunks and player data written by `save`. The chunks come back dirty
+    // so their meshes are rebuilt; any other chunk is generated as usual when it is loaded.
+    pub fn load(path: &Path) -> io::Result<(World, Option<PlayerData>)> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if ![SAVE_MAGIC, SAVE_MAGIC_V2, SAVE_MAGIC_V1].contains(&&magic) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a world save file"));
+        }
+
+        let mut u32_bytes = [0u8; 4];
+        let seed = if &magic == SAVE_MAGIC {
+            reader.read_exact(&mut u32_bytes)?;
+            u32::from_le_bytes(u32_bytes)
+        } else {
+            DEFAULT_SEED
+        };
+        reader.read_exact(&mut u32_bytes)?;
+        let chunk_count = u32::from_le_bytes(u32_bytes);
+
+        let mut world = World::with_seed(seed);
+        for _ in 0..chunk_count {
+            let mut coords = [0i32; 3];
+            for coord in coords.iter_mut() {
+                reader.read_exact(&mut u32_bytes)?;
+                *coord = i32::from_le_bytes(u32_bytes);
+            }
+
+            let pos = ChunkPos::new(coords[0], coords[1], coords[2]);
+            let chunk = Chunk::read_rle(&mut reader)?;
+
+            world.chunks.insert(pos, Arc::new(chunk));
+            world.mark_dirty(pos, ALL_SECTIONS);
+        }
+
+        let mut has_player = [0u8];
+        if &magic != SAVE_MAGIC_V1 {
+            reader.read_exact(&mut has_player)?;
+        }
+        let player = if has_player[0] != 0 { Some(PlayerData::read(&mut reader)?) } else { None };
+
+        // Light isn't stored; rebuild it once every saved chunk is in place.
+        let mut positions = world.chunks.keys().copied().collect::<Vec<_>>();
+        positions.sort();
+        for pos in positions {
+            lighting::light_new_chunk(&mut world, pos);
+        }
+
+        Ok((world, player))
+    }
+
+    // Writes one chunk as pretty-printed JSON for debugging. Each layer is a y level and each
+    // row a z line of block names ordered by x. Unlike `save`, this isn't meant to be read back.
+    pub fn export_chunk_json(&self, pos: ChunkPos, path: &Path) -> io::Result<()> {
+        let Some(chunk) = self.get_chunk(pos) else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("Chunk {:?} is not loaded", pos)));
+        };
+
+        let mut block_counts = BTreeMap::new();
+        let layers = (0..CHUNK_SIZE)
+            .map(|y| {
+                let rows = (0..CHUNK_SIZE)
+                    .map(|z| {
+                        (0..CHUNK_SIZE)
+                            .map(|x| {
+                                let name = chunk.get_voxel(x, y, z).unwrap_or(VoxelType::Air).def().name.as_str();
+                                *block_counts.entry(name).or_insert(0) += 1;
+                                name
+                            })
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    })
+                    .collect::<Vec<_>>();
+
+                serde_json::json!({ "y": y, "rows": rows })
+            })
+            .collect::<Vec<_>>();
+
+        let json = serde_json::json!({
+            "position": [pos.x, pos.y, pos.z],
+            "modified": chunk.is_modified(),
+            "uniform": chunk.is_uniform().map(|voxel| voxel.def().name.as_str()),
+            "memory_usage": chunk.memory_usage(),
+            "block_counts": block_counts,
+            "layers": layers,
+        });
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &json)?;
+        writer.flush()
+    }
+
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty_chunks.extend(self.chunks.keys().map(|pos| (*pos, ALL_SECTIONS)));
+    }
+
+    // Drains the dirty chunks, each with its dirty sections, in sorted order independent of
+    // HashMap iteration order.
+    pub fn take_dirty_chunks(&mut self) -> impl Iterator<Item = (ChunkPos, SectionMask)> + use<> {
+        let mut dirty = self.dirty_chunks.drain().collect::<Vec<_>>();
+        dirty.sort();
+
+        // Edited chunks were unpacked to dense storage. The edits are done by the time they're
+        // meshed, so pack them again before they're handed out.
+        for (pos, _) in &dirty {
+            if let Some(chunk) = self.chunks.get_mut(pos)
+                && chunk.is_dense()
+            {
+                Arc::make_mut(chunk).compress();
+            }
         }
+
+        dirty.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_loaded_voxel_ignores_unloaded_chunks() {
+        let mut world = World::new();
+        world.load_chunk(ChunkPos::new(0, 0, 0));
+
+        assert!(world.set_loaded_voxel(3, 4, 5, VoxelType::Glowstone));
+        assert_eq!(world.get_voxel(3, 4, 5), Some(VoxelType::Glowstone));
+        assert!(world.is_dirty(ChunkPos::new(0, 0, 0)));
+
+        assert!(!world.set_loaded_voxel(3, -4, 5, VoxelType::Stone));
+        assert_eq!(world.get_voxel(3, -4, 5), None);
+        assert_eq!(world.chunk_count(), 1);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        // Not the default seed, so the unsaved chunks only come back the same if it's kept.
+        let mut world = World::with_seed(1234);
+        world.load_region(ChunkPos::new(-1, -1, -1), ChunkPos::new(0, 0, 0));
+        world.set_voxel(3, 5, 7, VoxelType::Stone);
+        world.set_voxel(-1, -1, -1, VoxelType::Air);
+        world.set_voxel(-16, 2, 15, VoxelType::Grass);
+
+        let player = PlayerData {
+            hotbar: vec![VoxelType::Glass, VoxelType::Water, VoxelType::Stone],
+            selected_slot: 1,
+        };
+
+        let path = std::env::temp_dir().join(format!("voxel_world_round_trip_{}.dat", std::process::id()));
+        world.save(&path, Some(&player)).unwrap();
+        let (mut loaded, loaded_player) = World::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded_player, Some(player));
+        assert_eq!(loaded.seed(), 1234);
+
+        // Only the three edited chunks are stored; the rest regenerate.
+        assert_eq!(loaded.chunks.len(), 3);
+        loaded.load_region(ChunkPos::new(-1, -1, -1), ChunkPos::new(0, 0, 0));
+
+        for (pos, chunk) in &world.chunks {
+            let loaded_chunk = loaded.get_chunk(*pos).expect("chunk missing after load");
+            for z in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    for x in 0..CHUNK_SIZE {
+                        assert_eq!(loaded_chunk.get_voxel(x, y, z), chunk.get_voxel(x, y, z));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_stay_packed_after_edits() {
+        let mut dense = Chunk::new();
+        dense.set_voxel(0, 0, 0, VoxelType::Stone);
+        assert!(dense.is_dense());
+        // Light is always stored densely, so packing saves at most the voxel array. Packed
+        // chunks should save at least half of it.
+        let dense_usage = |chunks: usize| chunks * dense.memory_usage();
+        let packed_limit = |chunks: usize| chunks * (dense.memory_usage() - chunk::CHUNK_VOLUME / 2);
+
+        let mut generated = World::new();
+        generated.load_region(ChunkPos::new(-1, -1, -1), ChunkPos::new(0, 0, 0));
+        assert!(generated.memory_usage() < packed_limit(8), "{} bytes for generated terrain", generated.memory_usage());
+
+        let mut world = World::new();
+        world.set_generation_features(GenerationFeatures { noise_terrain: false, ..Default::default() });
+        world.load_region(ChunkPos::new(0, 0, 0), ChunkPos::new(1, 0, 1));
+        world.take_dirty_chunks().for_each(drop);
+        assert!(world.memory_usage() < packed_limit(4), "{} bytes for air", world.memory_usage());
+
+        // A floor across all four chunks unpacks them while it's being built...
+        for x in 0..32 {
+            for z in 0..32 {
+                world.set_voxel(x, 4, z, VoxelType::Stone);
+            }
+        }
+        assert_eq!(world.memory_usage(), dense_usage(4));
+
+        // ...and they're packed again once the edits are handed to meshing.
+        world.take_dirty_chunks().for_each(drop);
+        assert!(world.memory_usage() < packed_limit(4), "{} bytes after edits", world.memory_usage());
+    }
+
+    #[test]
+    fn trees_grow_from_the_ground() {
+        let mut world = World::new();
+        world.load_region(ChunkPos::new(-2, -1, -2), ChunkPos::new(2, 1, 2));
+
+        let size = CHUNK_SIZE as i32;
+        let (min, max) = (-2 * size, 3 * size);
+        let voxel = |x: i32, y: i32, z: i32| world.get_voxel(x, y, z);
+
+        let mut trunks = 0;
+        for z in min..max {
+            for x in min..max {
+                for y in -size..2 * size {
+                    match voxel(x, y, z) {
+                        Some(VoxelType::Wood) if voxel(x, y - 1, z) != Some(VoxelType::Wood) => {
+                            assert_eq!(voxel(x, y - 1, z), Some(VoxelType::Grass), "trunk at ({}, {}, {}) floats", x, y, z);
+                            trunks += 1;
+                        }
+                        // Every leaf hangs off the top of some trunk, including trunks across a
+                        // chunk border. Trunks outside the loaded area can't be checked.
+                        Some(VoxelType::Leaves) if [x, z].iter().all(|c| (min + TREE_CANOPY_RADIUS..max - TREE_CANOPY_RADIUS).contains(c)) => {
+                            let reach = TREE_CANOPY_RADIUS;
+                            let supported = (-reach..=reach).any(|dz| (-reach..=reach).any(|dx| {
+                                (y - 2..=y + 1).any(|ty| voxel(x + dx, ty, z + dz) == Some(VoxelType::Wood))
+                            }));
+                            assert!(supported, "leaves at ({}, {}, {}) float", x, y, z);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        assert!(trunks > 0, "no trees in {} columns", (max - min) * (max - min));
+    }
+
+    #[test]
+    fn editing_a_border_voxel_remeshes_the_neighbor() {
+        let mut world = World::new();
+        world.load_region(ChunkPos::new(-1, 0, 0), ChunkPos::new(1, 0, 0));
+
+        // Swaps a stone block for dirt, which doesn't change the light, so only the edit itself
+        // marks chunks dirty. Returns those chunks.
+        let mut swap_block = |x: i32, y: i32, z: i32| {
+            world.set_voxel(x, y, z, VoxelType::Stone);
+            world.take_dirty_chunks().for_each(drop);
+            world.set_voxel(x, y, z, VoxelType::Dirt);
+            world.take_dirty_chunks().map(|(pos, _)| pos).collect::<Vec<_>>()
+        };
+
+        let last = CHUNK_SIZE as i32 - 1;
+        assert_eq!(swap_block(last, 5, 3), vec![ChunkPos::new(0, 0, 0), ChunkPos::new(1, 0, 0)]);
+        assert_eq!(swap_block(0, 5, 3), vec![ChunkPos::new(-1, 0, 0), ChunkPos::new(0, 0, 0)]);
+
+        // Interior voxels only touch their own chunk, and unloaded neighbors aren't queued.
+        assert_eq!(swap_block(5, 5, 3), vec![ChunkPos::new(0, 0, 0)]);
+        assert_eq!(swap_block(5, 5, 0), vec![ChunkPos::new(0, 0, 0)]);
     }
 
-    pub fn take_dirty_chunks(&mut self) -> impl Iterator<Item = ChunkPos> + '_ {
-        self.dirty_chunks.drain()
+    #[test]
+    fn editing_a_block_dirties_only_the_sections_around_it() {
+        let mut world = World::new();
+        world.load_region(ChunkPos::new(0, -1, 0), ChunkPos::new(0, 1, 0));
+
+        // Like above, a swap that leaves the light alone. Returns the dirty sections.
+        let mut swap_block = |x: i32, y: i32, z: i32| {
+            world.set_voxel(x, y, z, VoxelType::Stone);
+            world.take_dirty_chunks().for_each(drop);
+            world.set_voxel(x, y, z, VoxelType::Dirt);
+            world.take_dirty_chunks().collect::<Vec<_>>()
+        };
+
+        let (below, here) = (ChunkPos::new(0, -1, 0), ChunkPos::new(0, 0, 0));
+        let top = 1 << (chunk::SECTIONS_PER_CHUNK - 1);
+        let boundary = chunk::SECTION_HEIGHT as i32;
+
+        assert_eq!(swap_block(5, 3, 5), vec![(here, 1)]);
+        // Next to a section boundary, the faces of the section across it may change too.
+        assert_eq!(swap_block(5, boundary - 1, 5), vec![(here, 0b11)]);
+        assert_eq!(swap_block(5, boundary, 5), vec![(here, 0b11)]);
+        // At the bottom of a chunk, only the top section of the chunk below.
+        assert_eq!(swap_block(5, 0, 5), vec![(below, top), (here, 1)]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn set_get_round_trip_at_chunk_edges() {
+        const COORDS: [i32; 6] = [-17, -16, -1, 0, 15, 16];
+        const BLOCKS: [VoxelType; 4] = [VoxelType::Stone, VoxelType::Dirt, VoxelType::Grass, VoxelType::Glass];
+
+        // Neighboring coordinates get different blocks, so a write landing one voxel off shows up.
+        let block_at = |x: i32, y: i32, z: i32| BLOCKS[(x + 2 * y + 3 * z).rem_euclid(4) as usize];
+
+        let mut world = World::new();
+        for x in COORDS {
+            for y in COORDS {
+                for z in COORDS {
+                    world.set_voxel(x, y, z, block_at(x, y, z));
+                }
+            }
+        }
+
+        let size = CHUNK_SIZE as i32;
+        for x in COORDS {
+            for y in COORDS {
+                for z in COORDS {
+                    assert_eq!(world.get_voxel(x, y, z), Some(block_at(x, y, z)), "at ({}, {}, {})", x, y, z);
+
+                    // The voxel has to be stored in the chunk and slot the coordinates belong to.
+                    let chunk = world.get_chunk(ChunkPos::new(x.div_euclid(size), y.div_euclid(size), z.div_euclid(size)))
+                        .expect("edited chunk not loaded");
+                    let local = (x.rem_euclid(size) as usize, y.rem_euclid(size) as usize, z.rem_euclid(size) as usize);
+                    assert_eq!(chunk.get_voxel(local.0, local.1, local.2), Some(block_at(x, y, z)), "at ({}, {}, {})", x, y, z);
+                }
+            }
+        }
+    }
+}
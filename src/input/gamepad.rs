@@ -0,0 +1,74 @@
+use gilrs::{Axis, Button, EventType, Gilrs, GilrsBuilder};
+
+// What a gamepad's face buttons do.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum GamepadAction {
+    Jump,
+    Break,
+    Place,
+}
+
+impl GamepadAction {
+    fn from_button(button: Button) -> Option<Self> {
+        match button {
+            Button::South => Some(GamepadAction::Jump),
+            Button::West => Some(GamepadAction::Break),
+            Button::East => Some(GamepadAction::Place),
+            _ => None,
+        }
+    }
+}
+
+// Every connected gamepad, read as one: their sticks add up and any of them can press a button.
+pub struct Gamepads {
+    gilrs: Gilrs,
+}
+
+impl Gamepads {
+    // `None` if the platform's gamepad support failed to start, in which case there just are
+    // no gamepads.
+    pub fn new() -> Option<Self> {
+        // gilrs's filters include its own deadzones; the controller applies the configured ones.
+        match GilrsBuilder::new().with_default_filters(false).build() {
+            Ok(gilrs) => {
+                for (_, gamepad) in gilrs.gamepads() {
+                    log::info!("Gamepad connected: {}", gamepad.name());
+                }
+                Some(Self { gilrs })
+            }
+            Err(e) => {
+                log::warn!("Gamepad support unavailable: {}", e);
+                None
+            }
+        }
+    }
+
+    // Takes the button presses and releases since the last poll, in order.
+    pub fn poll(&mut self) -> Vec<(GamepadAction, bool)> {
+        let mut actions = Vec::new();
+
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::ButtonPressed(button, _) => actions.extend(GamepadAction::from_button(button).map(|action| (action, true))),
+                EventType::ButtonReleased(button, _) => actions.extend(GamepadAction::from_button(button).map(|action| (action, false))),
+                EventType::Connected => log::info!("Gamepad connected: {}", self.gilrs.gamepad(event.id).name()),
+                EventType::Disconnected => log::info!("Gamepad disconnected: {}", self.gilrs.gamepad(event.id).name()),
+                _ => {}
+            }
+        }
+
+        actions
+    }
+
+    // The left and right sticks as (right, up), summed over all gamepads.
+    pub fn sticks(&self) -> ((f32, f32), (f32, f32)) {
+        let mut sticks = ((0.0, 0.0), (0.0, 0.0));
+        for (_, gamepad) in self.gilrs.gamepads() {
+            sticks.0.0 += gamepad.value(Axis::LeftStickX);
+            sticks.0.1 += gamepad.value(Axis::LeftStickY);
+            sticks.1.0 += gamepad.value(Axis::RightStickX);
+            sticks.1.1 += gamepad.value(Axis::RightStickY);
+        }
+        sticks
+    }
+}
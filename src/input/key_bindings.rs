@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use winit::keyboard::KeyCode;
+
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Jump,
+    Sneak,
+    Sprint,
+    Zoom,
+}
+
+// Which keys trigger each movement action (and zooming). An action can have several keys
+// (e.g. W and the up arrow), but each key drives at most one action.
+#[derive(Clone, Debug)]
+pub struct KeyBindings {
+    keys: HashMap<KeyCode, Action>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            keys: HashMap::from([
+                (KeyCode::KeyW, Action::Forward),
+                (KeyCode::ArrowUp, Action::Forward),
+                (KeyCode::KeyS, Action::Back),
+                (KeyCode::ArrowDown, Action::Back),
+                (KeyCode::KeyA, Action::Left),
+                (KeyCode::ArrowLeft, Action::Left),
+                (KeyCode::KeyD, Action::Right),
+                (KeyCode::ArrowRight, Action::Right),
+                (KeyCode::Space, Action::Jump),
+                (KeyCode::ShiftLeft, Action::Sneak),
+                (KeyCode::ControlLeft, Action::Sprint),
+                (KeyCode::KeyZ, Action::Zoom),
+            ]),
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        self.keys.get(&key).copied()
+    }
+
+    // Makes `key` the only key for `action`, taking it away from whatever it was bound to.
+    pub fn rebind(&mut self, action: Action, key: KeyCode) {
+        self.keys.retain(|_, bound| *bound != action);
+        self.keys.insert(key, action);
+    }
+}
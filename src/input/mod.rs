@@ -1 +1,4 @@
-pub mod player_controller;
\ No newline at end of file
+#[cfg(feature = "gamepad")]
+pub mod gamepad;
+pub mod key_bindings;
+pub mod player_controller;
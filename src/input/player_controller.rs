@@ -1,81 +1,277 @@
 use cgmath::{InnerSpace, Zero};
+use serde::{Deserialize, Serialize};
 use winit::keyboard::KeyCode;
 use crate::game::camera::Camera;
 use crate::game::player::Player;
+use crate::game::world::World;
+use crate::input::key_bindings::{Action, KeyBindings};
+use crate::rendering::projection::Projection;
 
 const JUMP_STRENGTH: f32 = 9.0;
+// Seconds after walking off a ledge during which a jump still works.
+const COYOTE_TIME: f32 = 0.15;
+// Seconds a jump pressed in mid-air is remembered, so it happens on landing.
+const JUMP_BUFFER_TIME: f32 = 0.15;
 const MOVE_SPEED: f32 = 5.0;
+const GROUND_ACCEL: f32 = 50.0;
+const AIR_ACCEL: f32 = 10.0;
+const FLY_VERTICAL_SPEED: f32 = 5.0;
+// Upward speed while holding jump in water, in m/s.
+const SWIM_UP_SPEED: f32 = 4.0;
+// Fraction of the normal movement speed in water.
+const WATER_SPEED_MULTIPLIER: f32 = 0.5;
+const SPRINT_MULTIPLIER: f32 = 1.6;
+const SPRINT_FOV_DELTA: f32 = 10.0;
+// How quickly the FOV eases towards its target, in 1/s.
+const FOV_EASE_RATE: f32 = 8.0;
+// FOV while the zoom key is held, in degrees.
+const ZOOM_FOV: f32 = 20.0;
+const MAX_PITCH_DEGREES: f32 = 89.0;
+
+// How gamepad sticks are read.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GamepadSettings {
+    // Stick tilt (0-1) below which the stick counts as centered, so worn sticks don't drift.
+    pub move_deadzone: f32,
+    pub look_deadzone: f32,
+    // How fast a fully tilted look stick turns, in mouse pixels per second.
+    pub look_speed: f32,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        Self {
+            move_deadzone: 0.15,
+            look_deadzone: 0.15,
+            look_speed: 800.0,
+        }
+    }
+}
 
 pub struct PlayerController {
     // Keyboard input.
+    key_bindings: KeyBindings,
     is_forward_pressed: bool,
     is_backward_pressed: bool,
     is_left_pressed: bool,
     is_right_pressed: bool,
     is_up_pressed: bool,
     is_down_pressed: bool,
+    is_sprint_pressed: bool,
+    is_zoom_pressed: bool,
+
+    // Gamepad sticks as (right, up), each -1 to 1, before deadzones. They work alongside the
+    // keyboard and mouse.
+    pub move_stick: (f32, f32),
+    pub look_stick: (f32, f32),
+    pub gamepad: GamepadSettings,
+
+    // Seconds since the player was last on the ground; infinite once they've jumped, so the
+    // coyote time can't be used for a second jump.
+    time_since_grounded: f32,
+    // Seconds left before a buffered jump press expires.
+    jump_buffer_timer: f32,
 
     // Mouse input.
-    mouse_sensitivity: f32,
+    pub mouse_sensitivity: f32,
+    // Multipliers on `mouse_sensitivity` for turning left/right and looking up/down.
+    pub sensitivity_x: f32,
+    pub sensitivity_y: f32,
+    // Moving the mouse forward looks down instead of up.
+    pub invert_y: bool,
+    // Pitch is clamped to ±`max_pitch` radians unless `free_pitch` allows looking all the way
+    // around, as spectators can.
+    pub max_pitch: f32,
+    pub free_pitch: bool,
+    // Seconds over which mouse movement is eased into the look, damping jitter; 0 applies it
+    // raw. About two thirds of a movement is applied within this time.
+    pub mouse_smoothing: f32,
+    // Mouse movement not yet applied to the look while smoothing, in pixels.
     pub mouse_delta: (f32, f32),
-    is_mouse_captured: bool,
+
+    // How quickly horizontal velocity approaches the desired velocity, in m/s².
+    pub ground_accel: f32,
+    pub air_accel: f32,
+
+    pub move_speed: f32,
+    pub sprint_multiplier: f32,
+    // Degrees added to the projection's base FOV while sprinting.
+    pub sprint_fov_delta: f32,
+    sprinting: bool,
+    // Current FOV over the base FOV, capped at 1. Mouse look is scaled by it so aiming while
+    // zoomed in isn't twitchy.
+    fov_scale: f32,
+
+    // Jump automatically when walking into a one-block step.
+    pub auto_jump: bool,
 }
 
 impl PlayerController {
     pub fn new(mouse_sensitivity: f32) -> Self {
         Self {
+            key_bindings: KeyBindings::default(),
             is_forward_pressed: false,
             is_backward_pressed: false,
             is_left_pressed: false,
             is_right_pressed: false,
             is_up_pressed: false,
             is_down_pressed: false,
+            is_sprint_pressed: false,
+            is_zoom_pressed: false,
+            move_stick: (0.0, 0.0),
+            look_stick: (0.0, 0.0),
+            gamepad: GamepadSettings::default(),
+            time_since_grounded: f32::INFINITY,
+            jump_buffer_timer: 0.0,
             mouse_sensitivity,
+            sensitivity_x: 1.0,
+            sensitivity_y: 1.0,
+            invert_y: false,
+            max_pitch: MAX_PITCH_DEGREES.to_radians(),
+            free_pitch: false,
+            mouse_smoothing: 0.0,
             mouse_delta: (0.0, 0.0),
-            is_mouse_captured: true,
+            ground_accel: GROUND_ACCEL,
+            air_accel: AIR_ACCEL,
+            move_speed: MOVE_SPEED,
+            sprint_multiplier: SPRINT_MULTIPLIER,
+            sprint_fov_delta: SPRINT_FOV_DELTA,
+            sprinting: false,
+            fov_scale: 1.0,
+            auto_jump: false,
         }
     }
 
+    pub fn key_bindings(&self) -> &KeyBindings {
+        &self.key_bindings
+    }
+
+    pub fn rebind(&mut self, action: Action, key: KeyCode) {
+        self.key_bindings.rebind(action, key);
+    }
+
     pub fn handle_key(&mut self, code: KeyCode, is_pressed: bool) -> bool {
-        match code {
-            KeyCode::KeyW | KeyCode::ArrowUp => {
-                self.is_forward_pressed = is_pressed;
-                true
-            }
-            KeyCode::KeyA | KeyCode::ArrowLeft => {
-                self.is_left_pressed = is_pressed;
-                true
-            }
-            KeyCode::KeyS | KeyCode::ArrowDown => {
-                self.is_backward_pressed = is_pressed;
-                true
-            }
-            KeyCode::KeyD | KeyCode::ArrowRight => {
-                self.is_right_pressed = is_pressed;
-                true
-            }
-            KeyCode::Space => {
-                self.is_up_pressed = is_pressed;
-                true
-            }
-            KeyCode::ShiftLeft => {
-                self.is_down_pressed = is_pressed;
-                true
-            }
-            _ => false,
+        let Some(action) = self.key_bindings.action(code) else {
+            return false;
+        };
+
+        self.handle_action(action, is_pressed);
+        true
+    }
+
+    // Presses or releases an action directly, as gamepad buttons do.
+    pub fn handle_action(&mut self, action: Action, is_pressed: bool) {
+        if action == Action::Jump && is_pressed && !self.is_up_pressed {
+            self.jump_buffer_timer = JUMP_BUFFER_TIME;
+        }
+
+        let state = match action {
+            Action::Forward => &mut self.is_forward_pressed,
+            Action::Back => &mut self.is_backward_pressed,
+            Action::Left => &mut self.is_left_pressed,
+            Action::Right => &mut self.is_right_pressed,
+            Action::Jump => &mut self.is_up_pressed,
+            Action::Sneak => &mut self.is_down_pressed,
+            Action::Sprint => &mut self.is_sprint_pressed,
+            Action::Zoom => &mut self.is_zoom_pressed,
+        };
+        *state = is_pressed;
+    }
+
+    // The move stick's tilt past the deadzone, rescaled so it still reaches full speed.
+    fn stick_movement(&self) -> (f32, f32) {
+        apply_deadzone(self.move_stick, self.gamepad.move_deadzone)
+    }
+
+    // Moves a detached camera directly: WASD along the view direction, Space/Shift up and down.
+    pub fn fly_camera(&self, camera: &mut Camera, dt: f32) {
+        let mut move_direction = cgmath::Vector3::zero();
+
+        if self.is_forward_pressed {
+            move_direction += camera.get_direction();
+        }
+        if self.is_backward_pressed {
+            move_direction -= camera.get_direction();
+        }
+        if self.is_right_pressed {
+            move_direction += camera.get_right();
+        }
+        if self.is_left_pressed {
+            move_direction -= camera.get_right();
+        }
+        if self.is_up_pressed {
+            move_direction += cgmath::Vector3::unit_y();
+        }
+        if self.is_down_pressed {
+            move_direction -= cgmath::Vector3::unit_y();
+        }
+        let (stick_right, stick_forward) = self.stick_movement();
+        move_direction += camera.get_direction() * stick_forward + camera.get_right() * stick_right;
+
+        if !move_direction.is_zero() {
+            let speed = if self.is_sprint_pressed { self.move_speed * self.sprint_multiplier } else { self.move_speed };
+            camera.position += clamp_length(move_direction) * speed * dt;
         }
     }
 
+    // Turns the camera right away without smoothing; otherwise `update_look` applies the
+    // movement over the next frames.
     pub fn handle_mouse(&mut self, delta_x: f64, delta_y: f64, camera: &mut Camera) {
+        if self.mouse_smoothing > 0.0 {
+            self.mouse_delta.0 += delta_x as f32;
+            self.mouse_delta.1 += delta_y as f32;
+        } else {
+            self.turn(delta_x as f32, delta_y as f32, camera);
+        }
+    }
+
+    // Applies part of the smoothed mouse movement, decaying exponentially so the result
+    // doesn't depend on the frame rate. The whole movement is applied eventually, so fast
+    // flicks turn as far as they would raw.
+    pub fn update_look(&mut self, camera: &mut Camera, dt: f32) {
+        // The look stick is already smooth, so it turns the camera like mouse movement but
+        // bypasses the smoothing.
+        let (look_x, look_y) = apply_deadzone(self.look_stick, self.gamepad.look_deadzone);
+        if (look_x, look_y) != (0.0, 0.0) {
+            let speed = self.gamepad.look_speed * dt;
+            // Stick up is positive, mouse up negative.
+            self.turn(look_x * speed, -look_y * speed, camera);
+        }
+
+        if self.mouse_smoothing <= 0.0 {
+            // Smoothing was just turned off.
+            let (delta_x, delta_y) = std::mem::take(&mut self.mouse_delta);
+            self.turn(delta_x, delta_y, camera);
+            return;
+        }
+
+        let t = 1.0 - (-dt / self.mouse_smoothing).exp();
+        let (delta_x, delta_y) = (self.mouse_delta.0 * t, self.mouse_delta.1 * t);
+        self.mouse_delta.0 -= delta_x;
+        self.mouse_delta.1 -= delta_y;
+        self.turn(delta_x, delta_y, camera);
+    }
+
+    fn turn(&self, delta_x: f32, delta_y: f32, camera: &mut Camera) {
+        let sensitivity = self.mouse_sensitivity * self.fov_scale;
+
         // Update yaw (horizontal rotation)
-        camera.yaw += delta_x as f32 * self.mouse_sensitivity;
+        camera.yaw += delta_x * sensitivity * self.sensitivity_x;
 
-        // Update pitch (vertical rotation) with clamping
-        camera.pitch -= delta_y as f32 * self.mouse_sensitivity;
-        camera.pitch = camera.pitch.clamp(-89.0_f32.to_radians(), 89.0_f32.to_radians());
+        // Update pitch (vertical rotation) with clamping. Mouse y grows downwards.
+        let delta_pitch = delta_y * sensitivity * self.sensitivity_y;
+        camera.pitch += if self.invert_y { delta_pitch } else { -delta_pitch };
+        camera.pitch = if self.free_pitch {
+            // Keep the angle in (-π, π] so switching back to clamped snaps to the nearer limit.
+            let wrapped = camera.pitch.rem_euclid(std::f32::consts::TAU);
+            if wrapped > std::f32::consts::PI { wrapped - std::f32::consts::TAU } else { wrapped }
+        } else {
+            camera.pitch.clamp(-self.max_pitch, self.max_pitch)
+        };
     }
 
-    pub fn update_velocity(&self, player: &mut Player, camera: &mut Camera, dt: f32) {
+    pub fn update_velocity(&mut self, player: &mut Player, camera: &mut Camera, world: &World, dt: f32) {
         let mut move_direction = cgmath::Vector3::zero();
 
         if self.is_forward_pressed {
@@ -90,20 +286,291 @@ impl PlayerController {
         if self.is_left_pressed {
             move_direction -= camera.get_right();
         }
+        let (stick_right, stick_forward) = self.stick_movement();
+        move_direction += camera.get_forward_horizontal() * stick_forward + camera.get_right() * stick_right;
+
+        // Sprinting has to start on the ground but carries on through jumps while held.
+        self.sprinting = self.is_sprint_pressed
+            && (self.is_forward_pressed || stick_forward > 0.0)
+            && (self.sprinting || player.is_on_ground || player.flying);
+
+        let mut speed = if self.sprinting { self.move_speed * self.sprint_multiplier } else { self.move_speed };
+        if player.is_in_water() && !player.flying {
+            speed *= WATER_SPEED_MULTIPLIER;
+        }
 
-        let horizontal_velocity = if !move_direction.is_zero() {
-            move_direction.normalize() * MOVE_SPEED
+        // Clamped so pressing two directions at once isn't faster than one. A partly tilted
+        // stick walks slower.
+        let target_velocity = clamp_length(move_direction) * speed;
+
+        let accel = if player.is_on_ground || player.flying { self.ground_accel } else { self.air_accel };
+        let current_velocity = cgmath::Vector3::new(player.velocity.x, 0.0, player.velocity.z);
+        let velocity_change = target_velocity - current_velocity;
+        let max_change = accel * dt;
+
+        let horizontal_velocity = if velocity_change.magnitude() <= max_change {
+            target_velocity
         } else {
-            cgmath::Vector3::zero()
+            current_velocity + velocity_change.normalize() * max_change
         };
 
+        let wants_step_jump = self.auto_jump
+            && !move_direction.is_zero()
+            && player.is_facing_step(world, move_direction);
+
+        if player.is_on_ground {
+            self.time_since_grounded = 0.0;
+        } else {
+            self.time_since_grounded += dt;
+        }
+        let can_jump = self.time_since_grounded <= COYOTE_TIME;
+        let wants_jump = self.is_up_pressed || self.jump_buffer_timer > 0.0 || wants_step_jump;
+        self.jump_buffer_timer = (self.jump_buffer_timer - dt).max(0.0);
+
         let mut vertical_velocity = player.velocity.y;
-        if self.is_up_pressed && player.is_on_ground {
+        if player.flying {
+            // Space and Shift move straight up and down instead of jumping.
+            vertical_velocity = match (self.is_up_pressed, self.is_down_pressed) {
+                (true, false) => FLY_VERTICAL_SPEED,
+                (false, true) => -FLY_VERTICAL_SPEED,
+                _ => 0.0,
+            };
+        } else if player.is_in_water() && self.is_up_pressed {
+            vertical_velocity = vertical_velocity.max(SWIM_UP_SPEED);
+        } else if wants_jump && can_jump {
             vertical_velocity = JUMP_STRENGTH;
+            self.time_since_grounded = f32::INFINITY;
+            self.jump_buffer_timer = 0.0;
         }
 
         player.velocity.x = horizontal_velocity.x;
         player.velocity.y = vertical_velocity;
         player.velocity.z = horizontal_velocity.z;
     }
+
+    // Eases the FOV in while zooming, out while sprinting, and back to the base FOV afterwards.
+    pub fn update_fov(&mut self, projection: &mut Projection, dt: f32) {
+        let target = if self.is_zoom_pressed {
+            ZOOM_FOV.min(projection.base_fovy)
+        } else if self.sprinting {
+            projection.base_fovy + self.sprint_fov_delta
+        } else {
+            projection.base_fovy
+        };
+
+        if (target - projection.fovy).abs() < 0.01 {
+            projection.fovy = target;
+        } else {
+            let t = (FOV_EASE_RATE * dt).min(1.0);
+            projection.fovy += (target - projection.fovy) * t;
+        }
+
+        self.fov_scale = (projection.fovy / projection.base_fovy).min(1.0);
+    }
+}
+
+// Scales `v` down to length 1 if it's longer.
+fn clamp_length(v: cgmath::Vector3<f32>) -> cgmath::Vector3<f32> {
+    if v.magnitude2() > 1.0 { v.normalize() } else { v }
+}
+
+// Zeroes a stick inside the deadzone and stretches the rest of its range back over 0-1, so the
+// stick doesn't jump from nothing to `deadzone` as it leaves it. Tilt beyond 1 (some sticks
+// reach the corners) is clamped.
+fn apply_deadzone((x, y): (f32, f32), deadzone: f32) -> (f32, f32) {
+    let tilt = x.hypot(y);
+    if tilt <= deadzone || deadzone >= 1.0 {
+        return (0.0, 0.0);
+    }
+
+    let scale = ((tilt - deadzone) / (1.0 - deadzone)).min(1.0) / tilt;
+    (x * scale, y * scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebound_forward_key_moves_forward() {
+        let mut controller = PlayerController::new(0.003);
+        controller.rebind(Action::Forward, KeyCode::KeyK);
+
+        assert!(!controller.handle_key(KeyCode::KeyW, true));
+        assert!(controller.handle_key(KeyCode::KeyK, true));
+
+        let world = World::new();
+        let mut player = Player::new((0.0, 0.0, 0.0).into());
+        let mut camera = Camera::new((0.0, 0.0, 0.0).into(), 0.0, 0.0);
+
+        controller.update_velocity(&mut player, &mut camera, &world, 0.1);
+
+        // Yaw 0 faces +x.
+        assert!(player.velocity.x > 0.0);
+        assert_eq!(player.velocity.z, 0.0);
+    }
+
+    // Horizontal speed after each of `steps` updates with the given movement keys held.
+    fn horizontal_speeds(keys: &[KeyCode], steps: usize) -> Vec<f32> {
+        let mut controller = PlayerController::new(0.003);
+        for key in keys {
+            controller.handle_key(*key, true);
+        }
+
+        let world = World::new();
+        let mut player = Player::new((0.0, 0.0, 0.0).into());
+        let mut camera = Camera::new((0.0, 0.0, 0.0).into(), 0.3, 0.0);
+
+        (0..steps)
+            .map(|_| {
+                controller.update_velocity(&mut player, &mut camera, &world, 0.05);
+                cgmath::Vector3::new(player.velocity.x, 0.0, player.velocity.z).magnitude()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diagonal_movement_is_not_faster() {
+        let forward = horizontal_speeds(&[KeyCode::KeyW], 200);
+        let diagonal = horizontal_speeds(&[KeyCode::KeyW, KeyCode::KeyD], 200);
+
+        // Both while accelerating and once at full speed.
+        for (forward, diagonal) in forward.iter().zip(&diagonal) {
+            assert!((forward - diagonal).abs() < 1e-4, "forward {} vs diagonal {}", forward, diagonal);
+        }
+        assert!((forward.last().unwrap() - MOVE_SPEED).abs() < 1e-4);
+    }
+
+    #[test]
+    fn stick_tilt_sets_walking_speed() {
+        let speed_with_stick = |stick: (f32, f32)| {
+            let mut controller = PlayerController::new(0.003);
+            controller.move_stick = stick;
+            let world = World::new();
+            let mut player = Player::new((0.0, 0.0, 0.0).into());
+            let mut camera = Camera::new((0.0, 0.0, 0.0).into(), 0.0, 0.0);
+            for _ in 0..100 {
+                controller.update_velocity(&mut player, &mut camera, &world, 0.05);
+            }
+            // Yaw 0 faces +x, so forward is +x and right is +z.
+            (player.velocity.x, player.velocity.z)
+        };
+
+        assert_eq!(speed_with_stick((0.0, 0.1)), (0.0, 0.0));
+        let (forward, _) = speed_with_stick((0.0, 1.0));
+        assert!((forward - MOVE_SPEED).abs() < 1e-4);
+        let (forward, _) = speed_with_stick((0.0, 0.5));
+        assert!(forward > 0.0 && forward < MOVE_SPEED * 0.5);
+        let (forward, right) = speed_with_stick((1.0, 1.0));
+        assert!((forward.hypot(right) - MOVE_SPEED).abs() < 1e-4);
+    }
+
+    // Steps `seconds` in 10 ms updates with the player on the ground or in the air, and returns
+    // whether any of them jumped.
+    fn step(controller: &mut PlayerController, player: &mut Player, on_ground: bool, seconds: f32) -> bool {
+        let world = World::new();
+        let mut camera = Camera::new((0.0, 0.0, 0.0).into(), 0.0, 0.0);
+        let mut jumped = false;
+
+        for _ in 0..(seconds / 0.01).round() as usize {
+            player.is_on_ground = on_ground;
+            player.velocity.y = 0.0;
+            controller.update_velocity(player, &mut camera, &world, 0.01);
+            jumped |= player.velocity.y == JUMP_STRENGTH;
+        }
+        jumped
+    }
+
+    fn press_jump(controller: &mut PlayerController) {
+        controller.handle_key(KeyCode::Space, true);
+        controller.handle_key(KeyCode::Space, false);
+    }
+
+    #[test]
+    fn jump_shortly_after_leaving_ground_succeeds() {
+        let mut controller = PlayerController::new(0.003);
+        let mut player = Player::new((0.0, 0.0, 0.0).into());
+
+        step(&mut controller, &mut player, true, 0.1);
+        assert!(!step(&mut controller, &mut player, false, 0.08));
+        press_jump(&mut controller);
+        assert!(step(&mut controller, &mut player, false, 0.01));
+
+        // The coyote time doesn't allow a second jump in mid-air.
+        press_jump(&mut controller);
+        assert!(!step(&mut controller, &mut player, false, 0.01));
+    }
+
+    #[test]
+    fn jump_long_after_leaving_ground_fails() {
+        let mut controller = PlayerController::new(0.003);
+        let mut player = Player::new((0.0, 0.0, 0.0).into());
+
+        step(&mut controller, &mut player, true, 0.1);
+        step(&mut controller, &mut player, false, 0.3);
+        press_jump(&mut controller);
+        assert!(!step(&mut controller, &mut player, false, 0.01));
+    }
+
+    #[test]
+    fn jump_shortly_before_landing_is_buffered() {
+        let mut controller = PlayerController::new(0.003);
+        let mut player = Player::new((0.0, 0.0, 0.0).into());
+
+        press_jump(&mut controller);
+        assert!(!step(&mut controller, &mut player, false, 0.08));
+        assert!(step(&mut controller, &mut player, true, 0.01));
+    }
+
+    #[test]
+    fn smoothed_look_catches_up_with_raw_look() {
+        let mut raw = PlayerController::new(0.003);
+        let mut smoothed = PlayerController::new(0.003);
+        smoothed.mouse_smoothing = 0.05;
+        let mut raw_camera = Camera::new((0.0, 0.0, 0.0).into(), 0.0, 0.0);
+        let mut smoothed_camera = Camera::new((0.0, 0.0, 0.0).into(), 0.0, 0.0);
+
+        raw.handle_mouse(200.0, -50.0, &mut raw_camera);
+        smoothed.handle_mouse(200.0, -50.0, &mut smoothed_camera);
+        smoothed.update_look(&mut smoothed_camera, 0.01);
+        assert!(smoothed_camera.yaw > 0.0 && smoothed_camera.yaw < raw_camera.yaw);
+
+        for _ in 0..100 {
+            smoothed.update_look(&mut smoothed_camera, 0.01);
+        }
+        assert!((smoothed_camera.yaw - raw_camera.yaw).abs() < 1e-4);
+        assert!((smoothed_camera.pitch - raw_camera.pitch).abs() < 1e-4);
+    }
+
+    #[test]
+    fn inverted_y_looks_up_when_moving_the_mouse_down() {
+        let mut controller = PlayerController::new(0.003);
+        controller.invert_y = true;
+        let mut camera = Camera::new((0.0, 0.0, 0.0).into(), 0.0, 0.0);
+
+        controller.handle_mouse(0.0, 100.0, &mut camera);
+        assert!(camera.pitch > 0.0);
+
+        // Still clamped.
+        controller.handle_mouse(0.0, 1e6, &mut camera);
+        assert_eq!(camera.pitch, controller.max_pitch);
+
+        controller.invert_y = false;
+        controller.sensitivity_x = 2.0;
+        controller.sensitivity_y = 0.5;
+        let mut camera = Camera::new((0.0, 0.0, 0.0).into(), 0.0, 0.0);
+        controller.handle_mouse(100.0, 100.0, &mut camera);
+        assert!(camera.pitch < 0.0);
+        assert!((camera.yaw + 4.0 * camera.pitch).abs() < 1e-6);
+    }
+
+    #[test]
+    fn jump_long_before_landing_expires() {
+        let mut controller = PlayerController::new(0.003);
+        let mut player = Player::new((0.0, 0.0, 0.0).into());
+
+        press_jump(&mut controller);
+        step(&mut controller, &mut player, false, 0.3);
+        assert!(!step(&mut controller, &mut player, true, 0.01));
+    }
 }
@@ -1,69 +1,182 @@
 pub mod app;
+mod console;
 mod game;
 mod input;
 mod rendering;
+mod settings;
 mod ui;
 
+use std::collections::VecDeque;
+use std::path::Path;
 use std::sync::Arc;
 use egui_wgpu::ScreenDescriptor;
 use wgpu::util::DeviceExt;
-use winit::{event_loop::ActiveEventLoop, event::DeviceEvent, keyboard::KeyCode, window::Window};
+use winit::{event_loop::ActiveEventLoop, event::{DeviceEvent, WindowEvent}, keyboard::KeyCode, window::Window};
 use winit::window::CursorGrabMode;
 use game::camera::Camera;
-use game::chunk::ChunkPos;
-use game::world::World;
+use game::chunk::{ChunkPos, CHUNK_SIZE};
+use game::world::{PlayerData, World};
 
 use input::player_controller::PlayerController;
+#[cfg(feature = "gamepad")]
+use input::{gamepad::{GamepadAction, Gamepads}, key_bindings::Action};
 
-use rendering::projection::Projection;
+use rendering::projection::{Projection, ViewProjectionCache};
+use crate::game::block_sound::block_sound_pitch;
+use crate::game::break_repeat::BreakRepeat;
+use crate::game::fixed_timestep::FixedTimestep;
+use crate::game::brush::MAX_BRUSH_RADIUS;
 use crate::game::chunk::VoxelType;
-use crate::game::player::Player;
-use crate::game::{raycast_voxel, RaycastHit};
-use crate::rendering::geometry_renderer::GeometryRenderer;
+use crate::game::game_mode::GameMode;
+use crate::game::player::{Player, MAX_HEALTH};
+use crate::game::time_of_day::TimeOfDay;
+use crate::game::{raycast_voxel_where, RaycastHit};
+use crate::rendering::GeometryRenderer;
+use crate::rendering::geometry_renderer::PipelineOptions;
+use crate::rendering::frustum::Frustum;
 use crate::rendering::gpu_context::GpuContext;
+use crate::rendering::lighting::{BlockHighlight, Fog, Lighting, PointLight};
+use crate::rendering::texture::{RenderTargetDesc, Texture};
+use crate::rendering::texture_atlas::TextureAtlas;
+use crate::rendering::outline_renderer::OutlineBox;
 use crate::rendering::SharedResources;
 use crate::ui::debug_ui::DebugUi;
+use crate::settings::{Settings, MAX_RENDER_DISTANCE, SETTINGS_PATH};
+use crate::ui::crosshair::Crosshair;
 use crate::ui::panels;
+use crate::console::{Command, Console};
 
-pub struct State {
-    // GPU Resources
+const WORLD_SAVE_PATH: &str = "world.dat";
+// Number of recent frames the FPS readout is averaged over.
+const FRAME_TIME_WINDOW: usize = 60;
+
+// Physics runs in steps this long, and catches up on at most `MAX_STEPS_PER_FRAME` steps a frame.
+const PHYSICS_STEP: f32 = 1.0 / 60.0;
+const MAX_STEPS_PER_FRAME: u32 = 6;
+
+// Height of the camera above the player's center.
+const EYE_HEIGHT: f32 = 0.8;
+// How far away blocks can be targeted.
+const REACH_DISTANCE: f32 = 5.0;
+// Warm, like a torch.
+const HELD_LIGHT_COLOR: [f32; 3] = [1.0, 0.85, 0.6];
+// Seconds between blocks broken while holding the break button in creative.
+const CREATIVE_BREAK_REPEAT: f32 = 0.25;
+const SPAWN_REGION: (ChunkPos, ChunkPos) = (ChunkPos { x: 0, y: -1, z: 0 }, ChunkPos { x: 0, y: 1, z: 0 });
+
+// A window's swapchain, along with the UI drawn over it.
+struct WindowTarget {
     window: Arc<Window>,
     surface: wgpu::Surface<'static>,
-    gpu_context: GpuContext,
     config: wgpu::SurfaceConfiguration,
+    is_surface_configured: bool,
+    debug_ui: DebugUi,
+    atlas_texture_id: egui::TextureId,
+}
+
+// Where frames are drawn. Headless states render into a texture, without any UI.
+enum RenderTarget {
+    Window(Box<WindowTarget>),
+    Offscreen(Texture),
+}
+
+impl RenderTarget {
+    fn desc(&self) -> RenderTargetDesc {
+        match self {
+            RenderTarget::Window(target) => RenderTargetDesc::from(&target.config),
+            RenderTarget::Offscreen(texture) => RenderTargetDesc {
+                format: texture.texture.format(),
+                width: texture.texture.width(),
+                height: texture.texture.height(),
+            },
+        }
+    }
+}
+
+pub struct State {
+    // GPU Resources
+    target: RenderTarget,
+    gpu_context: GpuContext,
 
     // Game State
     world: World,
+    // Chunks loaded and drawn around the player in each horizontal direction.
+    render_distance: i32,
+    // The player's chunk when chunks were last loaded around them.
+    loaded_center: Option<ChunkPos>,
     player: Player,
     camera: Camera,
+    // While detached the camera flies freely and the player stands still.
+    camera_detached: bool,
+    // The player's view when the camera was detached, outlined while `show_frustum` is on.
+    player_frustum: Option<Frustum>,
+    show_frustum: bool,
+    // The player's last targeting ray, from its origin to the hit or the end of reach. Drawn
+    // while `show_debug_ray` is on, and frozen while the camera is detached so it can be
+    // inspected from outside.
+    debug_ray: Option<[cgmath::Point3<f32>; 2]>,
+    show_debug_ray: bool,
     selected_block: Option<RaycastHit>,
-    held_block_type: VoxelType,
+    // Voxel currently being broken and how long the break button has been held on it.
+    break_progress: Option<((i32, i32, i32), f32)>,
+    game_mode: GameMode,
+    // Paces breaking while the break button is held in creative.
+    creative_break_repeat: BreakRepeat,
+    hotbar: Vec<VoxelType>,
+    selected_slot: usize,
+    spectator_free_pitch: bool,
 
     // Input state
     player_controller: PlayerController,
     cursor_grabbed: bool,
     mouse_pressed: bool,
+    #[cfg(feature = "gamepad")]
+    gamepads: Option<Gamepads>,
 
     // UI state
-    debug_ui: DebugUi,
+    texture_atlas: TextureAtlas,
+    console: Console,
+    show_settings: bool,
+    // Paused games don't simulate and show the pause menu over the still-rendered world.
+    is_paused: bool,
+    quit_requested: bool,
+    show_crosshair: bool,
+    crosshair: Crosshair,
 
     // Rendering state
     projection: Projection,
+    view_projection_cache: ViewProjectionCache,
     geometry_renderer: GeometryRenderer,
 
     // Render pipeline and resources
     shared_resources: SharedResources,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
+    lighting: Lighting,
+    time_of_day: TimeOfDay,
 
     // Timing
     last_render_time: std::time::Instant,
-    is_surface_configured: bool,
+    // Unclamped durations of the last `FRAME_TIME_WINDOW` frames, in seconds.
+    frame_times: VecDeque<f32>,
+    timestep: FixedTimestep,
+    // Where the player was before the last physics step, for the camera to interpolate from.
+    previous_player_position: cgmath::Point3<f32>,
+
+    // The settings the game started with, plus any changed in the settings panel. Only saved
+    // on exit if something changed, so an invalid settings file isn't overwritten by defaults.
+    settings: Settings,
+    settings_changed: bool,
 }
 
+// Color format of headless render targets; `read_pixels` returns it as RGBA bytes.
+const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
 impl State {
     pub async fn new(window: Arc<Window>) -> anyhow::Result<Self> {
-        let (gpu_context, surface) = GpuContext::new(window.clone()).await?;
+        let settings = Settings::load(Path::new(SETTINGS_PATH));
+
+        let (gpu_context, surface) = GpuContext::new(window.clone(), settings.adapter).await?;
 
         let size = window.inner_size();
 
@@ -84,6 +197,55 @@ impl State {
             desired_maximum_frame_latency: 2,
         };
 
+        let (texture_bind_group_layout, shared_resources) = Self::create_shared_resources(&gpu_context);
+
+        let mut debug_ui = DebugUi::new(
+            &gpu_context.device,
+            surface_format,
+            None,
+            1,
+            &window,
+        );
+
+        let atlas_texture_id = debug_ui.register_texture(&gpu_context.device, &shared_resources.voxel_texture.view);
+
+        Self::set_cursor_grabbed(&window, true);
+
+        let target = RenderTarget::Window(Box::new(WindowTarget {
+            window,
+            surface,
+            config,
+            is_surface_configured: false,
+            debug_ui,
+            atlas_texture_id,
+        }));
+
+        Ok(Self::with_target(gpu_context, target, texture_bind_group_layout, shared_resources, settings))
+    }
+
+    // A state that renders into a `width` x `height` texture instead of a window, for automated
+    // tests. It uses the default settings rather than the settings file, and draws no UI. MSAA
+    // is off so frames don't depend on the driver's sample pattern, and only the spawn chunks
+    // are loaded to keep startup quick.
+    pub async fn new_headless(width: u32, height: u32) -> anyhow::Result<Self> {
+        let settings = Settings {
+            msaa: false,
+            render_distance: 0,
+            ..Settings::default()
+        };
+        let gpu_context = GpuContext::new_headless(settings.adapter).await?;
+
+        let (texture_bind_group_layout, shared_resources) = Self::create_shared_resources(&gpu_context);
+
+        let desc = RenderTargetDesc { format: OFFSCREEN_FORMAT, width, height };
+        let target = RenderTarget::Offscreen(Texture::create_offscreen_target(&gpu_context.device, desc, "offscreen_target"));
+
+        let mut state = Self::with_target(gpu_context, target, texture_bind_group_layout, shared_resources, settings);
+        state.cursor_grabbed = false;
+        Ok(state)
+    }
+
+    fn create_shared_resources(gpu_context: &GpuContext) -> (wgpu::BindGroupLayout, SharedResources) {
         let texture_bind_group_layout =
             gpu_context.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[
@@ -108,6 +270,17 @@ impl State {
             });
 
         let shared_resources = SharedResources::new(&gpu_context.device, &gpu_context.queue, &texture_bind_group_layout);
+        (texture_bind_group_layout, shared_resources)
+    }
+
+    fn with_target(
+        gpu_context: GpuContext,
+        target: RenderTarget,
+        texture_bind_group_layout: wgpu::BindGroupLayout,
+        shared_resources: SharedResources,
+        settings: Settings,
+    ) -> Self {
+        let target_desc = target.desc();
 
         /*
             Setup Game State
@@ -118,15 +291,52 @@ impl State {
             0.0,
         );
 
-        let projection = Projection::new(config.width, config.height);
-        let player_controller = PlayerController::new(0.003);
+        let mut projection = Projection::new(target_desc.width, target_desc.height);
+        projection.fov_axis = settings.fov_axis;
+        projection.base_fovy = settings.fov;
+        projection.fovy = settings.fov;
+        projection.zfar = Self::far_plane(settings.render_distance as i32);
+        let mut player_controller = PlayerController::new(settings.mouse_sensitivity);
+        player_controller.auto_jump = settings.auto_jump;
+        player_controller.max_pitch = settings.max_pitch.to_radians();
+        player_controller.sensitivity_x = settings.sensitivity_x;
+        player_controller.sensitivity_y = settings.sensitivity_y;
+        player_controller.invert_y = settings.invert_y;
+        player_controller.gamepad = settings.gamepad;
+        player_controller.mouse_smoothing = if settings.smooth_mouse { settings.mouse_smoothing } else { 0.0 };
 
         let mut world = World::new();
-        world.load_chunk(ChunkPos::new(0, 1, 0));
-        world.load_chunk(ChunkPos::new(0, 0, 0));
-        world.load_chunk(ChunkPos::new(0, -1, 0));
+        world.set_generation_features(settings.generation);
+        world.set_ore_veins(settings.ore_veins);
+        let render_distance = settings.render_distance as i32;
+        let (min, max) = Self::render_region(ChunkPos::new(0, 0, 0), render_distance);
+        world.load_region(min, max);
+        log::info!(
+            "Generated {} chunks using {} bytes of voxel storage",
+            world.chunk_count(),
+            world.memory_usage(),
+        );
+
+        // Stand on the ground rather than dropping onto it, which would hurt.
+        let mut player = Player::new((0.0, 32.0, 16.0).into());
+        if let Some(ground) = world.highest_solid(0, 16) {
+            player.position.y = ground as f32 + 1.0 + player.height / 2.0 + 0.01;
+        }
+        player.step_height = settings.step_height;
+        player.fall_damage = settings.fall_damage;
+        player.footstep_distance = settings.footstep_distance;
+        player.invulnerable = settings.game_mode == GameMode::Creative;
 
-        let player = Player::new((0.0, 32.0, 16.0).into());
+        // Start on the configured block, adding it to the hotbar if it isn't there already.
+        let mut hotbar = settings.hotbar.clone();
+        let selected_slot = match hotbar.iter().position(|voxel| *voxel == settings.held_block) {
+            Some(slot) => slot,
+            None if settings.held_block == VoxelType::Air => 0,
+            None => {
+                hotbar.insert(0, settings.held_block);
+                0
+            }
+        };
 
         /*
             Setup Camera Uniform
@@ -166,45 +376,87 @@ impl State {
             label: Some("camera_bind_group"),
         });
 
-        let geometry_renderer = GeometryRenderer::new(
+        let mut lighting = Lighting::new(&gpu_context.device);
+        lighting.speed_fog = settings.speed_fog;
+
+        let sample_count = if settings.msaa { gpu_context.msaa_sample_count(target_desc.format) } else { 1 };
+        let mut geometry_renderer = GeometryRenderer::new(
             &gpu_context.device,
-            &config,
+            target_desc,
+            sample_count,
             &texture_bind_group_layout,
             &camera_bind_group_layout,
+            &lighting.bind_group_layout,
+            PipelineOptions {
+                vertex_format: settings.vertex_format,
+                transparent_backfaces: settings.transparent_backfaces,
+            },
         );
+        geometry_renderer.set_outline_depth_bias(&gpu_context.queue, settings.outline_depth_bias);
+        geometry_renderer.chunk_renderer_mut().set_view_distance(Some(render_distance));
 
-        let debug_ui = DebugUi::new(
-            &gpu_context.device,
-            surface_format,
-            None,
-            1,
-            &window,
-        );
-
-        Self::set_cursor_grabbed(&window, true);
-
-        Ok(Self {
-            surface,
-            config,
-            is_surface_configured: false,
-            window,
+        Self {
+            target,
             gpu_context,
             shared_resources,
             camera,
+            camera_detached: false,
+            player_frustum: None,
+            show_frustum: false,
+            debug_ray: None,
+            show_debug_ray: false,
             player_controller,
             projection,
+            view_projection_cache: ViewProjectionCache::new(settings.cache_view_projection),
             camera_buffer,
             camera_bind_group,
+            lighting,
+            // Start at noon.
+            time_of_day: TimeOfDay::new(0.5),
             world,
+            render_distance,
+            loaded_center: None,
+            previous_player_position: player.position,
             player,
-            debug_ui,
+            texture_atlas: TextureAtlas::new(256, 16),
+            console: Console::default(),
+            show_crosshair: true,
+            crosshair: Crosshair::new(),
             cursor_grabbed: true,
             selected_block: None,
-            held_block_type: VoxelType::Stone,
+            break_progress: None,
+            game_mode: settings.game_mode,
+            creative_break_repeat: BreakRepeat::new(CREATIVE_BREAK_REPEAT),
+            hotbar,
+            selected_slot,
+            spectator_free_pitch: settings.spectator_free_pitch,
+            settings,
+            settings_changed: false,
+            show_settings: false,
+            is_paused: false,
+            quit_requested: false,
             last_render_time: std::time::Instant::now(),
+            frame_times: VecDeque::with_capacity(FRAME_TIME_WINDOW),
+            timestep: FixedTimestep::new(PHYSICS_STEP, MAX_STEPS_PER_FRAME),
             mouse_pressed: false,
+            #[cfg(feature = "gamepad")]
+            gamepads: Gamepads::new(),
             geometry_renderer,
-        })
+        }
+    }
+
+    // `None` for headless states.
+    pub fn window(&self) -> Option<&Arc<Window>> {
+        match &self.target {
+            RenderTarget::Window(target) => Some(&target.window),
+            RenderTarget::Offscreen(_) => None,
+        }
+    }
+
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        if let RenderTarget::Window(target) = &mut self.target {
+            target.debug_ui.handle_input(&target.window, event);
+        }
     }
 
     fn set_cursor_grabbed(window: &Window, grabbed: bool) {
@@ -231,37 +483,680 @@ impl State {
     */
     pub fn resize(&mut self, _width: u32, _height: u32) {
         if _width > 0 && _height > 0 {
-            self.config.width = _width;
-            self.config.height = _height;
-            self.surface.configure(&self.gpu_context.device, &self.config);
-            self.is_surface_configured = true;
+            match &mut self.target {
+                RenderTarget::Window(target) => {
+                    target.config.width = _width;
+                    target.config.height = _height;
+                    target.surface.configure(&self.gpu_context.device, &target.config);
+                    target.is_surface_configured = true;
+                }
+                RenderTarget::Offscreen(texture) => {
+                    let desc = RenderTargetDesc { format: OFFSCREEN_FORMAT, width: _width, height: _height };
+                    *texture = Texture::create_offscreen_target(&self.gpu_context.device, desc, "offscreen_target");
+                }
+            }
+            self.projection.resize(_width, _height);
         }
 
-        self.geometry_renderer.recreate_depth_texture(&self.gpu_context.device, &self.config);
+        self.geometry_renderer.recreate_render_targets(&self.gpu_context.device, self.target.desc());
     }
 
-    fn handle_key(&mut self, event_loop: &ActiveEventLoop, code: KeyCode, is_pressed: bool) {
-        if code == KeyCode::Escape && is_pressed {
-            self.cursor_grabbed = !self.cursor_grabbed;
-            Self::set_cursor_grabbed(&self.window, self.cursor_grabbed);
+    fn handle_key(&mut self, _event_loop: &ActiveEventLoop, code: KeyCode, is_pressed: bool) {
+        if code == KeyCode::Backquote && is_pressed {
+            self.toggle_console();
+        } else if self.console.open {
+            // Typing goes to the console, but releases still reach the controller so held
+            // movement keys don't stick.
+            if !is_pressed {
+                self.player_controller.handle_key(code, is_pressed);
+            }
+        } else if code == KeyCode::Escape && is_pressed {
+            self.set_paused(!self.is_paused);
+        } else if self.is_paused && self.player_controller.key_bindings().action(code).is_some() {
+            // As with the console, releases still reach the controller so keys held when
+            // pausing don't stick.
+            if !is_pressed {
+                self.player_controller.handle_key(code, is_pressed);
+            }
+        } else if code == KeyCode::F1 && is_pressed {
+            self.show_crosshair = !self.show_crosshair;
+        } else if code == KeyCode::F2 && is_pressed {
+            self.toggle_surface_preview();
+        } else if code == KeyCode::F3 && is_pressed {
+            self.lighting.highlight_seams = !self.lighting.highlight_seams;
+            log::info!("Seam highlighting: {}", self.lighting.highlight_seams);
+        } else if code == KeyCode::F4 && is_pressed {
+            self.toggle_wireframe();
+        } else if code == KeyCode::F5 && is_pressed {
+            self.save_world();
+        } else if code == KeyCode::F6 && is_pressed {
+            self.export_current_chunk();
+        } else if code == KeyCode::F8 && is_pressed {
+            self.show_frustum = !self.show_frustum;
+            log::info!("Player frustum outline: {}", self.show_frustum);
+        } else if code == KeyCode::F10 && is_pressed {
+            self.toggle_settings_panel();
+        } else if code == KeyCode::F9 && is_pressed {
+            self.load_world();
+        } else if code == KeyCode::F7 && is_pressed {
+            let mismatches = self.geometry_renderer.chunk_renderer().check_consistency(&self.world);
+            log::info!("Chunk buffer consistency check found {} mismatches", mismatches);
+        } else if code == KeyCode::KeyF && is_pressed {
+            self.player.set_flying(!self.player.flying);
+            self.update_free_pitch();
+            log::info!("Flying: {}", self.player.flying);
+        } else if code == KeyCode::KeyN && is_pressed {
+            // Noclip is a flying variant, so turning it on also starts flying.
+            let noclip = !(self.player.flying && self.player.noclip);
+            self.player.set_flying(noclip || self.player.flying);
+            self.player.noclip = noclip;
+            self.update_free_pitch();
+            log::info!("Noclip: {}", noclip);
+        } else if code == KeyCode::KeyG && is_pressed {
+            self.player.ghost = !self.player.ghost;
+            log::info!("Collisions: {}", if self.player.ghost { "off" } else { "on" });
+        } else if let Some(slot) = Self::hotbar_slot_key(code) && is_pressed {
+            self.select_hotbar_slot(slot);
+        } else if code == KeyCode::KeyC && is_pressed {
+            self.toggle_camera_detached();
+        } else if code == KeyCode::KeyT && is_pressed && self.camera_detached {
+            self.teleport_player_to_camera();
+        } else if code == KeyCode::KeyM && is_pressed {
+            self.cycle_mesh_strategy();
+        } else if code == KeyCode::KeyO && is_pressed {
+            self.toggle_occlusion_culling();
+        } else if code == KeyCode::KeyB && is_pressed {
+            self.settings.brush = self.settings.brush.next();
+            self.settings_changed = true;
+            log::info!("Brush: {:?}", self.settings.brush);
         } else {
             self.player_controller.handle_key(code, is_pressed);
         }
     }
 
+    fn hotbar_slot_key(code: KeyCode) -> Option<usize> {
+        const SLOT_KEYS: [KeyCode; 9] = [
+            KeyCode::Digit1,
+            KeyCode::Digit2,
+            KeyCode::Digit3,
+            KeyCode::Digit4,
+            KeyCode::Digit5,
+            KeyCode::Digit6,
+            KeyCode::Digit7,
+            KeyCode::Digit8,
+            KeyCode::Digit9,
+        ];
+
+        SLOT_KEYS.iter().position(|key| *key == code)
+    }
+
+    // Spectating in noclip may look all the way around; otherwise the look is clamped again.
+    fn update_free_pitch(&mut self) {
+        let free_pitch = self.spectator_free_pitch && self.player.noclip;
+        self.player_controller.free_pitch = free_pitch;
+
+        if !free_pitch {
+            let max_pitch = self.player_controller.max_pitch;
+            self.camera.pitch = self.camera.pitch.clamp(-max_pitch, max_pitch);
+        }
+    }
+
+    fn toggle_camera_detached(&mut self) {
+        self.camera_detached = !self.camera_detached;
+        if self.camera_detached {
+            // Don't let the player keep walking on its own.
+            self.player.velocity.x = 0.0;
+            self.player.velocity.z = 0.0;
+            let view_projection = self.projection.get_view_projection_matrix(&self.camera);
+            self.player_frustum = Some(Frustum::from_view_projection(view_projection.into()));
+        }
+        log::info!("Camera detached: {}", self.camera_detached);
+    }
+
+    // Drops the player at the detached camera and re-attaches the camera.
+    fn teleport_player_to_camera(&mut self) {
+        let target = self.camera.position - cgmath::vec3(0.0, EYE_HEIGHT, 0.0);
+        self.world.load_chunk(ChunkPos::from_world_pos(target.x, target.y, target.z));
+
+        self.player.position = target;
+        self.previous_player_position = target;
+        self.player.velocity = cgmath::Vector3::new(0.0, 0.0, 0.0);
+        self.camera_detached = false;
+        log::info!("Teleported player to {:?}", target);
+    }
+
+    fn toggle_console(&mut self) {
+        self.console.open = !self.console.open;
+        self.console.input.clear();
+        self.mouse_pressed = false;
+        self.cursor_grabbed = !self.console.open;
+        if let Some(window) = self.window() {
+            Self::set_cursor_grabbed(window, self.cursor_grabbed);
+        }
+    }
+
+    pub fn is_console_open(&self) -> bool {
+        self.console.open
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    // Pausing stops the simulation and frees the cursor for the pause menu. Resuming grabs it
+    // again and restarts the frame clock, so the time spent paused isn't simulated at once.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.is_paused = paused;
+        self.mouse_pressed = false;
+        self.cursor_grabbed = !paused && !self.show_settings;
+        if let Some(window) = self.window() {
+            Self::set_cursor_grabbed(window, self.cursor_grabbed);
+        }
+        if !paused {
+            self.last_render_time = std::time::Instant::now();
+        }
+    }
+
+    // Set by the pause menu's Quit button; the app exits when it sees it.
+    pub fn quit_requested(&self) -> bool {
+        self.quit_requested
+    }
+
+    fn toggle_settings_panel(&mut self) {
+        self.show_settings = !self.show_settings;
+        self.mouse_pressed = false;
+        self.cursor_grabbed = !self.show_settings && !self.is_paused;
+        if let Some(window) = self.window() {
+            Self::set_cursor_grabbed(window, self.cursor_grabbed);
+        }
+    }
+
+    // Whether the mouse is being used by a UI window instead of for playing.
+    pub fn is_ui_open(&self) -> bool {
+        self.console.open || self.show_settings || self.is_paused
+    }
+
+    // Pushes the mouse and FOV settings to the controller and projection, and loads any chunks
+    // a larger render distance now covers.
+    fn apply_settings(&mut self) {
+        self.player_controller.mouse_sensitivity = self.settings.mouse_sensitivity;
+        self.player_controller.sensitivity_x = self.settings.sensitivity_x;
+        self.player_controller.sensitivity_y = self.settings.sensitivity_y;
+        self.player_controller.invert_y = self.settings.invert_y;
+        self.player_controller.gamepad = self.settings.gamepad;
+        self.player_controller.mouse_smoothing = if self.settings.smooth_mouse { self.settings.mouse_smoothing } else { 0.0 };
+        self.projection.fov_axis = self.settings.fov_axis;
+        self.projection.base_fovy = self.settings.fov;
+        self.projection.fovy = self.settings.fov;
+        self.set_render_distance(self.settings.render_distance as i32);
+        self.settings_changed = true;
+    }
+
+    // Chunks within `distance` of `center` horizontally, across the spawn area's layers.
+    fn render_region(center: ChunkPos, distance: i32) -> (ChunkPos, ChunkPos) {
+        (
+            ChunkPos::new(center.x - distance, SPAWN_REGION.0.y, center.z - distance),
+            ChunkPos::new(center.x + distance, SPAWN_REGION.1.y, center.z + distance),
+        )
+    }
+
+    // Just far enough to reach the far corners of the loaded region from the player's chunk.
+    fn far_plane(distance: i32) -> f32 {
+        let horizontal = (distance + 1) as f32 * CHUNK_SIZE as f32 * std::f32::consts::SQRT_2;
+        let vertical = (SPAWN_REGION.1.y - SPAWN_REGION.0.y + 1) as f32 * CHUNK_SIZE as f32;
+        horizontal.hypot(vertical)
+    }
+
+    pub fn render_distance(&self) -> i32 {
+        self.render_distance
+    }
+
+    // Loads the chunks within `distance` of the player (clamped to 0..=MAX_RENDER_DISTANCE)
+    // and stops drawing those beyond it, pulling the far plane in to match. Chunks beyond it
+    // stay loaded.
+    pub fn set_render_distance(&mut self, distance: i32) {
+        let distance = distance.clamp(0, MAX_RENDER_DISTANCE as i32);
+        self.render_distance = distance;
+        self.settings.render_distance = distance as u32;
+        self.projection.zfar = Self::far_plane(distance);
+        self.geometry_renderer.chunk_renderer_mut().set_view_distance(Some(distance));
+        self.loaded_center = None;
+        self.load_around_player();
+    }
+
+    // Loads the chunks within render distance once the player enters a new chunk.
+    fn load_around_player(&mut self) {
+        let position = self.player.position;
+        let center = ChunkPos::from_world_pos(position.x, position.y, position.z);
+        if self.loaded_center == Some(center) {
+            return;
+        }
+
+        let (min, max) = Self::render_region(center, self.render_distance);
+        self.world.load_region(min, max);
+        self.loaded_center = Some(center);
+    }
+
+    pub fn save_settings(&self) {
+        if !self.settings_changed {
+            return;
+        }
+
+        match self.settings.save(Path::new(SETTINGS_PATH)) {
+            Ok(()) => log::info!("Saved settings to {}", SETTINGS_PATH),
+            Err(e) => log::error!("Failed to save settings: {}", e),
+        }
+    }
+
+    fn run_command(&mut self, line: &str) {
+        self.console.log(format!("> {}", line));
+
+        let command = match Command::parse(line) {
+            Ok(command) => command,
+            Err(e) => {
+                self.console.log(e.to_string());
+                return;
+            }
+        };
+
+        match command {
+            Command::Teleport { x, y, z } => {
+                self.world.load_chunk(ChunkPos::from_world_pos(x, y, z));
+                if self.teleport_player(cgmath::Point3::new(x, y, z)) {
+                    self.console.log(format!("Teleported to {}, {}, {}", x, y, z));
+                } else {
+                    self.console.log(format!("Can't teleport to {}, {}, {}: its chunk isn't loaded", x, y, z));
+                }
+            }
+            Command::Give(block) => {
+                // The hotbar holds unlimited blocks, so giving just makes the block selectable.
+                let slot = match self.hotbar.iter().position(|voxel| *voxel == block) {
+                    Some(slot) => slot,
+                    None => {
+                        self.hotbar.push(block);
+                        self.hotbar.len() - 1
+                    }
+                };
+                self.selected_slot = slot;
+                self.console.log(format!("Gave {:?}", block));
+            }
+            Command::RenderDistance(distance) => {
+                self.set_render_distance(distance as i32);
+                self.settings_changed = true;
+                self.console.log(format!("Loaded chunks within {} of the player ({} total)", self.render_distance, self.world.chunk_count()));
+            }
+            Command::Seed(seed) => {
+                self.replace_world(World::with_seed(seed));
+                self.console.log(format!("Generated a new world from seed {}", seed));
+            }
+            Command::RayOffset { right, up, forward } => {
+                self.settings.ray_origin_offset = [right, up, forward];
+                self.settings_changed = true;
+                self.console.log(format!("Targeting ray starts {}, {}, {} from the camera", right, up, forward));
+            }
+            Command::DebugRay => {
+                self.show_debug_ray = !self.show_debug_ray;
+                self.console.log(format!("Targeting ray shown: {}", self.show_debug_ray));
+            }
+            Command::BatchDraws => {
+                let chunk_renderer = self.geometry_renderer.chunk_renderer_mut();
+                let batching = !chunk_renderer.batching();
+                chunk_renderer.set_batching(batching);
+                self.console.log(format!("Batched chunk draws: {}", batching));
+            }
+            Command::FrustumCulling => {
+                let chunk_renderer = self.geometry_renderer.chunk_renderer_mut();
+                let enabled = !chunk_renderer.frustum_culling();
+                chunk_renderer.set_frustum_culling(enabled);
+                self.console.log(format!("Frustum culling: {}", enabled));
+            }
+            Command::Light { color, range } => {
+                self.lighting.point_lights.push(PointLight {
+                    position: self.camera.position,
+                    color,
+                    range,
+                });
+                self.console.log(format!("Placed a light at the camera ({} lights)", self.lighting.point_lights.len()));
+            }
+            Command::ClearLights => {
+                self.lighting.point_lights.clear();
+                self.console.log("Removed all lights");
+            }
+            Command::ShowDepth => {
+                let show = !self.geometry_renderer.show_depth();
+                self.geometry_renderer.set_show_depth(show);
+                self.console.log(format!("Depth buffer view: {}", show));
+            }
+            Command::HighlightBlocks(mode) => {
+                self.lighting.highlight_blocks = mode;
+                if mode == BlockHighlight::Off {
+                    self.console.log("Block highlighting off");
+                } else {
+                    let blocks = (0..=u8::MAX)
+                        .map_while(VoxelType::from_u8)
+                        .filter(|voxel| mode.matches(*voxel))
+                        .map(|voxel| voxel.def().name.as_str())
+                        .collect::<Vec<_>>();
+                    self.console.log(format!("Highlighting {:?} blocks: {}", mode, blocks.join(", ")));
+                }
+            }
+            Command::Remesh => {
+                self.geometry_renderer.chunk_renderer_mut().force_remesh(&mut self.world);
+                self.console.log(format!("Remeshing {} chunks", self.world.chunk_count()));
+            }
+            Command::Brush { shape, radius } => {
+                self.settings.brush = shape;
+                if let Some(radius) = radius {
+                    self.settings.brush_radius = radius.min(MAX_BRUSH_RADIUS);
+                }
+                self.settings_changed = true;
+                self.console.log(format!("Brush set to {:?} with radius {}", shape, self.settings.brush_radius));
+            }
+            Command::Heal(amount) => {
+                self.player.heal(amount.unwrap_or(MAX_HEALTH));
+                self.console.log(format!("Health: {:.1} / {:.0}", self.player.health, MAX_HEALTH));
+            }
+            Command::Bind { action, key } => {
+                self.player_controller.rebind(action, key);
+                self.console.log(format!("Bound {:?} to {:?}", action, key));
+            }
+            Command::GameMode(mode) => {
+                self.game_mode = mode;
+                self.player.invulnerable = mode == GameMode::Creative;
+                self.break_progress = None;
+                self.console.log(format!("Game mode set to {:?}", mode));
+            }
+        }
+    }
+
+    fn save_world(&self) {
+        let player = PlayerData {
+            hotbar: self.hotbar.clone(),
+            selected_slot: self.selected_slot,
+        };
+
+        match self.world.save(Path::new(WORLD_SAVE_PATH), Some(&player)) {
+            Ok(()) => log::info!("Saved world to {}", WORLD_SAVE_PATH),
+            Err(e) => log::error!("Failed to save world: {}", e),
+        }
+    }
+
+    // Dumps the chunk the player is standing in to chunk_<x>_<y>_<z>.json.
+    fn export_current_chunk(&self) {
+        let position = self.player.position;
+        let pos = ChunkPos::from_world_pos(position.x, position.y, position.z);
+        let path = format!("chunk_{}_{}_{}.json", pos.x, pos.y, pos.z);
+
+        match self.world.export_chunk_json(pos, Path::new(&path)) {
+            Ok(()) => log::info!("Exported chunk {:?} to {}", pos, path),
+            Err(e) => log::error!("Failed to export chunk {:?}: {}", pos, e),
+        }
+    }
+
+    fn load_world(&mut self) {
+        match World::load(Path::new(WORLD_SAVE_PATH)) {
+            Ok((world, player)) => {
+                self.replace_world(world);
+                if let Some(player) = player {
+                    self.restore_player_data(player);
+                }
+                log::info!("Loaded world from {}", WORLD_SAVE_PATH);
+            }
+            Err(e) => log::error!("Failed to load world: {}", e),
+        }
+    }
+
+    // Swaps in `world`, generated with the current generation features and ore veins, and
+    // drops every mesh of the old one.
+    fn replace_world(&mut self, mut world: World) {
+        world.set_generation_features(self.world.generation_features());
+        world.set_ore_veins(self.world.ore_veins());
+        world.load_region(SPAWN_REGION.0, SPAWN_REGION.1);
+        self.world = world;
+        self.geometry_renderer.chunk_renderer_mut().clear();
+        self.loaded_center = None;
+    }
+
+    // Saves from older versions may hold blocks the hotbar can't, so bad entries are dropped
+    // and an empty hotbar is left as it was.
+    fn restore_player_data(&mut self, player: PlayerData) {
+        let hotbar = player.hotbar.into_iter()
+            .filter(|voxel| *voxel != VoxelType::Air)
+            .collect::<Vec<_>>();
+        if hotbar.is_empty() {
+            return;
+        }
+
+        self.selected_slot = player.selected_slot.min(hotbar.len() - 1);
+        self.hotbar = hotbar;
+    }
+
+    fn toggle_surface_preview(&mut self) {
+        let mut features = self.world.generation_features();
+        features.surface_only = !features.surface_only;
+        self.world.set_generation_features(features);
+        self.world.regenerate();
+        log::info!("Surface-only generation preview: {}", features.surface_only);
+    }
+
+    fn cycle_mesh_strategy(&mut self) {
+        let chunk_renderer = self.geometry_renderer.chunk_renderer_mut();
+        let strategy = chunk_renderer.mesh_strategy().next();
+        chunk_renderer.set_mesh_strategy(strategy);
+        self.world.mark_all_dirty();
+        log::info!("Mesh strategy: {:?}", strategy);
+    }
+
+    fn toggle_wireframe(&mut self) {
+        let requested = !self.geometry_renderer.wireframe();
+        let enabled = self.geometry_renderer.set_wireframe(requested);
+        if requested && !enabled {
+            log::info!("Wireframe rendering is not supported on this device");
+        } else {
+            log::info!("Wireframe: {}", if enabled { "on" } else { "off" });
+        }
+    }
+
+    fn toggle_occlusion_culling(&mut self) {
+        let chunk_renderer = self.geometry_renderer.chunk_renderer_mut();
+        let requested = !chunk_renderer.occlusion_culling();
+        let enabled = chunk_renderer.set_occlusion_culling(requested);
+        if requested && !enabled {
+            log::info!("Occlusion culling is not supported on this device");
+        } else {
+            log::info!("Occlusion culling: {}", if enabled { "on" } else { "off" });
+        }
+    }
+
+    // Feeds gamepad sticks and buttons to the same controls as the keyboard and mouse. While a
+    // UI window is open only releases get through, so nothing stays held.
+    #[cfg(feature = "gamepad")]
+    pub fn poll_gamepads(&mut self) {
+        let Some(gamepads) = &mut self.gamepads else {
+            return;
+        };
+        let actions = gamepads.poll();
+        let (move_stick, look_stick) = gamepads.sticks();
+
+        let ui_open = self.is_ui_open();
+        self.player_controller.move_stick = if ui_open { (0.0, 0.0) } else { move_stick };
+        self.player_controller.look_stick = if ui_open { (0.0, 0.0) } else { look_stick };
+
+        for (action, pressed) in actions {
+            if ui_open && pressed {
+                continue;
+            }
+            match action {
+                GamepadAction::Jump => self.player_controller.handle_action(Action::Jump, pressed),
+                GamepadAction::Break => self.mouse_pressed = pressed,
+                GamepadAction::Place if pressed => self.place_block(),
+                GamepadAction::Place => {}
+            }
+        }
+    }
+
     pub fn device_input(&mut self, event: &DeviceEvent) {
-        match event {
-            DeviceEvent::MouseMotion { delta } => {
-                self.player_controller.handle_mouse(delta.0, delta.1, &mut self.camera);
+        if let DeviceEvent::MouseMotion { delta } = event && !self.show_settings && !self.is_paused {
+            self.player_controller.handle_mouse(delta.0, delta.1, &mut self.camera);
+        }
+    }
+
+    // 0.0 is midnight, 0.5 noon.
+    pub fn set_time_of_day(&mut self, time: f32) {
+        self.time_of_day.set_time(time);
+    }
+
+    // Multiplier on how fast the day passes; 0 stops the clock.
+    pub fn set_time_of_day_speed(&mut self, speed: f32) {
+        self.time_of_day.speed = speed;
+    }
+
+    pub fn fog(&self) -> Fog {
+        self.lighting.fog
+    }
+
+    // Also sets the sky color, which is the fog color.
+    pub fn set_fog(&mut self, fog: Fog) {
+        self.lighting.fog = fog;
+    }
+
+    // The block at a world position, or `None` if its chunk isn't loaded.
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> Option<VoxelType> {
+        self.world.get_voxel(x, y, z)
+    }
+
+    // Edits the world as breaking and placing do: the chunk (and any neighbor sharing the
+    // block's faces) is remeshed, light is updated and the change is saved with the world.
+    // Unlike `World::set_voxel` this never loads chunks; returns false and does nothing if the
+    // block's chunk isn't loaded. Placing a solid block inside the player doesn't push them out.
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, voxel: VoxelType) -> bool {
+        if !self.world.set_loaded_voxel(x, y, z, voxel) {
+            return false;
+        }
+
+        if self.break_progress.is_some_and(|(target, _)| target == (x, y, z)) {
+            self.break_progress = None;
+        }
+        true
+    }
+
+    // See `Player::teleport`.
+    pub fn teleport_player(&mut self, position: cgmath::Point3<f32>) -> bool {
+        if !self.player.teleport(&self.world, position) {
+            return false;
+        }
+
+        self.previous_player_position = position;
+        self.break_progress = None;
+        true
+    }
+
+    fn render_stats(&self) -> panels::RenderStats {
+        let average_frame_time = if self.frame_times.is_empty() {
+            0.0
+        } else {
+            self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32
+        };
+
+        let chunk_renderer = self.geometry_renderer.chunk_renderer();
+        panels::RenderStats {
+            fps: if average_frame_time > 0.0 { 1.0 / average_frame_time } else { 0.0 },
+            frame_time_ms: average_frame_time * 1000.0,
+            chunk_count: self.world.chunk_count(),
+            vertex_count: chunk_renderer.vertex_count(),
+            vertex_memory: chunk_renderer.vertex_memory(),
+            draw_calls: self.geometry_renderer.draw_call_count(),
+        }
+    }
+
+    fn held_block(&self) -> VoxelType {
+        self.hotbar[self.selected_slot]
+    }
+
+    // A light at the player's eyes while holding a light-emitting block, reaching as many
+    // blocks as the block's light level. It stays with the player while the camera is detached.
+    fn held_light(&self) -> Option<PointLight> {
+        let level = self.held_block().light_emission();
+        (level > 0).then(|| PointLight {
+            position: self.player.position + cgmath::vec3(0.0, EYE_HEIGHT, 0.0),
+            color: HELD_LIGHT_COLOR,
+            range: level as f32,
+        })
+    }
+
+    // Moves the hotbar selection by `step` slots, wrapping around at either end.
+    pub fn cycle_hotbar(&mut self, step: isize) {
+        let len = self.hotbar.len() as isize;
+        self.selected_slot = (self.selected_slot as isize + step).rem_euclid(len) as usize;
+    }
+
+    fn select_hotbar_slot(&mut self, slot: usize) {
+        if slot < self.hotbar.len() {
+            self.selected_slot = slot;
+        }
+    }
+
+    // How far breaking the targeted block has got, from 0 to 1, for a cracking overlay.
+    pub fn break_progress(&self) -> Option<((i32, i32, i32), f32)> {
+        let ((x, y, z), elapsed) = self.break_progress?;
+        let voxel = self.world.get_voxel(x, y, z)?;
+        let time = voxel.hardness();
+        let fraction = if time > 0.0 { (elapsed / time).min(1.0) } else { 1.0 };
+        Some(((x, y, z), fraction))
+    }
+
+    // Breaks the targeted block once the break button has been held on it for its hardness.
+    // Looking at a different block starts over.
+    fn update_breaking(&mut self, dt: f32) {
+        let (target, normal) = match &self.selected_block {
+            Some(hit) if self.mouse_pressed => (hit.position, hit.normal),
+            _ => {
+                self.break_progress = None;
+                if !self.mouse_pressed {
+                    self.creative_break_repeat.reset();
+                }
+                return;
+            }
+        };
+
+        // Creative breaks instantly on click, then repeats while held.
+        if self.game_mode == GameMode::Creative {
+            if self.creative_break_repeat.tick(dt) {
+                self.break_with_brush(target, normal);
             }
-            _ => {}
+            return;
+        }
+
+        let elapsed = match self.break_progress {
+            Some((pos, elapsed)) if pos == target => elapsed + dt,
+            _ => 0.0,
+        };
+
+        let (x, y, z) = target;
+        let voxel = self.world.get_voxel(x, y, z).unwrap_or(VoxelType::Air);
+
+        if elapsed >= voxel.hardness() {
+            self.break_with_brush(target, normal);
+            self.break_progress = None;
+        } else {
+            self.break_progress = Some((target, elapsed));
         }
     }
 
-    fn break_block(&mut self) {
-        if let Some(hit) = &self.selected_block {
-            let (x, y, z) = hit.position;
-            self.world.set_voxel(x, y, z, VoxelType::Air);
+    // The blocks the brush covers around `center`, lines running along `direction`.
+    fn brush_cells(&self, center: (i32, i32, i32), direction: (i32, i32, i32)) -> Vec<(i32, i32, i32)> {
+        self.settings.brush.cells(center, direction, self.settings.brush_radius)
+    }
+
+    // Breaks the targeted block and the others the brush covers, lines digging into the hit
+    // face. Air, liquids and blocks in unloaded chunks are left alone.
+    fn break_with_brush(&mut self, target: (i32, i32, i32), normal: (i32, i32, i32)) {
+        for (x, y, z) in self.brush_cells(target, (-normal.0, -normal.1, -normal.2)) {
+            let voxel = self.world.get_voxel(x, y, z);
+            if let Some(voxel) = voxel.filter(|voxel| *voxel != VoxelType::Air && !voxel.is_liquid()) {
+                self.world.set_voxel(x, y, z, VoxelType::Air);
+                self.play_block_sound("Break", voxel, (x, y, z));
+            }
         }
     }
 
@@ -269,17 +1164,29 @@ impl State {
         if let Some(hit) = &self.selected_block {
             let (x, y, z) = hit.position;
             let (nx, ny, nz) = hit.normal;
+            let place = (x + nx, y + ny, z + nz);
+            let held = self.held_block();
 
-            let place_x = x + nx;
-            let place_y = y + ny;
-            let place_z = z + nz;
-
-            if !self.is_position_inside_player(place_x, place_y, place_z) {
-                self.world.set_voxel(place_x, place_y, place_z, self.held_block_type);
+            // Lines build out from the hit face.
+            for (place_x, place_y, place_z) in self.brush_cells(place, hit.normal) {
+                // When aiming through glass the cell in front of the hit face may be the glass
+                // itself. Liquids are replaced like air.
+                let is_free = self.world.get_voxel(place_x, place_y, place_z)
+                    .is_some_and(|voxel| voxel == VoxelType::Air || voxel.is_liquid());
+                if is_free && !self.is_position_inside_player(place_x, place_y, place_z) {
+                    self.world.set_voxel(place_x, place_y, place_z, held);
+                    self.play_block_sound("Place", held, (place_x, place_y, place_z));
+                }
             }
         }
     }
 
+    // Stands in for playing a block's place or break sound until there is audio.
+    fn play_block_sound(&self, action: &str, voxel: VoxelType, position: (i32, i32, i32)) {
+        let pitch = block_sound_pitch(position, self.settings.block_sound_pitch_variation);
+        log::debug!("{} sound for {:?} at {:?}, pitch {:.2}", action, voxel, position, pitch);
+    }
+
     fn is_position_inside_player(&self, x: i32, y: i32, z: i32) -> bool {
         // Check if block would intersect with player's collision box
         let block_min_x = x as f32;
@@ -314,55 +1221,225 @@ impl State {
         let mut dt = now.duration_since(self.last_render_time).as_secs_f32();
         self.last_render_time = now;
 
+        if self.frame_times.len() == FRAME_TIME_WINDOW {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(dt);
+
         dt = dt.min(0.1);
 
-        // Update camera
-        self.player_controller.update_velocity(&mut self.player, &mut self.camera, dt);
-        self.gpu_context.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.projection.get_view_projection_matrix(&self.camera)]));
+        if !self.is_paused {
+            self.simulate(dt);
+        }
+        if !self.camera_detached {
+            // Physics may be up to a step ahead of the frame, so the camera is placed between
+            // the last two steps to move smoothly.
+            let moved = self.player.position - self.previous_player_position;
+            let position = self.previous_player_position + moved * self.timestep.alpha();
+            self.camera.position = position + cgmath::vec3(0.0, EYE_HEIGHT + self.player.step_offset(), 0.0);
+        }
 
-        self.player.update(&mut self.world, dt);
-        self.camera.position = self.player.position + cgmath::vec3(0.0, 0.8, 0.0);
+        if let Some(view_projection) = self.view_projection_cache.update(&self.projection, &self.camera) {
+            self.gpu_context.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[view_projection]));
+            let frustum = Frustum::from_view_projection(view_projection.into());
+            self.geometry_renderer.chunk_renderer_mut().set_view_frustum(frustum);
+            self.geometry_renderer.set_depth_range(&self.gpu_context.queue, self.projection.znear, self.projection.zfar);
+        }
+
+        if !self.is_paused {
+            self.time_of_day.update(dt);
+        }
+        self.lighting.set_sun_direction(self.time_of_day.sun_direction());
+        self.lighting.daylight = self.time_of_day.daylight();
+        self.lighting.update_speed_factor(cgmath::InnerSpace::magnitude(self.player.velocity), dt);
+        self.lighting.held_light = self.held_light();
+        self.lighting.update_buffer(&self.gpu_context.queue, self.camera.position);
 
         // Raycast to find selected block
-        let ray_origin = self.camera.position;
+        let [right, up, forward] = self.settings.ray_origin_offset;
+        let ray_origin = self.camera.position
+            + self.camera.get_right() * right
+            + self.camera.get_up() * up
+            + self.camera.get_direction() * forward;
         let ray_direction = self.camera.get_direction();
-        self.selected_block = raycast_voxel(
+        let reach_through_transparent = self.settings.reach_through_transparent;
+        self.selected_block = raycast_voxel_where(
             &self.world,
             ray_origin,
             ray_direction,
-            5.0,
+            REACH_DISTANCE,
+            |voxel| voxel != VoxelType::Air
+                && !voxel.is_liquid()
+                && !(reach_through_transparent && voxel.is_transparent()),
         );
+        if !self.camera_detached {
+            let length = self.selected_block.as_ref().map_or(REACH_DISTANCE, |hit| hit.distance);
+            self.debug_ray = Some([ray_origin, ray_origin + ray_direction * length]);
+        }
+        if !self.is_paused {
+            self.update_breaking(dt);
+        }
+        self.crosshair.update(self.selected_block.is_some(), dt);
+        let outlines = self.selected_block.iter()
+            .map(|hit| OutlineBox::voxel(hit.position))
+            .collect::<Vec<_>>();
+        self.geometry_renderer.set_outlines(&self.gpu_context.device, &self.gpu_context.queue, &outlines);
+        // Inside the frustum it would only be visible as the edges of the screen.
+        let frustum = self.player_frustum.as_ref().filter(|_| self.show_frustum && self.camera_detached);
+        self.geometry_renderer.set_frustum_outline(&self.gpu_context.queue, frustum);
+        let debug_ray = self.debug_ray.filter(|_| self.show_debug_ray);
+        self.geometry_renderer.set_debug_line(&self.gpu_context.queue, debug_ray);
 
         // Remesh chunks if necessary
-        self.geometry_renderer.update_chunk_renderer(&mut self.world, &self.gpu_context.device);
+        self.geometry_renderer.update_chunk_renderer(
+            &mut self.world,
+            &self.gpu_context.device,
+            &self.gpu_context.queue,
+            self.camera.position,
+        );
     }
 
-    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        self.window.request_redraw();
+    // Moves the camera and player by `dt` of input and physics. Looking around and flying the
+    // detached camera follow the frame; the player moves in fixed physics steps.
+    fn simulate(&mut self, dt: f32) {
+        self.player_controller.update_look(&mut self.camera, dt);
+        if self.camera_detached {
+            self.player_controller.fly_camera(&mut self.camera, dt);
+        }
+        self.player_controller.update_fov(&mut self.projection, dt);
+
+        for _ in 0..self.timestep.advance(dt) {
+            self.step_physics(self.timestep.step());
+        }
+    }
+
+    fn step_physics(&mut self, dt: f32) {
+        self.previous_player_position = self.player.position;
+        if !self.camera_detached {
+            self.player_controller.update_velocity(&mut self.player, &mut self.camera, &self.world, dt);
+        }
 
-        if !self.is_surface_configured {
-            return Ok(());
+        self.player.update(&mut self.world, dt);
+        self.load_around_player();
+        for event in self.player.take_events() {
+            log::debug!("Player event: {:?}", event);
         }
+    }
+
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let (output, view) = match &self.target {
+            RenderTarget::Window(target) => {
+                target.window.request_redraw();
+
+                if !target.is_surface_configured {
+                    return Ok(());
+                }
 
-        let output = self.surface.get_current_texture()?;
-        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+                let output = target.surface.get_current_texture()?;
+                let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+                (Some(output), view)
+            }
+            RenderTarget::Offscreen(texture) => (None, texture.view.clone()),
+        };
 
         let mut encoder = self.gpu_context.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder"),
         });
 
-        self.geometry_renderer.render(&view, &mut encoder, &self.shared_resources.voxel_bind_group, &self.camera_bind_group);
+        self.geometry_renderer.render(
+            &view,
+            &mut encoder,
+            &self.shared_resources.voxel_bind_group,
+            &self.camera_bind_group,
+            &self.lighting,
+            self.camera.position,
+        );
+
+        let submitted = self.draw_ui(&mut encoder, &view);
 
-        // UI rendering
-        let surface_view = output
-            .texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.gpu_context.queue.submit(std::iter::once(encoder.finish()));
+        if let Some(output) = output {
+            output.present();
+        }
+
+        if let Some(line) = submitted {
+            self.run_command(&line);
+        }
+
+        Ok(())
+    }
+
+    // Copies the last rendered frame back from the GPU as tightly packed RGBA rows, top row
+    // first. Only headless states can be read back.
+    pub fn read_pixels(&self) -> anyhow::Result<Vec<u8>> {
+        let RenderTarget::Offscreen(texture) = &self.target else {
+            anyhow::bail!("only headless states can read back their frames");
+        };
+
+        let desc = self.target.desc();
+        let row_bytes = desc.width * 4;
+        let padded_row_bytes = row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let device = &self.gpu_context.device;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_row_bytes * desc.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            texture.texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: None,
+                },
+            },
+            texture.texture.size(),
+        );
+        self.gpu_context.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |mapped| {
+            let _ = sender.send(mapped);
+        });
+        device.poll(wgpu::PollType::wait_indefinitely())?;
+        receiver.recv()??;
+
+        let pixels = slice.get_mapped_range()
+            .chunks(padded_row_bytes as usize)
+            .flat_map(|row| &row[..row_bytes as usize])
+            .copied()
+            .collect();
+        Ok(pixels)
+    }
+
+    // Draws the debug panels, hotbar, crosshair and console over the frame. Returns a console
+    // line submitted this frame, if any. Headless states have no UI.
+    fn draw_ui(&mut self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) -> Option<String> {
+        let render_stats = self.render_stats();
+        let held_block = self.held_block();
+        let ground_block = self.player.ground_block(&self.world).map(|(.., voxel)| voxel);
+        let break_progress = self.break_progress().map(|(_, fraction)| fraction);
+        let biome = self.world.biome_at(self.player.position.x.floor() as i32, self.player.position.z.floor() as i32);
+
+        let RenderTarget::Window(target) = &mut self.target else {
+            return None;
+        };
 
         let screen_descriptor = ScreenDescriptor {
-            size_in_pixels: [self.config.width, self.config.height],
-            pixels_per_point: self.window.as_ref().scale_factor() as f32,
+            size_in_pixels: [target.config.width, target.config.height],
+            pixels_per_point: target.window.scale_factor() as f32,
         };
 
-        self.debug_ui.begin_frame(&self.window);
+        target.debug_ui.begin_frame(&target.window);
 
         egui::Window::new("Debug Panel 1")
             .frame(egui::Frame {
@@ -378,27 +1455,251 @@ impl State {
             .default_open(true)
             .collapsible(false)
             .movable(false)
-            .show(self.debug_ui.context(), |ui| {
+            .show(target.debug_ui.context(), |ui| {
                 panels::PlayerInfoPanel::show(
                     ui,
-                    self.player.position,
-                    self.player.velocity
+                    &self.player,
+                    held_block,
+                    ground_block,
+                    biome,
+                    self.selected_block.as_ref(),
+                    break_progress,
                 );
+                ui.add_space(8.0);
+                panels::RenderStatsPanel::show(ui, &render_stats);
+                ui.add_space(8.0);
+                panels::TimeOfDayPanel::show(ui, &mut self.time_of_day);
             });
 
-        self.debug_ui.end_frame_and_draw(
+        egui::Area::new(egui::Id::new("hotbar"))
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -8.0))
+            .interactable(false)
+            .show(target.debug_ui.context(), |ui| {
+                panels::HotbarPanel::show(
+                    ui,
+                    target.atlas_texture_id,
+                    &self.texture_atlas,
+                    &self.hotbar,
+                    self.selected_slot,
+                );
+            });
+
+        if self.show_crosshair {
+            self.crosshair.draw(target.debug_ui.context());
+        }
+
+        let mut settings_changed = false;
+        if self.show_settings {
+            egui::Window::new("Settings")
+                .resizable(false)
+                .collapsible(false)
+                .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+                .show(target.debug_ui.context(), |ui| {
+                    settings_changed = panels::SettingsPanel::show(ui, &mut self.settings);
+                });
+        }
+
+        // Settings opened from the pause menu cover it.
+        let mut pause_choice = None;
+        if self.is_paused && !self.show_settings {
+            egui::Window::new("Pause Menu")
+                .title_bar(false)
+                .resizable(false)
+                .collapsible(false)
+                .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+                .show(target.debug_ui.context(), |ui| {
+                    pause_choice = panels::PauseMenu::show(ui);
+                });
+        }
+
+        let mut submitted = None;
+        if self.console.open {
+            egui::Window::new("Console")
+                .title_bar(false)
+                .resizable(false)
+                .collapsible(false)
+                .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(0.0, 0.0))
+                .default_width(400.0)
+                .show(target.debug_ui.context(), |ui| {
+                    submitted = panels::ConsolePanel::show(ui, &mut self.console);
+                });
+        }
+
+        target.debug_ui.end_frame_and_draw(
             &self.gpu_context.device,
             &self.gpu_context.queue,
-            &mut encoder,
-            &self.window,
-            &surface_view,
+            encoder,
+            &target.window,
+            view,
             screen_descriptor,
         );
 
+        if settings_changed {
+            self.apply_settings();
+        }
+        match pause_choice {
+            Some(panels::PauseChoice::Resume) => self.set_paused(false),
+            Some(panels::PauseChoice::Quit) => self.quit_requested = true,
+            None => {}
+        }
 
-        self.gpu_context.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+        submitted
+    }
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::*;
+    use crate::game::world::GenerationFeatures;
+    use crate::rendering::mesh::{ChunkMeshBuffer, ChunkMesher, VertexFormat};
+
+    // A 64x64 offscreen `State`. Any adapter will do, including a software one like llvmpipe.
+    fn headless_state() -> State {
+        pollster::block_on(State::new_headless(64, 64)).expect("the headless tests need a GPU adapter")
+    }
+
+    // Renders a single chunk offscreen and checks that something other than the sky was drawn.
+    #[test]
+    fn headless_render_draws_a_chunk() {
+        let mut state = headless_state();
+
+        state.world = World::new();
+        let pos = ChunkPos::new(0, -1, 0);
+        state.world.load_chunk(pos);
+
+        // Look straight down at the chunk from above, leaving the player where it is.
+        state.camera_detached = true;
+        state.camera.position = (8.0, 24.0, 8.0).into();
+        state.camera.pitch = -1.5;
+
+        // Chunks are meshed on worker threads, so keep updating until every section is uploaded.
+        let start = Instant::now();
+        let meshing = |state: &State| {
+            let chunk_renderer = state.geometry_renderer.chunk_renderer();
+            state.world.is_dirty(pos) || chunk_renderer.is_meshing(pos) || chunk_renderer.buffer_count() == 0
+        };
+        while meshing(&state) {
+            assert!(start.elapsed() < Duration::from_secs(10), "the chunk was never meshed");
+            state.update();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        state.render().unwrap();
+        let pixels = state.read_pixels().unwrap();
+        assert_eq!(pixels.len(), 64 * 64 * 4);
+
+        // An empty frame is just the clear color.
+        let first = &pixels[..4];
+        assert!(pixels.chunks(4).any(|pixel| pixel != first), "the frame is empty");
+    }
+
+    // Sections behind the camera aren't drawn, unless frustum culling is off.
+    #[test]
+    fn sections_outside_the_view_are_not_drawn() {
+        let mut state = headless_state();
+
+        state.world = World::new();
+        let pos = ChunkPos::new(0, -1, 0);
+        state.world.load_chunk(pos);
+        state.camera_detached = true;
+        state.camera.position = (8.0, 24.0, 8.0).into();
+        state.camera.pitch = -1.5;
+
+        let start = Instant::now();
+        while state.world.is_dirty(pos) || state.geometry_renderer.chunk_renderer().is_meshing(pos) {
+            assert!(start.elapsed() < Duration::from_secs(10), "the chunk was never meshed");
+            state.update();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(state.geometry_renderer.chunk_renderer().draw_call_count() > 0);
+
+        // Looking straight up, the chunk is behind the camera.
+        state.camera.pitch = 1.5;
+        state.update();
+        assert_eq!(state.geometry_renderer.chunk_renderer().draw_call_count(), 0);
+
+        state.geometry_renderer.chunk_renderer_mut().set_frustum_culling(false);
+        state.update();
+        assert!(state.geometry_renderer.chunk_renderer().draw_call_count() > 0);
+    }
+
+    // Chunks with nothing but air must mesh to nothing and never get a GPU buffer.
+    #[test]
+    fn all_air_chunks_get_no_buffer() {
+        let mut state = headless_state();
+
+        let ground = ChunkPos::new(0, -1, 0);
+        let sky = ChunkPos::new(0, 8, 0);
+        state.world = World::new();
+        state.world.load_chunk(ground);
+        state.world.load_chunk(sky);
+        // The player isn't above these chunks, so don't leave them out for being too far away.
+        state.geometry_renderer.chunk_renderer_mut().set_view_distance(None);
+
+        let chunk = state.world.get_chunk(sky).unwrap();
+        assert_eq!(chunk.is_uniform(), Some(VoxelType::Air));
+
+        let meshes = ChunkMesher::new().generate_mesh(chunk, &state.world.neighbors(sky), sky, 0..CHUNK_SIZE);
+        assert!(meshes.opaque.is_empty() && meshes.transparent.is_empty());
+        for format in [VertexFormat::Standard, VertexFormat::Compact] {
+            assert!(ChunkMeshBuffer::from_mesh(&state.gpu_context.device, &meshes.opaque, format, sky).is_none());
+        }
+
+        // Both chunks go through the renderer; wait until neither is still being meshed.
+        let start = Instant::now();
+        loop {
+            state.update();
+            let chunk_renderer = state.geometry_renderer.chunk_renderer();
+            let meshing = [ground, sky].iter().any(|pos| state.world.is_dirty(*pos) || chunk_renderer.is_meshing(*pos));
+            if !meshing && chunk_renderer.has_buffer(ground) {
+                break;
+            }
+            assert!(start.elapsed() < Duration::from_secs(10), "the chunks were never meshed");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(!state.geometry_renderer.chunk_renderer().has_buffer(sky));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn paused_game_does_not_simulate() {
+        let mut state = headless_state();
+
+        // Falling through empty space.
+        state.world = World::new();
+        state.world.set_generation_features(GenerationFeatures { noise_terrain: false, ..Default::default() });
+        state.world.load_chunk(ChunkPos::new(0, 0, 0));
+        state.player.position = (8.5, 10.0, 8.5).into();
+        let time = state.time_of_day.time();
+
+        state.set_paused(true);
+        assert!(state.is_ui_open());
+        for _ in 0..3 {
+            std::thread::sleep(Duration::from_millis(10));
+            state.update();
+        }
+        assert_eq!(state.player.position, (8.5, 10.0, 8.5).into());
+        assert_eq!(state.time_of_day.time(), time);
+
+        state.set_paused(false);
+        // Longer than a physics step, so at least one runs.
+        std::thread::sleep(Duration::from_millis(20));
+        state.update();
+        assert!(state.player.position.y < 10.0);
+    }
+
+    #[test]
+    fn raising_render_distance_loads_more_chunks() {
+        let center = ChunkPos::new(0, 0, 0);
+        let mut world = World::new();
+        let (min, max) = State::render_region(center, 0);
+        world.load_region(min, max);
+        let near_chunks = world.chunk_count();
+
+        let (min, max) = State::render_region(center, 1);
+        world.load_region(min, max);
+        assert!(world.chunk_count() > near_chunks);
+        assert!(State::far_plane(1) > State::far_plane(0));
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use wgpu::util::DrawIndirectArgs;
+use crate::game::chunk::SectionPos;
+use crate::rendering::mesh::ChunkMeshBuffer;
+use crate::rendering::occlusion::OcclusionCuller;
+
+const ORIGIN_SIZE: wgpu::BufferAddress = std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress;
+const INDIRECT_ARGS_SIZE: wgpu::BufferAddress = std::mem::size_of::<DrawIndirectArgs>() as wgpu::BufferAddress;
+
+// One chunk section's vertices within the batch's vertex buffer.
+struct BatchedChunk {
+    pos: SectionPos,
+    first_vertex: u32,
+    vertex_count: u32,
+}
+
+// Copies of a set of chunk buffers packed into one vertex buffer, so they can be drawn with a
+// single draw call instead of one each. Compact chunks also need their origins, which are
+// packed into an instance buffer and picked per chunk by its draw's first instance.
+pub struct ChunkBatch {
+    label: &'static str,
+    // Whether compact chunks can be drawn with one `multi_draw_indirect`, which needs a
+    // non-zero first instance in indirect draws. Otherwise they're drawn one by one, still
+    // without rebinding buffers in between.
+    multi_draw: bool,
+    chunks: Vec<BatchedChunk>,
+    // The chunk buffers that were copied in, in order. The batch is rebuilt when they change.
+    sources: Vec<(SectionPos, wgpu::Buffer)>,
+    vertex_buffer: Option<wgpu::Buffer>,
+    vertex_bytes: wgpu::BufferAddress,
+    // `None` for standard vertices, which are already in world space.
+    origin_buffer: Option<wgpu::Buffer>,
+    indirect_buffer: Option<wgpu::Buffer>,
+}
+
+impl ChunkBatch {
+    pub fn new(device: &wgpu::Device, label: &'static str) -> Self {
+        Self {
+            label,
+            multi_draw: device.features().contains(wgpu::Features::INDIRECT_FIRST_INSTANCE),
+            chunks: Vec::new(),
+            sources: Vec::new(),
+            vertex_buffer: None,
+            vertex_bytes: 0,
+            origin_buffer: None,
+            indirect_buffer: None,
+        }
+    }
+
+    fn matches(&self, chunks: &[(&SectionPos, &ChunkMeshBuffer)], keep_order: bool) -> bool {
+        if self.sources.len() != chunks.len() {
+            return false;
+        }
+
+        if keep_order {
+            self.sources.iter().zip(chunks).all(|((source_pos, source), (pos, buffer))| {
+                source_pos == *pos && *source == buffer.vertex_buffer
+            })
+        } else {
+            let sources = self.sources.iter().map(|(pos, buffer)| (pos, buffer)).collect::<HashMap<_, _>>();
+            chunks.iter().all(|(pos, buffer)| sources.get(pos) == Some(&&buffer.vertex_buffer))
+        }
+    }
+
+    // Rebuilds the batch from `chunks`, in that order, unless it already holds them. With
+    // `keep_order` off, the same chunks in a different order don't count as a change. Returns
+    // whether the batch can be drawn; it can't if the chunks don't fit in one buffer.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        chunks: &[(&SectionPos, &ChunkMeshBuffer)],
+        keep_order: bool,
+    ) -> bool {
+        if !self.matches(chunks, keep_order) {
+            self.rebuild(device, queue, chunks);
+        }
+        self.is_built()
+    }
+
+    pub fn is_built(&self) -> bool {
+        self.sources.is_empty() || self.vertex_buffer.is_some()
+    }
+
+    fn rebuild(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, chunks: &[(&SectionPos, &ChunkMeshBuffer)]) {
+        self.chunks.clear();
+        self.sources = chunks.iter().map(|(pos, buffer)| (**pos, buffer.vertex_buffer.clone())).collect();
+        self.vertex_bytes = chunks.iter().map(|(_, buffer)| buffer.vertex_bytes()).sum();
+
+        if chunks.is_empty() {
+            return;
+        }
+
+        if self.vertex_bytes > device.limits().max_buffer_size {
+            log::warn!("{} chunks don't fit in one buffer, drawing them separately", self.label);
+            self.vertex_buffer = None;
+            return;
+        }
+
+        let compact = chunks[0].1.origin_offset().is_some();
+        let origin_bytes = chunks.len() as wgpu::BufferAddress * ORIGIN_SIZE;
+        let indirect_bytes = chunks.len() as wgpu::BufferAddress * INDIRECT_ARGS_SIZE;
+
+        let vertex_buffer = Self::reserve(device, &mut self.vertex_buffer, self.label, self.vertex_bytes, wgpu::BufferUsages::VERTEX);
+        let origin_buffer = compact.then(|| Self::reserve(device, &mut self.origin_buffer, self.label, origin_bytes, wgpu::BufferUsages::VERTEX));
+        if !compact {
+            self.origin_buffer = None;
+        }
+        let indirect_buffer = Self::reserve(device, &mut self.indirect_buffer, self.label, indirect_bytes, wgpu::BufferUsages::INDIRECT);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Chunk Batch Encoder"),
+        });
+        let mut offset = 0;
+        let mut first_vertex = 0;
+        let mut indirect_args = Vec::with_capacity(indirect_bytes as usize);
+
+        for (instance, (pos, buffer)) in chunks.iter().enumerate() {
+            let bytes = buffer.vertex_bytes();
+            encoder.copy_buffer_to_buffer(&buffer.vertex_buffer, 0, &vertex_buffer, offset, bytes);
+            if let (Some(origin_buffer), Some(origin_offset)) = (&origin_buffer, buffer.origin_offset()) {
+                let origin_index = instance as wgpu::BufferAddress * ORIGIN_SIZE;
+                encoder.copy_buffer_to_buffer(&buffer.vertex_buffer, origin_offset, origin_buffer, origin_index, ORIGIN_SIZE);
+            }
+
+            let args = DrawIndirectArgs {
+                vertex_count: buffer.vertex_count,
+                instance_count: 1,
+                first_vertex,
+                first_instance: if compact { instance as u32 } else { 0 },
+            };
+            indirect_args.extend_from_slice(args.as_bytes());
+
+            self.chunks.push(BatchedChunk {
+                pos: **pos,
+                first_vertex,
+                vertex_count: buffer.vertex_count,
+            });
+            offset += bytes;
+            first_vertex += buffer.vertex_count;
+        }
+
+        queue.write_buffer(&indirect_buffer, 0, &indirect_args);
+        queue.submit([encoder.finish()]);
+    }
+
+    // Returns a buffer of at least `size` bytes, reusing `slot`'s if it's big enough. New
+    // buffers leave room to grow so a few more chunks don't mean another reallocation.
+    fn reserve(
+        device: &wgpu::Device,
+        slot: &mut Option<wgpu::Buffer>,
+        label: &str,
+        size: wgpu::BufferAddress,
+        usage: wgpu::BufferUsages,
+    ) -> wgpu::Buffer {
+        if let Some(buffer) = slot && buffer.size() >= size {
+            return buffer.clone();
+        }
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: size.next_power_of_two().min(device.limits().max_buffer_size).max(size),
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        *slot = Some(buffer.clone());
+        buffer
+    }
+
+    // Chunks with an occlusion query this frame each need a draw of their own.
+    fn has_queries(&self, occlusion: Option<&OcclusionCuller>) -> bool {
+        occlusion.is_some_and(|occlusion| self.chunks.iter().any(|chunk| occlusion.draw_query(&chunk.pos).is_some()))
+    }
+
+    fn draws_separately(&self, occlusion: Option<&OcclusionCuller>) -> bool {
+        (self.origin_buffer.is_some() && !self.multi_draw) || self.has_queries(occlusion)
+    }
+
+    pub fn draw_call_count(&self, occlusion: Option<&OcclusionCuller>) -> usize {
+        match self.chunks.len() {
+            0 => 0,
+            count if self.draws_separately(occlusion) => count,
+            _ => 1,
+        }
+    }
+
+    // Draws every chunk in the batch, wrapping those with an occlusion query in it.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, occlusion: Option<&OcclusionCuller>) {
+        let Some(vertex_buffer) = self.vertex_buffer.as_ref().filter(|_| !self.chunks.is_empty()) else {
+            return;
+        };
+
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..self.vertex_bytes));
+        if let Some(origin_buffer) = &self.origin_buffer {
+            render_pass.set_vertex_buffer(1, origin_buffer.slice(..));
+        }
+
+        if self.draws_separately(occlusion) {
+            for (instance, chunk) in self.chunks.iter().enumerate() {
+                let vertices = chunk.first_vertex..chunk.first_vertex + chunk.vertex_count;
+                let instance = if self.origin_buffer.is_some() { instance as u32 } else { 0 };
+                match occlusion.and_then(|occlusion| occlusion.draw_query(&chunk.pos)) {
+                    Some(query) => {
+                        render_pass.begin_occlusion_query(query);
+                        render_pass.draw(vertices, instance..instance + 1);
+                        render_pass.end_occlusion_query();
+                    }
+                    None => render_pass.draw(vertices, instance..instance + 1),
+                }
+            }
+        } else if self.origin_buffer.is_some() {
+            let indirect_buffer = self.indirect_buffer.as_ref().expect("built alongside the vertex buffer");
+            render_pass.multi_draw_indirect(indirect_buffer, 0, self.chunks.len() as u32);
+        } else {
+            // Standard chunks are already in world space and packed back to back.
+            let vertex_count = self.chunks.iter().map(|chunk| chunk.vertex_count).sum();
+            render_pass.draw(0..vertex_count, 0..1);
+        }
+    }
+
+    // Bytes of GPU memory the batch holds on to.
+    pub fn size(&self) -> u64 {
+        [&self.vertex_buffer, &self.origin_buffer, &self.indirect_buffer]
+            .into_iter()
+            .flatten()
+            .map(|buffer| buffer.size())
+            .sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.sources.clear();
+        self.vertex_buffer = None;
+        self.vertex_bytes = 0;
+        self.origin_buffer = None;
+        self.indirect_buffer = None;
+    }
+}
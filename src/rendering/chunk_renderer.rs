@@ -1,45 +1,498 @@
-use std::collections::HashMap;
-use crate::game::{world::World, chunk::ChunkPos};
-use crate::rendering::mesh::{ChunkMeshBuffer, ChunkMesher};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use cgmath::MetricSpace;
+use crate::game::{world::World, chunk::{self, Chunk, ChunkNeighbors, ChunkPos, SectionPos, VoxelType, ALL_SECTIONS}};
+use crate::rendering::texture_atlas::FaceDirection;
+use crate::rendering::chunk_batch::ChunkBatch;
+use crate::rendering::frustum::Frustum;
+use crate::rendering::mesh::{ChunkMeshBuffer, ChunkMesher, MeshStrategy, VertexFormat};
+use crate::rendering::mesh_worker::MeshWorkerPool;
+use crate::rendering::occlusion::OcclusionCuller;
 
+// Limits GPU buffer creation per frame so a burst of finished meshes doesn't cause a spike.
+const MAX_UPLOADS_PER_FRAME: usize = 8 * chunk::SECTIONS_PER_CHUNK;
+// Chunks at least this many chunks from the camera's chunk (in any axis) are meshed at the
+// paired LOD step instead of full resolution.
+const LOD_BANDS: [(i32, usize); 2] = [(4, 2), (8, 4)];
+
+// Chunks are meshed, drawn and culled by section, so editing a block only rebuilds the
+// sections around it.
 pub struct ChunkRenderer {
-    mesher: ChunkMesher,
-    buffers: HashMap<ChunkPos, ChunkMeshBuffer>
+    workers: MeshWorkerPool,
+    buffers: HashMap<SectionPos, ChunkMeshBuffer>,
+    transparent_buffers: HashMap<SectionPos, ChunkMeshBuffer>,
+    // `None` when the device doesn't support occlusion queries.
+    occlusion: Option<OcclusionCuller>,
+    occlusion_culling: bool,
+    // Skip sections outside the camera's view volume, as of the last `set_view_frustum`.
+    frustum_culling: bool,
+    frustum: Option<Frustum>,
+    // LOD step each chunk was last meshed at, and the camera chunk those were picked for.
+    lods: HashMap<ChunkPos, usize>,
+    lod_center: Option<ChunkPos>,
+    // Chunks horizontally farther than this from the camera's chunk aren't meshed or drawn.
+    // `None` draws every loaded chunk.
+    view_distance: Option<i32>,
+    // Loaded chunks left unmeshed for being out of view distance, to mesh once they're not.
+    out_of_range: HashSet<ChunkPos>,
+    // Chunks to remesh next time they're dirty even if their mesh looks up to date.
+    forced: HashSet<ChunkPos>,
+    vertex_format: VertexFormat,
+    // Draw the visible chunks from batches instead of one buffer at a time. Each pass falls
+    // back to separate buffers while its batch can't be built.
+    batching: bool,
+    opaque_batch: ChunkBatch,
+    transparent_batch: ChunkBatch,
 }
 
 impl ChunkRenderer {
-    pub fn new() -> Self {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        vertex_format: VertexFormat,
+    ) -> Self {
+        let occlusion = OcclusionCuller::new(device, format, sample_count, camera_bind_group_layout);
+
         Self {
-            mesher: ChunkMesher::new(),
+            workers: MeshWorkerPool::new(ChunkMesher::new()),
             buffers: HashMap::new(),
+            transparent_buffers: HashMap::new(),
+            occlusion_culling: occlusion.is_some(),
+            occlusion,
+            frustum_culling: true,
+            frustum: None,
+            lods: HashMap::new(),
+            lod_center: None,
+            view_distance: None,
+            out_of_range: HashSet::new(),
+            forced: HashSet::new(),
+            vertex_format,
+            batching: true,
+            opaque_batch: ChunkBatch::new(device, "Opaque Chunk Batch"),
+            transparent_batch: ChunkBatch::new(device, "Transparent Chunk Batch"),
+        }
+    }
+
+    pub fn set_view_distance(&mut self, distance: Option<i32>) {
+        if self.view_distance != distance {
+            self.view_distance = distance;
+            // Re-sorts the chunks in and out of range on the next update.
+            self.lod_center = None;
         }
     }
 
-    pub fn update(&mut self, world: &mut World, device: &wgpu::Device) {
-        let dirty_chunks = world.take_dirty_chunks().collect::<Vec<_>>();
+    fn in_view_distance(&self, pos: ChunkPos, center: ChunkPos) -> bool {
+        self.view_distance.is_none_or(|distance| {
+            (pos.x - center.x).abs().max((pos.z - center.z).abs()) <= distance
+        })
+    }
+
+    pub fn mesh_strategy(&self) -> MeshStrategy {
+        self.workers.mesher().strategy()
+    }
 
-        for pos in dirty_chunks {
-            self.remesh_chunk(world, pos, device);
+    // Chunks keep their current meshes until they are next remeshed.
+    pub fn set_mesh_strategy(&mut self, strategy: MeshStrategy) {
+        self.workers.set_mesher(ChunkMesher::with_strategy(strategy));
+    }
+
+    pub fn occlusion_culling(&self) -> bool {
+        self.occlusion_culling
+    }
+
+    // Occlusion culling can only be turned on if the device supports occlusion queries.
+    // Returns whether it is now on.
+    pub fn set_occlusion_culling(&mut self, enabled: bool) -> bool {
+        self.occlusion_culling = enabled && self.occlusion.is_some();
+        if !self.occlusion_culling && let Some(occlusion) = &mut self.occlusion {
+            occlusion.reset();
         }
+        self.occlusion_culling
+    }
+
+    pub fn frustum_culling(&self) -> bool {
+        self.frustum_culling
+    }
+
+    pub fn set_frustum_culling(&mut self, enabled: bool) {
+        self.frustum_culling = enabled;
+    }
+
+    // The view volume sections are culled against, for when the camera moves.
+    pub fn set_view_frustum(&mut self, frustum: Frustum) {
+        self.frustum = Some(frustum);
+    }
+
+    fn active_frustum(&self) -> Option<&Frustum> {
+        self.frustum.as_ref().filter(|_| self.frustum_culling)
+    }
+
+    // Whether a section may be visible: inside the view volume, and not hidden behind other
+    // geometry last time it was tested.
+    fn is_visible(pos: &SectionPos, frustum: Option<&Frustum>, occlusion: Option<&OcclusionCuller>) -> bool {
+        let in_frustum = frustum.is_none_or(|frustum| {
+            let min = pos.min_corner();
+            let size = SectionPos::size();
+            frustum.intersects_box(min.into(), [min[0] + size[0], min[1] + size[1], min[2] + size[2]].into())
+        });
+        in_frustum && !occlusion.is_some_and(|occlusion| occlusion.is_occluded(pos))
+    }
+
+    pub fn batching(&self) -> bool {
+        self.batching
+    }
+
+    pub fn set_batching(&mut self, enabled: bool) {
+        self.batching = enabled;
+        if !enabled {
+            self.opaque_batch.clear();
+            self.transparent_batch.clear();
+        }
+    }
+
+    fn active_occlusion(&self) -> Option<&OcclusionCuller> {
+        self.occlusion.as_ref().filter(|_| self.occlusion_culling)
     }
 
-    fn remesh_chunk(&mut self, world: &World, pos: ChunkPos, device: &wgpu::Device) {
-        if let Some(chunk) = world.get_chunk(pos) {
-            let mesh = self.mesher.generate_mesh(chunk, pos);
+    // Updates which sections are hidden, plans this frame's occlusion queries and rebuilds the
+    // batches if the visible sections changed.
+    pub fn begin_frame(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, camera_position: cgmath::Point3<f32>) {
+        let frustum = self.active_frustum();
+        if self.occlusion_culling {
+            // Sections outside the view volume aren't drawn, so they can't be queried either.
+            let sections = self.buffers.keys()
+                .chain(self.transparent_buffers.keys().filter(|pos| !self.buffers.contains_key(pos)))
+                .filter(|pos| Self::is_visible(pos, frustum, None))
+                .map(|pos| (*pos, self.buffers.contains_key(pos)))
+                .collect::<Vec<_>>();
 
-            if !mesh.is_empty() {
-                if let Some(buffer) = ChunkMeshBuffer::from_mesh(device, &mesh) {
-                    self.buffers.insert(pos, buffer);
+            if let Some(occlusion) = &mut self.occlusion {
+                occlusion.begin_frame(device, queue, &sections, camera_position);
+            }
+        }
+
+        if self.batching {
+            let occlusion = self.occlusion.as_ref().filter(|_| self.occlusion_culling);
+
+            // Only the transparent batch depends on the order, so the opaque one isn't rebuilt
+            // just because the camera moved; its sections stay roughly front-to-back.
+            let frustum = self.frustum.as_ref().filter(|_| self.frustum_culling);
+            let opaque = Self::sorted_visible(&self.buffers, frustum, occlusion, camera_position, true);
+            self.opaque_batch.update(device, queue, &opaque, false);
+
+            let transparent = Self::sorted_visible(&self.transparent_buffers, frustum, occlusion, camera_position, false);
+            self.transparent_batch.update(device, queue, &transparent, true);
+        }
+    }
+
+    // Set on the render pass so `render` can record occlusion queries.
+    pub fn occlusion_query_set(&self) -> Option<&wgpu::QuerySet> {
+        self.active_occlusion().and_then(|occlusion| occlusion.query_set())
+    }
+
+    // Must be called once the render pass has ended.
+    pub fn resolve_occlusion_queries(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.occlusion_culling && let Some(occlusion) = &mut self.occlusion {
+            occlusion.resolve(encoder);
+        }
+    }
+
+    fn lod_step(pos: ChunkPos, center: ChunkPos) -> usize {
+        let distance = (pos.x - center.x).abs()
+            .max((pos.y - center.y).abs())
+            .max((pos.z - center.z).abs());
+
+        LOD_BANDS.iter()
+            .rev()
+            .find(|(min_distance, _)| distance >= *min_distance)
+            .map_or(1, |(_, step)| *step)
+    }
+
+    pub fn update(&mut self, world: &mut World, device: &wgpu::Device, camera_position: cgmath::Point3<f32>) {
+        let center = ChunkPos::from_world_pos(camera_position.x, camera_position.y, camera_position.z);
+        let mut to_mesh = world.take_dirty_chunks().collect::<BTreeMap<_, _>>();
+
+        // Remesh chunks whose distance band changed since the camera last crossed a chunk border,
+        // and those that came into or went out of view distance.
+        if self.lod_center != Some(center) {
+            self.lod_center = Some(center);
+            let moved = self.lods.iter()
+                .filter(|(pos, step)| {
+                    Self::lod_step(**pos, center) != **step || !self.in_view_distance(**pos, center)
+                })
+                .map(|(pos, _)| *pos)
+                .chain(self.out_of_range.iter().copied().filter(|pos| self.in_view_distance(*pos, center)))
+                .collect::<Vec<_>>();
+            for pos in moved {
+                to_mesh.insert(pos, ALL_SECTIONS);
+            }
+        }
+
+        for (pos, sections) in to_mesh {
+            let forced = self.forced.remove(&pos);
+            if !self.in_view_distance(pos, center) {
+                for section in SectionPos::in_mask(pos, ALL_SECTIONS) {
+                    self.buffers.remove(&section);
+                    self.transparent_buffers.remove(&section);
+                }
+                self.lods.remove(&pos);
+                if world.get_chunk(pos).is_some() {
+                    self.out_of_range.insert(pos);
+                }
+                continue;
+            }
+            self.out_of_range.remove(&pos);
+
+            if let Some(chunk) = world.get_chunk_arc(pos) {
+                let step = Self::lod_step(pos, center);
+                // A new LOD step changes every section.
+                let sections = if self.lods.insert(pos, step) == Some(step) { sections } else { ALL_SECTIONS };
+
+                let neighbors = world.neighbors(pos);
+                let hash = self.mesh_hash(&chunk, &neighbors, step);
+                for section in SectionPos::in_mask(pos, sections) {
+                    // An older mesh still in flight would replace the current one, so only skip
+                    // when nothing is.
+                    if !forced && !self.workers.is_pending(section) && self.cached_hash(section) == Some(hash) {
+                        continue;
+                    }
+                    self.workers.dispatch(section, chunk.clone(), neighbors.clone(), step, hash);
                 }
             } else {
-                self.buffers.remove(&pos);
+                self.lods.remove(&pos);
             }
         }
+
+        for result in self.workers.collect(MAX_UPLOADS_PER_FRAME) {
+            if self.out_of_range.contains(&result.pos.chunk) {
+                continue;
+            }
+
+            let meshes = [
+                (&mut self.buffers, &result.meshes.opaque),
+                (&mut self.transparent_buffers, &result.meshes.transparent),
+            ];
+
+            for (buffers, mesh) in meshes {
+                if let Some(mut buffer) = ChunkMeshBuffer::from_mesh(device, mesh, self.vertex_format, result.pos.chunk) {
+                    buffer.content_hash = result.content_hash;
+                    buffers.insert(result.pos, buffer);
+                } else {
+                    buffers.remove(&result.pos);
+                }
+            }
+        }
+    }
+
+    // Hash of everything a chunk's section meshes are built from: its voxels and light, those of
+    // the neighbors it looks into at its borders, and how it's meshed. Sections share their
+    // chunk's hash, so it only tells an untouched section apart from one that was dirtied.
+    fn mesh_hash(&self, chunk: &Chunk, neighbors: &ChunkNeighbors, lod_step: usize) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        chunk.content_hash().hash(&mut hasher);
+        for face in FaceDirection::ALL {
+            neighbors.get(face).map(Chunk::content_hash).hash(&mut hasher);
+        }
+        lod_step.hash(&mut hasher);
+        self.mesh_strategy().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // The hash the section's current buffers were built from. Sections whose mesh came out
+    // empty have no buffer to keep it in, but those are cheap to mesh again.
+    fn cached_hash(&self, pos: SectionPos) -> Option<u64> {
+        self.buffers.get(&pos)
+            .or_else(|| self.transparent_buffers.get(&pos))
+            .map(|buffer| buffer.content_hash)
+    }
+
+    // Checks that every buffer belongs to a loaded chunk with something in it, and that every
+    // chunk with non-air voxels has a buffer. Chunks still waiting to be meshed, or out of view
+    // distance, are skipped.
+    // Logs each mismatch and returns how many were found.
+    pub fn check_consistency(&self, world: &World) -> usize {
+        let mut mismatches = 0;
+
+        let buffer_positions = self.buffers.keys().chain(self.transparent_buffers.keys());
+        for &section in buffer_positions {
+            let pos = section.chunk;
+            if world.is_dirty(pos) || self.workers.is_pending(section) {
+                continue;
+            }
+
+            match world.get_chunk(pos) {
+                None => {
+                    log::warn!("Buffer for unloaded chunk {:?}", pos);
+                    mismatches += 1;
+                }
+                Some(chunk) if chunk.is_uniform() == Some(VoxelType::Air) => {
+                    log::warn!("Buffer for all-air chunk {:?}", pos);
+                    mismatches += 1;
+                }
+                Some(_) => {}
+            }
+        }
+
+        for pos in world.chunk_positions() {
+            if world.is_dirty(pos) || self.is_meshing(pos) || self.out_of_range.contains(&pos) {
+                continue;
+            }
+
+            let has_buffer = self.has_buffer(pos);
+            let is_empty = world.get_chunk(pos).is_some_and(|chunk| chunk.is_uniform() == Some(VoxelType::Air));
+            if !has_buffer && !is_empty {
+                log::warn!("No buffer for non-empty chunk {:?}", pos);
+                mismatches += 1;
+            }
+        }
+
+        mismatches
+    }
+
+    // Whether any of the chunk's sections has an opaque or transparent buffer.
+    pub fn has_buffer(&self, pos: ChunkPos) -> bool {
+        SectionPos::in_mask(pos, ALL_SECTIONS)
+            .any(|section| self.buffers.contains_key(&section) || self.transparent_buffers.contains_key(&section))
+    }
+
+    // Whether any of the chunk's sections is being meshed, so its buffers may be about to change.
+    pub fn is_meshing(&self, pos: ChunkPos) -> bool {
+        SectionPos::in_mask(pos, ALL_SECTIONS).any(|section| self.workers.is_pending(section))
     }
 
-    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
-        for buffers in self.buffers.values() {
-            buffers.draw(render_pass);
+    // Section buffers, opaque and transparent.
+    #[cfg(test)]
+    pub fn buffer_count(&self) -> usize {
+        self.buffers.len() + self.transparent_buffers.len()
+    }
+
+    // Draw calls `render` and `render_transparent` issue for chunk geometry this frame, not
+    // counting occlusion test boxes.
+    pub fn draw_call_count(&self) -> usize {
+        let frustum = self.active_frustum();
+        let occlusion = self.active_occlusion();
+        let passes = [
+            (&self.buffers, &self.opaque_batch, occlusion),
+            (&self.transparent_buffers, &self.transparent_batch, None),
+        ];
+
+        passes.into_iter()
+            .map(|(buffers, batch, queries)| {
+                if self.batching && batch.is_built() {
+                    batch.draw_call_count(queries)
+                } else {
+                    buffers.keys().filter(|pos| Self::is_visible(pos, frustum, occlusion)).count()
+                }
+            })
+            .sum()
+    }
+
+    pub fn vertex_count(&self) -> u64 {
+        self.buffers.values()
+            .chain(self.transparent_buffers.values())
+            .map(|buffer| buffer.vertex_count as u64)
+            .sum()
+    }
+
+    // Bytes of vertex data across all chunk buffers and the batches copied from them.
+    pub fn vertex_memory(&self) -> u64 {
+        self.buffers.values()
+            .chain(self.transparent_buffers.values())
+            .map(|buffer| buffer.size())
+            .sum::<u64>()
+            + self.opaque_batch.size()
+            + self.transparent_batch.size()
+    }
+
+    // Remeshes every loaded chunk, including those whose content hasn't changed, for after
+    // something the content hash doesn't cover (like the texture atlas) changes. Meshing runs
+    // on the workers and uploads keep to the per-frame limit, so this spreads over frames.
+    pub fn force_remesh(&mut self, world: &mut World) {
+        self.forced.extend(world.chunk_positions());
+        world.mark_all_dirty();
+    }
+
+    // Drops all chunk buffers, e.g. when the world is replaced wholesale.
+    pub fn clear(&mut self) {
+        self.workers.cancel_all();
+        self.buffers.clear();
+        self.transparent_buffers.clear();
+        self.lods.clear();
+        self.out_of_range.clear();
+        self.forced.clear();
+        self.opaque_batch.clear();
+        self.transparent_batch.clear();
+    }
+
+    fn distance2(pos: &SectionPos, camera_position: cgmath::Point3<f32>) -> f32 {
+        let (min, size) = (pos.min_corner(), SectionPos::size());
+        let center = cgmath::Point3::new(
+            min[0] + size[0] / 2.0,
+            min[1] + size[1] / 2.0,
+            min[2] + size[2] / 2.0,
+        );
+        center.distance2(camera_position)
+    }
+
+    // The sections in `buffers` that may be visible, nearest first or farthest first.
+    fn sorted_visible<'a>(
+        buffers: &'a HashMap<SectionPos, ChunkMeshBuffer>,
+        frustum: Option<&Frustum>,
+        occlusion: Option<&OcclusionCuller>,
+        camera_position: cgmath::Point3<f32>,
+        front_to_back: bool,
+    ) -> Vec<(&'a SectionPos, &'a ChunkMeshBuffer)> {
+        let mut sorted = buffers.iter()
+            .filter(|(pos, _)| Self::is_visible(pos, frustum, occlusion))
+            .collect::<Vec<_>>();
+        sorted.sort_by(|(a, _), (b, _)| {
+            let order = Self::distance2(a, camera_position).total_cmp(&Self::distance2(b, camera_position));
+            if front_to_back { order } else { order.reverse() }
+        });
+        sorted
+    }
+
+    // Draws opaque geometry front-to-back, so nearer sections fill the depth buffer before the
+    // occlusion queries of farther ones, then tests the bounding boxes of hidden sections. The
+    // occlusion pipeline may be left bound, so callers rebind before drawing anything else.
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        camera_position: cgmath::Point3<f32>,
+    ) {
+        let occlusion = self.active_occlusion();
+        if self.batching && self.opaque_batch.is_built() {
+            self.opaque_batch.draw(render_pass, occlusion);
+        } else {
+            for (pos, buffer) in Self::sorted_visible(&self.buffers, self.active_frustum(), occlusion, camera_position, true) {
+                match occlusion.and_then(|occlusion| occlusion.draw_query(pos)) {
+                    Some(query) => {
+                        render_pass.begin_occlusion_query(query);
+                        buffer.draw(render_pass);
+                        render_pass.end_occlusion_query();
+                    }
+                    None => buffer.draw(render_pass),
+                }
+            }
+        }
+
+        if let Some(occlusion) = occlusion {
+            occlusion.render_boxes(render_pass, camera_bind_group);
         }
     }
-}
\ No newline at end of file
+
+    // Draws transparent geometry back-to-front by section so blending composites correctly.
+    pub fn render_transparent<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_position: cgmath::Point3<f32>) {
+        if self.batching && self.transparent_batch.is_built() {
+            self.transparent_batch.draw(render_pass, None);
+            return;
+        }
+
+        for (_, buffer) in Self::sorted_visible(&self.transparent_buffers, self.active_frustum(), self.active_occlusion(), camera_position, false) {
+            buffer.draw(render_pass);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use cgmath::{Matrix, Matrix4, Point3, SquareMatrix, Vector4};
+
+// The volume a camera can see, taken from its view-projection matrix.
+pub struct Frustum {
+    // Left, right, bottom, top, near, far; each (a, b, c, d) is positive on the inside.
+    planes: [Vector4<f32>; 6],
+    // Numbered like the outline cube's corners: bit 0 = right, 1 = top, 2 = far.
+    corners: [Point3<f32>; 8],
+}
+
+impl Frustum {
+    pub fn from_view_projection(view_projection: Matrix4<f32>) -> Self {
+        let m = view_projection;
+        let rows = [m.row(0), m.row(1), m.row(2), m.row(3)];
+
+        // wgpu clip space has z in 0..w, so the near plane is the bare z row.
+        let planes = [
+            rows[3] + rows[0],
+            rows[3] - rows[0],
+            rows[3] + rows[1],
+            rows[3] - rows[1],
+            rows[2],
+            rows[3] - rows[2],
+        ];
+
+        let inverse = m.invert().unwrap_or_else(Matrix4::identity);
+        let corners = std::array::from_fn(|i| {
+            let ndc = Vector4::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { 0.0 } else { 1.0 },
+                1.0,
+            );
+            let world = inverse * ndc;
+            Point3::new(world.x / world.w, world.y / world.w, world.z / world.w)
+        });
+
+        Self { planes, corners }
+    }
+
+    pub fn corners(&self) -> &[Point3<f32>; 8] {
+        &self.corners
+    }
+
+    // True unless the box is entirely outside one of the planes. Boxes near the frustum's
+    // corners can pass without actually being visible.
+    pub fn intersects_box(&self, min: Point3<f32>, max: Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            // The box corner furthest along the plane's normal.
+            let x = if plane.x >= 0.0 { max.x } else { min.x };
+            let y = if plane.y >= 0.0 { max.y } else { min.y };
+            let z = if plane.z >= 0.0 { max.z } else { min.z };
+            plane.x * x + plane.y * y + plane.z * z + plane.w >= 0.0
+        })
+    }
+}
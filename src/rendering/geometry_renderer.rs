@@ -1,21 +1,54 @@
 use wgpu::{BindGroup, RenderPipeline, TextureView};
 use crate::game::world::World;
-use crate::rendering;
 use crate::rendering::chunk_renderer::ChunkRenderer;
-use crate::rendering::texture::Texture;
+use crate::rendering::depth_view::DepthView;
+use crate::rendering::lighting::Lighting;
+use crate::rendering::mesh::VertexFormat;
+use crate::rendering::frustum::Frustum;
+use crate::rendering::outline_renderer::{OutlineBox, OutlineRenderer};
+use crate::rendering::texture::{RenderTargetDesc, Texture};
+
+enum PipelineKind {
+    Opaque,
+    Transparent,
+    Wireframe,
+}
+
+// Settings the geometry pipelines are built with. Changing them means recreating the renderer.
+#[derive(Copy, Clone, Debug)]
+pub struct PipelineOptions {
+    pub vertex_format: VertexFormat,
+    // Draw both sides of transparent faces, so the far walls of a glass box show from inside
+    // it. Costs fill rate wherever there's a lot of water or glass.
+    pub transparent_backfaces: bool,
+}
 
 pub struct GeometryRenderer {
     chunk_renderer: ChunkRenderer,
+    outline_renderer: OutlineRenderer,
     render_pipeline: RenderPipeline,
+    transparent_pipeline: RenderPipeline,
+    // `None` when the device lacks `POLYGON_MODE_LINE`.
+    wireframe_pipeline: Option<RenderPipeline>,
+    wireframe: bool,
     depth_texture: Texture,
+    depth_view: DepthView,
+    show_depth: bool,
+    // 1 renders straight into the surface; otherwise geometry is drawn into `msaa_texture`
+    // and resolved into the surface.
+    sample_count: u32,
+    msaa_texture: Option<Texture>,
 }
 
 impl GeometryRenderer {
     pub fn new(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
+        target: RenderTargetDesc,
+        sample_count: u32,
         texture_bind_group_layout: &wgpu::BindGroupLayout,
         camera_bind_group_layout: &wgpu::BindGroupLayout,
+        lighting_bind_group_layout: &wgpu::BindGroupLayout,
+        options: PipelineOptions,
     ) -> Self {
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Shader"),
@@ -26,30 +59,110 @@ impl GeometryRenderer {
             &wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
                 bind_group_layouts: &[
-                    &texture_bind_group_layout,
-                    &camera_bind_group_layout,
+                    texture_bind_group_layout,
+                    camera_bind_group_layout,
+                    lighting_bind_group_layout,
                 ],
                 push_constant_ranges: &[],
             }
         );
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
+        let render_pipeline = Self::create_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            target.format,
+            sample_count,
+            options,
+            PipelineKind::Opaque,
+        );
+
+        let transparent_pipeline = Self::create_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            target.format,
+            sample_count,
+            options,
+            PipelineKind::Transparent,
+        );
+
+        let wireframe_pipeline = device.features().contains(wgpu::Features::POLYGON_MODE_LINE).then(|| {
+            Self::create_pipeline(
+                device,
+                &render_pipeline_layout,
+                &shader,
+                target.format,
+                sample_count,
+                options,
+                PipelineKind::Wireframe,
+            )
+        });
+
+        let depth_texture = Texture::create_depth_texture(device, target, sample_count, "depth_texture");
+        let msaa_texture = Self::create_msaa_texture(device, target, sample_count);
+        let depth_view = DepthView::new(device, target.format, &depth_texture.view, sample_count);
+
+        let chunk_renderer = ChunkRenderer::new(device, target.format, sample_count, camera_bind_group_layout, options.vertex_format);
+        let outline_renderer = OutlineRenderer::new(device, target.format, sample_count, camera_bind_group_layout);
+
+        GeometryRenderer {
+            chunk_renderer,
+            outline_renderer,
+            render_pipeline,
+            transparent_pipeline,
+            wireframe_pipeline,
+            wireframe: false,
+            depth_texture,
+            depth_view,
+            show_depth: false,
+            sample_count,
+            msaa_texture,
+        }
+    }
+
+    fn create_msaa_texture(device: &wgpu::Device, target: RenderTargetDesc, sample_count: u32) -> Option<Texture> {
+        (sample_count > 1).then(|| Texture::create_multisampled_texture(device, target, sample_count, "msaa_texture"))
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        options: PipelineOptions,
+        kind: PipelineKind,
+    ) -> RenderPipeline {
+        // Transparent geometry blends over what is already drawn and must not hide
+        // transparent faces behind it, so it tests depth without writing it.
+        // The wireframe draws every triangle's edges, back faces included, for debugging meshes.
+        let (label, blend, depth_write_enabled) = match kind {
+            PipelineKind::Opaque => ("Render Pipeline", wgpu::BlendState::REPLACE, true),
+            PipelineKind::Transparent => ("Transparent Render Pipeline", wgpu::BlendState::ALPHA_BLENDING, false),
+            PipelineKind::Wireframe => ("Wireframe Render Pipeline", wgpu::BlendState::REPLACE, true),
+        };
+        let (cull_mode, polygon_mode) = match kind {
+            PipelineKind::Wireframe => (None, wgpu::PolygonMode::Line),
+            PipelineKind::Transparent if options.transparent_backfaces => (None, wgpu::PolygonMode::Fill),
+            _ => (Some(wgpu::Face::Back), wgpu::PolygonMode::Fill),
+        };
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
             vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[
-                    rendering::mesh::Vertex::desc(),
-                ],
+                module: shader,
+                entry_point: Some(options.vertex_format.vertex_entry_point()),
+                buffers: &options.vertex_format.buffer_layouts(),
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
             },
             fragment: Some(wgpu::FragmentState {
-                module: &shader,
+                module: shader,
                 entry_point: Some("fs_main"),
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
+                    format,
+                    blend: Some(blend),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
@@ -58,70 +171,119 @@ impl GeometryRenderer {
                 topology: wgpu::PrimitiveTopology::TriangleList,
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
+                cull_mode,
+                polygon_mode,
                 unclipped_depth: false,
                 conservative: false,
             },
             depth_stencil: Some(wgpu::DepthStencilState {
                 format: Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
+                depth_write_enabled,
                 depth_compare: wgpu::CompareFunction::Less,
                 stencil: wgpu::StencilState::default(),
                 bias: wgpu::DepthBiasState::default(),
             }),
             multisample: wgpu::MultisampleState {
-                count: 1,
+                count: sample_count,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
             multiview: None,
             cache: None,
-        });
+        })
+    }
 
-        let depth_texture = Texture::create_depth_texture(&device, &config, "depth_texture");
+    // Typically used when resizing a window.
+    pub fn recreate_render_targets(&mut self, device: &wgpu::Device, target: RenderTargetDesc) {
+        self.depth_texture = Texture::create_depth_texture(device, target, self.sample_count, "depth_texture");
+        self.depth_view.set_depth_texture(device, &self.depth_texture.view);
+        self.msaa_texture = Self::create_msaa_texture(device, target, self.sample_count);
+    }
 
-        let chunk_renderer = ChunkRenderer::new();
+    pub fn wireframe(&self) -> bool {
+        self.wireframe
+    }
 
-        GeometryRenderer {
-            chunk_renderer,
-            render_pipeline,
-            depth_texture,
-        }
+    // Wireframe can only be turned on if the device supports line polygon mode. Returns
+    // whether it is now on.
+    pub fn set_wireframe(&mut self, enabled: bool) -> bool {
+        self.wireframe = enabled && self.wireframe_pipeline.is_some();
+        self.wireframe
     }
 
-    // Typically used when resizing a window.
-    pub fn recreate_depth_texture(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
-        self.depth_texture = Texture::create_depth_texture(device, config, "depth_texture");
+    pub fn show_depth(&self) -> bool {
+        self.show_depth
+    }
+
+    // Replaces the frame with the depth buffer in grayscale.
+    pub fn set_show_depth(&mut self, show: bool) {
+        self.show_depth = show;
+    }
+
+    // The near and far planes the depth view linearizes depth with.
+    pub fn set_depth_range(&self, queue: &wgpu::Queue, znear: f32, zfar: f32) {
+        self.depth_view.set_range(queue, znear, zfar);
+    }
+
+    pub fn chunk_renderer(&self) -> &ChunkRenderer {
+        &self.chunk_renderer
     }
 
     pub fn chunk_renderer_mut(&mut self) -> &mut ChunkRenderer {
         &mut self.chunk_renderer
     }
 
-    pub fn update_chunk_renderer(&mut self, world: &mut World, device: &wgpu::Device) {
-        self.chunk_renderer.update(world, device);
+    // Draw calls issued by `render` with the current buffers.
+    pub fn draw_call_count(&self) -> usize {
+        self.chunk_renderer.draw_call_count() + self.outline_renderer.draw_call_count()
+    }
+
+    pub fn update_chunk_renderer(
+        &mut self,
+        world: &mut World,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        camera_position: cgmath::Point3<f32>,
+    ) {
+        self.chunk_renderer.update(world, device, camera_position);
+        self.chunk_renderer.begin_frame(device, queue, camera_position);
+    }
+
+    // Replaces the set of outlined boxes (the selected block, region previews, ...).
+    pub fn set_outlines(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, boxes: &[OutlineBox]) {
+        self.outline_renderer.set_boxes(device, queue, boxes);
+    }
+
+    // Outlines a camera's view volume, or hides it with `None`.
+    pub fn set_frustum_outline(&mut self, queue: &wgpu::Queue, frustum: Option<&Frustum>) {
+        self.outline_renderer.set_frustum(queue, frustum);
+    }
+
+    pub fn set_outline_depth_bias(&self, queue: &wgpu::Queue, depth_bias: f32) {
+        self.outline_renderer.set_depth_bias(queue, depth_bias);
+    }
+
+    pub fn set_debug_line(&mut self, queue: &wgpu::Queue, line: Option<[cgmath::Point3<f32>; 2]>) {
+        self.outline_renderer.set_line(queue, line);
     }
 
-    pub fn render<'rpass>(
-        &'rpass self,
-        view: &'rpass TextureView,
+    pub fn render(
+        &mut self,
+        view: &TextureView,
         encoder: &mut wgpu::CommandEncoder,
-        diffuse_bind_group: &'rpass BindGroup,
-        camera_bind_group: &'rpass BindGroup,
+        diffuse_bind_group: &BindGroup,
+        camera_bind_group: &BindGroup,
+        lighting: &Lighting,
+        camera_position: cgmath::Point3<f32>,
     ) {
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
+                view: self.msaa_texture.as_ref().map_or(view, |texture| &texture.view),
+                resolve_target: self.msaa_texture.as_ref().map(|_| view),
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.1,
-                        g: 0.2,
-                        b: 0.3,
-                        a: 1.0,
-                    }),
+                    // The sky is the fog color so distant geometry fades into it.
+                    load: wgpu::LoadOp::Clear(lighting.clear_color()),
                     store: wgpu::StoreOp::Store,
                 },
                 depth_slice: None,
@@ -134,14 +296,33 @@ impl GeometryRenderer {
                 }),
                 stencil_ops: None,
             }),
-            occlusion_query_set: None,
+            occlusion_query_set: self.chunk_renderer.occlusion_query_set(),
             timestamp_writes: None,
         });
 
-        render_pass.set_pipeline(&self.render_pipeline);
+        let wireframe_pipeline = self.wireframe_pipeline.as_ref().filter(|_| self.wireframe);
+
+        render_pass.set_pipeline(wireframe_pipeline.unwrap_or(&self.render_pipeline));
         render_pass.set_bind_group(0, diffuse_bind_group, &[]);
         render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &lighting.bind_group, &[]);
 
-        self.chunk_renderer.render(&mut render_pass);
+        self.chunk_renderer.render(&mut render_pass, camera_bind_group, camera_position);
+
+        // The occlusion pass may have replaced the bind groups.
+        render_pass.set_pipeline(wireframe_pipeline.unwrap_or(&self.transparent_pipeline));
+        render_pass.set_bind_group(0, diffuse_bind_group, &[]);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &lighting.bind_group, &[]);
+        self.chunk_renderer.render_transparent(&mut render_pass, camera_position);
+
+        self.outline_renderer.render(&mut render_pass, camera_bind_group);
+        drop(render_pass);
+
+        self.chunk_renderer.resolve_occlusion_queries(encoder);
+
+        if self.show_depth {
+            self.depth_view.render(encoder, view);
+        }
     }
 }
\ No newline at end of file
@@ -1,31 +1,114 @@
 use std::sync::Arc;
+use serde::{Deserialize, Serialize};
 use winit::window::Window;
+use crate::rendering::texture::Texture;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendPreference {
+    // Whichever of Vulkan, DX12 and Metal the platform has.
+    #[default]
+    Auto,
+    Vulkan,
+    Dx12,
+    Metal,
+    Gl,
+}
+
+impl BackendPreference {
+    fn backends(self) -> wgpu::Backends {
+        match self {
+            BackendPreference::Auto => wgpu::Backends::PRIMARY,
+            BackendPreference::Vulkan => wgpu::Backends::VULKAN,
+            BackendPreference::Dx12 => wgpu::Backends::DX12,
+            BackendPreference::Metal => wgpu::Backends::METAL,
+            BackendPreference::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerPreference {
+    #[default]
+    Default,
+    HighPerformance,
+    LowPower,
+}
+
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(preference: PowerPreference) -> Self {
+        match preference {
+            PowerPreference::Default => wgpu::PowerPreference::None,
+            PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+            PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+        }
+    }
+}
+
+// Which GPU to render with. The standard `WGPU_BACKEND` (e.g. `vulkan`) and `WGPU_POWER_PREF`
+// (`high` or `low`) environment variables override these, which is handy for reproducing
+// driver-specific issues without editing the settings file.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdapterOptions {
+    pub backend: BackendPreference,
+    pub power_preference: PowerPreference,
+}
 
 pub struct GpuContext {
-    pub instance: wgpu::Instance,
     pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
 }
 
 impl GpuContext {
-    pub async fn new(window: Arc<Window>) -> anyhow::Result<(Self, wgpu::Surface<'static>)> {
-        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY,
-            ..Default::default()
-        });
+    pub async fn new(window: Arc<Window>, options: AdapterOptions) -> anyhow::Result<(Self, wgpu::Surface<'static>)> {
+        let (context, surface) = Self::create(Some(&window), options).await?;
+        Ok((context, surface.expect("a surface is created for every window")))
+    }
 
-        let surface = instance.create_surface(window.clone()).unwrap();
+    // A context that isn't tied to any window, for rendering offscreen.
+    pub async fn new_headless(options: AdapterOptions) -> anyhow::Result<Self> {
+        let (context, _) = Self::create(None, options).await?;
+        Ok(context)
+    }
 
-        let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::default(),
-            compatible_surface: Some(&surface),
-            force_fallback_adapter: false,
-        }).await?;
+    async fn create(
+        window: Option<&Arc<Window>>,
+        options: AdapterOptions,
+    ) -> anyhow::Result<(Self, Option<wgpu::Surface<'static>>)> {
+        let backends = wgpu::Backends::from_env().unwrap_or(options.backend.backends());
+        let power_preference = wgpu::PowerPreference::from_env().unwrap_or(options.power_preference.into());
+
+        // Fall back to the platform's usual backends if the requested one has no adapter. Without
+        // a window any backend will do, such as a GL software renderer on a machine with no GPU.
+        let fallback = if window.is_some() { wgpu::Backends::PRIMARY } else { wgpu::Backends::all() };
+
+        let (surface, adapter) = match Self::request_adapter(window, backends, power_preference).await {
+            Ok(found) => found,
+            Err(e) if backends != fallback => {
+                log::warn!("No adapter for backends {:?} ({}), falling back to {:?}", backends, e, fallback);
+                Self::request_adapter(window, fallback, power_preference).await?
+            }
+            Err(e) => return Err(e),
+        };
+
+        let info = adapter.get_info();
+        log::info!(
+            "Using adapter {} ({:?}, {:?}, driver {} {})",
+            info.name,
+            info.backend,
+            info.device_type,
+            info.driver,
+            info.driver_info,
+        );
 
         let (device, queue) = adapter.request_device(&wgpu::DeviceDescriptor {
             label: None,
-            required_features: wgpu::Features::empty(),
+            // Optional, for the wireframe debug view and drawing batched compact chunks with one
+            // indirect draw.
+            required_features: adapter.features() & (wgpu::Features::POLYGON_MODE_LINE | wgpu::Features::INDIRECT_FIRST_INSTANCE),
             experimental_features: wgpu::ExperimentalFeatures::disabled(),
             required_limits: wgpu::Limits::default(),
             memory_hints: Default::default(),
@@ -33,7 +116,6 @@ impl GpuContext {
         }).await?;
 
         let context = Self {
-            instance,
             adapter,
             device,
             queue,
@@ -41,4 +123,42 @@ impl GpuContext {
 
         Ok((context, surface))
     }
+
+    // 4x MSAA if the adapter supports it for both the color format and the depth format,
+    // otherwise 1 (no MSAA).
+    pub fn msaa_sample_count(&self, format: wgpu::TextureFormat) -> u32 {
+        const SAMPLES: u32 = 4;
+
+        let supported = [format, Texture::DEPTH_FORMAT].into_iter().all(|format| {
+            self.adapter.get_texture_format_features(format).flags.sample_count_supported(SAMPLES)
+        });
+
+        if supported {
+            SAMPLES
+        } else {
+            log::warn!("{}x MSAA isn't supported for {:?}, rendering without it", SAMPLES, format);
+            1
+        }
+    }
+
+    async fn request_adapter(
+        window: Option<&Arc<Window>>,
+        backends: wgpu::Backends,
+        power_preference: wgpu::PowerPreference,
+    ) -> anyhow::Result<(Option<wgpu::Surface<'static>>, wgpu::Adapter)> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends,
+            ..Default::default()
+        });
+
+        let surface = window.map(|window| instance.create_surface(window.clone())).transpose()?;
+
+        let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference,
+            compatible_surface: surface.as_ref(),
+            force_fallback_adapter: false,
+        }).await?;
+
+        Ok((surface, adapter))
+    }
 }
\ No newline at end of file
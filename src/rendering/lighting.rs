@@ -0,0 +1,268 @@
+use cgmath::{InnerSpace, MetricSpace};
+use serde::Deserialize;
+use wgpu::util::DeviceExt;
+use crate::game::chunk::VoxelType;
+use crate::rendering::mesh::{BLOCK_NON_SOLID, BLOCK_TRANSPARENT};
+
+// Speeds (m/s) over which the speed fog goes from nothing to full: about sprinting speed up to a
+// long fall.
+const SPEED_FOG_MIN_SPEED: f32 = 6.0;
+const SPEED_FOG_MAX_SPEED: f32 = 25.0;
+// How quickly the speed fog follows the player's speed, in 1/s.
+const SPEED_FOG_EASE_RATE: f32 = 4.0;
+
+// At night ambient light drops to this fraction of its daytime value, and the sky and fog fade
+// to this color.
+const NIGHT_AMBIENT_SCALE: f32 = 0.3;
+const NIGHT_SKY_COLOR: [f32; 3] = [0.01, 0.01, 0.03];
+
+// Most point lights the shader applies at once. Must match `MAX_POINT_LIGHTS` in shader.wgsl.
+pub const MAX_POINT_LIGHTS: usize = 8;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct PointLightUniform {
+    position: [f32; 3],
+    range: f32,
+    color: [f32; 3],
+    _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightingUniform {
+    sun_direction: [f32; 3],
+    ambient: f32,
+    highlight_seams: u32,
+    speed_factor: f32,
+    sun_intensity: f32,
+    highlight_blocks: u32,
+    fog_color: [f32; 3],
+    fog_start: f32,
+    camera_position: [f32; 3],
+    fog_end: f32,
+    point_light_count: u32,
+    _padding2: [u32; 3],
+    point_lights: [PointLightUniform; MAX_POINT_LIGHTS],
+}
+
+// A dynamic light, separate from the block light baked into chunk meshes. It lights faces
+// turned towards it, fading out to nothing at `range` blocks away. It doesn't cast shadows.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PointLight {
+    pub position: cgmath::Point3<f32>,
+    pub color: [f32; 3],
+    pub range: f32,
+}
+
+// Debug view tinting the faces of every block that isn't solid, or every transparent block,
+// to check the block table at a glance.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockHighlight {
+    #[default]
+    Off,
+    NonSolid,
+    Transparent,
+}
+
+impl BlockHighlight {
+    // The vertex `block_flags` bit of the blocks to tint.
+    fn flags(self) -> u32 {
+        match self {
+            BlockHighlight::Off => 0,
+            BlockHighlight::NonSolid => BLOCK_NON_SOLID,
+            BlockHighlight::Transparent => BLOCK_TRANSPARENT,
+        }
+    }
+
+    // Whether the view tints `voxel`.
+    pub fn matches(self, voxel: VoxelType) -> bool {
+        match self {
+            BlockHighlight::Off => false,
+            BlockHighlight::NonSolid => !voxel.is_solid(),
+            BlockHighlight::Transparent => voxel.is_transparent(),
+        }
+    }
+}
+
+// Linear distance fog: geometry fades to the sky color between `start` and `end` blocks from
+// the camera, so the far edge blends into the sky. `color` is the daytime sky color.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Fog {
+    pub color: [f32; 3],
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            color: [0.1, 0.2, 0.3],
+            start: 64.0,
+            end: 128.0,
+        }
+    }
+}
+
+// Directional sunlight shared by all geometry. `sun_direction` points from the sun into the
+// world; faces get `max(dot(normal, -sun_direction), ambient)` of their texture color.
+pub struct Lighting {
+    pub sun_direction: cgmath::Vector3<f32>,
+    pub ambient: f32,
+    // Debug view tinting chunk-border faces that face an unloaded chunk.
+    pub highlight_seams: bool,
+    pub highlight_blocks: BlockHighlight,
+    pub fog: Fog,
+    // From 0.0 at night to 1.0 during the day. Scales the sun and ambient light and fades the
+    // sky from the fog color to a night sky.
+    pub daylight: f32,
+    // Stylistic option pulling the fog closer while moving fast. `speed_factor` eases between
+    // 0 (slow) and 1 (very fast); the shader pulls the fog in by up to 40%.
+    pub speed_fog: bool,
+    speed_factor: f32,
+    // Only the `MAX_POINT_LIGHTS` nearest the camera are drawn.
+    pub point_lights: Vec<PointLight>,
+    // Light carried by the player, such as a held light-emitting block. Drawn like the other
+    // point lights.
+    pub held_light: Option<PointLight>,
+
+    buffer: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Lighting {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let sun_direction = cgmath::Vector3::new(-0.3, -1.0, -0.5).normalize();
+        let ambient = 0.4;
+        let fog = Fog::default();
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Lighting Buffer"),
+            contents: bytemuck::cast_slice(&[LightingUniform {
+                sun_direction: sun_direction.into(),
+                ambient,
+                highlight_seams: 0,
+                speed_factor: 0.0,
+                sun_intensity: 1.0,
+                highlight_blocks: 0,
+                fog_color: fog.color,
+                fog_start: fog.start,
+                camera_position: [0.0; 3],
+                fog_end: fog.end,
+                point_light_count: 0,
+                _padding2: [0; 3],
+                point_lights: [PointLightUniform::default(); MAX_POINT_LIGHTS],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("lighting_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }
+            ],
+            label: Some("lighting_bind_group"),
+        });
+
+        Self {
+            sun_direction,
+            ambient,
+            highlight_seams: false,
+            highlight_blocks: BlockHighlight::Off,
+            fog,
+            daylight: 1.0,
+            speed_fog: false,
+            speed_factor: 0.0,
+            point_lights: Vec::new(),
+            held_light: None,
+            buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    pub fn set_sun_direction(&mut self, direction: cgmath::Vector3<f32>) {
+        self.sun_direction = direction.normalize();
+    }
+
+    pub fn update_speed_factor(&mut self, speed: f32, dt: f32) {
+        let target = if self.speed_fog {
+            ((speed - SPEED_FOG_MIN_SPEED) / (SPEED_FOG_MAX_SPEED - SPEED_FOG_MIN_SPEED)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        let t = (SPEED_FOG_EASE_RATE * dt).min(1.0);
+        self.speed_factor += (target - self.speed_factor) * t;
+    }
+
+    // The sky's color where it meets the ground. The sky is a single color for now, so this is
+    // the whole sky; a gradient sky should keep its horizon at this color.
+    pub fn horizon_color(&self) -> [f32; 3] {
+        std::array::from_fn(|i| NIGHT_SKY_COLOR[i] + (self.fog.color[i] - NIGHT_SKY_COLOR[i]) * self.daylight)
+    }
+
+    // Always the horizon color, so distant geometry fades into the sky behind it at any time
+    // of day.
+    pub fn fog_color(&self) -> [f32; 3] {
+        self.horizon_color()
+    }
+
+    pub fn clear_color(&self) -> wgpu::Color {
+        let [r, g, b] = self.horizon_color().map(f64::from);
+        wgpu::Color { r, g, b, a: 1.0 }
+    }
+
+    pub fn update_buffer(&self, queue: &wgpu::Queue, camera_position: cgmath::Point3<f32>) {
+        let ambient_scale = NIGHT_AMBIENT_SCALE + (1.0 - NIGHT_AMBIENT_SCALE) * self.daylight;
+
+        let mut nearest = self.point_lights.iter().chain(&self.held_light).collect::<Vec<_>>();
+        nearest.sort_by(|a, b| a.position.distance2(camera_position).total_cmp(&b.position.distance2(camera_position)));
+        let mut point_lights = [PointLightUniform::default(); MAX_POINT_LIGHTS];
+        for (uniform, light) in point_lights.iter_mut().zip(&nearest) {
+            *uniform = PointLightUniform {
+                position: light.position.into(),
+                range: light.range,
+                color: light.color,
+                _padding: 0,
+            };
+        }
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[LightingUniform {
+            sun_direction: self.sun_direction.into(),
+            ambient: self.ambient * ambient_scale,
+            highlight_seams: self.highlight_seams as u32,
+            speed_factor: self.speed_factor,
+            sun_intensity: self.daylight,
+            highlight_blocks: self.highlight_blocks.flags(),
+            fog_color: self.fog_color(),
+            fog_start: self.fog.start,
+            camera_position: camera_position.into(),
+            fog_end: self.fog.end,
+            point_light_count: nearest.len().min(MAX_POINT_LIGHTS) as u32,
+            _padding2: [0; 3],
+            point_lights,
+        }]));
+    }
+}
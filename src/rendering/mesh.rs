@@ -1,14 +1,33 @@
-use wgpu::BindingType::Texture;
+use std::ops::Range;
+use serde::{Deserialize, Serialize};
 use wgpu::util::DeviceExt;
-use crate::game::chunk::{Chunk, ChunkPos, VoxelType, CHUNK_SIZE};
+use crate::game::chunk::{Chunk, ChunkNeighbors, ChunkPos, VoxelType, CHUNK_SIZE};
+use crate::game::lighting::MAX_LIGHT;
 use crate::rendering::texture_atlas::{TextureAtlas, FaceDirection};
 
+// How far below the top of its block a liquid's surface is drawn. Must match `LIQUID_DROP` in
+// shader.wgsl.
+pub const LIQUID_DROP: f32 = 0.125;
+
+// Bits of `Vertex::block_flags`. Must match shader.wgsl.
+pub const BLOCK_NON_SOLID: u32 = 1;
+pub const BLOCK_TRANSPARENT: u32 = 2;
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
     pub normal: [f32; 3],
+    // UV of the top-left corner of the block's tile in the atlas.
+    pub tile: [f32; 2],
+    // Light level of the voxel the face looks into, from 0.0 (dark) to 1.0 (full sky light).
+    pub light: f32,
+    // 1.0 for faces on the chunk border drawn only because the neighboring chunk isn't loaded.
+    pub seam: f32,
+    // `BLOCK_NON_SOLID` and `BLOCK_TRANSPARENT` for the face's block, for the block highlight
+    // debug view.
+    pub block_flags: u32,
 }
 
 impl Vertex {
@@ -33,9 +52,117 @@ impl Vertex {
                     shader_location: 2,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 10]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Uint32,
+                },
+            ],
+        }
+    }
+}
+
+// `Vertex` packed into 8 bytes, relative to its chunk's origin. Chunk meshes only have
+// integer positions (apart from liquid surfaces, `LIQUID_DROP` below one) and tile-sized
+// texture coordinates within 0..=CHUNK_SIZE, so these fit in a few bits each:
+//   word 0: x, y, z, u, v (5 bits each), normal index (3 bits, `FaceDirection::ALL` order),
+//           seam (1 bit), lowered by `LIQUID_DROP` (1 bit)
+//   word 1: tile column, tile row (4 bits each), light level (4 bits), block flags (2 bits)
+// Must match `vs_compact` in shader.wgsl.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CompactVertex {
+    pub packed: [u32; 2],
+}
+
+impl CompactVertex {
+    // Must match the tile count passed to TextureAtlas::new.
+    const ATLAS_TILES: f32 = 16.0;
+
+    pub fn pack(vertex: &Vertex, origin: [f32; 3]) -> Self {
+        let lowered = {
+            let y = vertex.position[1] - origin[1];
+            (y - y.round()).abs() > 0.01
+        };
+        let local = std::array::from_fn::<u32, 3, _>(|i| {
+            let c = vertex.position[i] - origin[i];
+            if i == 1 && lowered { (c + LIQUID_DROP).round() as u32 } else { c.round() as u32 }
+        });
+        debug_assert!(local.iter().all(|c| *c <= CHUNK_SIZE as u32), "vertex {:?} outside its chunk", vertex.position);
+
+        let [u, v] = vertex.tex_coords.map(|c| c as u32);
+        let normal = FaceDirection::ALL.iter()
+            .position(|face| face.normal() == vertex.normal)
+            .unwrap_or(0) as u32;
+        let seam = (vertex.seam > 0.5) as u32;
+        let [tile_x, tile_y] = vertex.tile.map(|c| (c * Self::ATLAS_TILES).round() as u32);
+        let light = (vertex.light * MAX_LIGHT as f32).round() as u32;
+
+        Self {
+            packed: [
+                local[0] | local[1] << 5 | local[2] << 10 | u << 15 | v << 20 | normal << 25 | seam << 28 | (lowered as u32) << 29,
+                tile_x | tile_y << 4 | light << 8 | vertex.block_flags << 12,
             ],
         }
     }
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<CompactVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &wgpu::vertex_attr_array![0 => Uint32x2],
+        }
+    }
+
+    // The chunk origin compact vertices are offset by, one per draw (or per chunk in a batch).
+    pub fn origin_desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &wgpu::vertex_attr_array![1 => Float32x3],
+        }
+    }
+}
+
+// How chunk vertices are stored on the GPU. Meshing always produces `Vertex`; compact buffers
+// are packed when uploaded, at 1/6 the size.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VertexFormat {
+    #[default]
+    Standard,
+    Compact,
+}
+
+impl VertexFormat {
+    pub fn buffer_layouts(self) -> Vec<wgpu::VertexBufferLayout<'static>> {
+        match self {
+            VertexFormat::Standard => vec![Vertex::desc()],
+            VertexFormat::Compact => vec![CompactVertex::desc(), CompactVertex::origin_desc()],
+        }
+    }
+
+    pub fn vertex_entry_point(self) -> &'static str {
+        match self {
+            VertexFormat::Standard => "vs_main",
+            VertexFormat::Compact => "vs_compact",
+        }
+    }
 }
 
 pub struct ChunkMesh {
@@ -51,16 +178,44 @@ impl ChunkMesh {
         self.vertices.is_empty()
     }
 
-    pub fn clear(&mut self) {
-        self.vertices.clear();
-    }
-
     pub fn vertex_count(&self) -> u32 {
         self.vertices.len() as u32
     }
 }
 
+// A chunk's geometry split by render pass.
+pub struct ChunkMeshes {
+    pub opaque: ChunkMesh,
+    pub transparent: ChunkMesh,
+}
+
+impl ChunkMeshes {
+    pub fn new() -> Self {
+        Self {
+            opaque: ChunkMesh::new(),
+            transparent: ChunkMesh::new(),
+        }
+    }
+
+    fn for_voxel(&mut self, voxel: VoxelType) -> &mut ChunkMesh {
+        if voxel.is_transparent() {
+            &mut self.transparent
+        } else {
+            &mut self.opaque
+        }
+    }
+}
+
 impl FaceDirection {
+    pub const ALL: [FaceDirection; 6] = [
+        FaceDirection::North,
+        FaceDirection::South,
+        FaceDirection::East,
+        FaceDirection::West,
+        FaceDirection::Top,
+        FaceDirection::Bottom,
+    ];
+
     pub fn normal(&self) -> [f32; 3] {
         match self {
             FaceDirection::North => [0.0, 0.0, 1.0],
@@ -72,183 +227,518 @@ impl FaceDirection {
         }
     }
 
-    pub fn vertices(&self, x: f32, y: f32, z: f32) -> [[f32; 3]; 6] {
+    pub fn offset(&self) -> (i32, i32, i32) {
+        match self {
+            FaceDirection::North => (0, 0, 1),
+            FaceDirection::South => (0, 0, -1),
+            FaceDirection::East => (1, 0, 0),
+            FaceDirection::West => (-1, 0, 0),
+            FaceDirection::Top => (0, 1, 0),
+            FaceDirection::Bottom => (0, -1, 0),
+        }
+    }
+
+    // The face whose outward normal is the given unit offset.
+    pub fn from_offset(offset: (i32, i32, i32)) -> Option<FaceDirection> {
+        FaceDirection::ALL.into_iter().find(|face| face.offset() == offset)
+    }
+
+    // Index of the axis the face points along (0 = x, 1 = y, 2 = z).
+    pub fn axis(&self) -> usize {
+        match self {
+            FaceDirection::East | FaceDirection::West => 0,
+            FaceDirection::Top | FaceDirection::Bottom => 1,
+            FaceDirection::North | FaceDirection::South => 2,
+        }
+    }
+
+    // Vertices of this face of the box spanning `[x, x + w] x [y, y + h] x [z, z + d]`.
+    pub fn vertices(&self, x: f32, y: f32, z: f32, [w, h, d]: [f32; 3]) -> [[f32; 3]; 6] {
         match self {
             FaceDirection::North => [
                 // Triangle 1
-                [x, y, z + 1.0],           // Bottom-left
-                [x + 1.0, y, z + 1.0],     // Bottom-right
-                [x + 1.0, y + 1.0, z + 1.0], // Top-right
+                [x, y, z + d],         // Bottom-left
+                [x + w, y, z + d],     // Bottom-right
+                [x + w, y + h, z + d], // Top-right
                 // Triangle 2
-                [x, y, z + 1.0],           // Bottom-left
-                [x + 1.0, y + 1.0, z + 1.0], // Top-right
-                [x, y + 1.0, z + 1.0],     // Top-left
+                [x, y, z + d],         // Bottom-left
+                [x + w, y + h, z + d], // Top-right
+                [x, y + h, z + d],     // Top-left
             ],
             FaceDirection::South => [
                 // Triangle 1
-                [x + 1.0, y, z],
+                [x + w, y, z],
                 [x, y, z],
-                [x, y + 1.0, z],
+                [x, y + h, z],
                 // Triangle 2
-                [x + 1.0, y, z],
-                [x, y + 1.0, z],
-                [x + 1.0, y + 1.0, z],
+                [x + w, y, z],
+                [x, y + h, z],
+                [x + w, y + h, z],
             ],
             FaceDirection::East => [
                 // Triangle 1
-                [x + 1.0, y, z + 1.0],
-                [x + 1.0, y, z],
-                [x + 1.0, y + 1.0, z],
+                [x + w, y, z + d],
+                [x + w, y, z],
+                [x + w, y + h, z],
                 // Triangle 2
-                [x + 1.0, y, z + 1.0],
-                [x + 1.0, y + 1.0, z],
-                [x + 1.0, y + 1.0, z + 1.0],
+                [x + w, y, z + d],
+                [x + w, y + h, z],
+                [x + w, y + h, z + d],
             ],
             FaceDirection::West => [
                 // Triangle 1
                 [x, y, z],
-                [x, y, z + 1.0],
-                [x, y + 1.0, z + 1.0],
+                [x, y, z + d],
+                [x, y + h, z + d],
                 // Triangle 2
                 [x, y, z],
-                [x, y + 1.0, z + 1.0],
-                [x, y + 1.0, z],
+                [x, y + h, z + d],
+                [x, y + h, z],
             ],
             FaceDirection::Top => [
                 // Triangle 1
-                [x, y + 1.0, z + 1.0],
-                [x + 1.0, y + 1.0, z + 1.0],
-                [x + 1.0, y + 1.0, z],
+                [x, y + h, z + d],
+                [x + w, y + h, z + d],
+                [x + w, y + h, z],
                 // Triangle 2
-                [x, y + 1.0, z + 1.0],
-                [x + 1.0, y + 1.0, z],
-                [x, y + 1.0, z],
+                [x, y + h, z + d],
+                [x + w, y + h, z],
+                [x, y + h, z],
             ],
             FaceDirection::Bottom => [
                 // Triangle 1
                 [x, y, z],
-                [x + 1.0, y, z],
-                [x + 1.0, y, z + 1.0],
+                [x + w, y, z],
+                [x + w, y, z + d],
                 // Triangle 2
                 [x, y, z],
-                [x + 1.0, y, z + 1.0],
-                [x, y, z + 1.0],
+                [x + w, y, z + d],
+                [x, y, z + d],
             ],
         }
     }
+
+    // Width and height of this face of a box of the given size, in the face's texture space.
+    pub fn uv_extent(&self, [w, h, d]: [f32; 3]) -> (f32, f32) {
+        match self {
+            FaceDirection::North | FaceDirection::South => (w, h),
+            FaceDirection::East | FaceDirection::West => (d, h),
+            FaceDirection::Top | FaceDirection::Bottom => (w, d),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum MeshStrategy {
+    // Every face of every solid voxel.
+    Naive,
+    // Only faces next to a transparent voxel.
+    Culled,
+    // Culled faces merged into the largest rectangles of the same block.
+    Greedy,
+}
+
+impl MeshStrategy {
+    pub fn next(self) -> Self {
+        match self {
+            MeshStrategy::Naive => MeshStrategy::Culled,
+            MeshStrategy::Culled => MeshStrategy::Greedy,
+            MeshStrategy::Greedy => MeshStrategy::Naive,
+        }
+    }
+}
+
+// What a face looks into: the light level there, and whether it's an unloaded chunk.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct FaceLighting {
+    light: u8,
+    seam: bool,
+}
+
+// Everything that decides how a face looks. Greedy meshing only merges faces with equal keys.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct FaceKey {
+    voxel: VoxelType,
+    lighting: FaceLighting,
+    // A liquid surface, with its top edge lowered by `LIQUID_DROP`.
+    lowered: bool,
 }
 
 pub struct ChunkMesher {
     texture_atlas: TextureAtlas,
+    strategy: MeshStrategy,
 }
 
 impl ChunkMesher {
     pub fn new() -> Self {
+        Self::with_strategy(MeshStrategy::Culled)
+    }
+
+    pub fn with_strategy(strategy: MeshStrategy) -> Self {
         Self {
             texture_atlas: TextureAtlas::new(256, 16),
+            strategy,
+        }
+    }
+
+    pub fn strategy(&self) -> MeshStrategy {
+        self.strategy
+    }
+
+    // Meshes the voxels in the chunk-local rows `ys`, so a chunk can be meshed a section at a
+    // time. Faces are never merged across the range's ends.
+    pub fn generate_mesh(&self, chunk: &Chunk, neighbors: &ChunkNeighbors, chunk_pos: ChunkPos, ys: Range<usize>) -> ChunkMeshes {
+        let mut meshes = ChunkMeshes::new();
+
+        if chunk.is_uniform() == Some(VoxelType::Air) {
+            return meshes;
+        }
+
+        let offset = [
+            (chunk_pos.x * CHUNK_SIZE as i32) as f32,
+            (chunk_pos.y * CHUNK_SIZE as i32) as f32,
+            (chunk_pos.z * CHUNK_SIZE as i32) as f32,
+        ];
+
+        match self.strategy {
+            MeshStrategy::Naive | MeshStrategy::Culled => {
+                for z in 0..CHUNK_SIZE {
+                    for y in ys.clone() {
+                        for x in 0..CHUNK_SIZE {
+                            if let Some(voxel) = chunk.get_voxel(x, y, z) {
+                                if let VoxelType::Air = voxel {
+                                    continue;
+                                }
+
+                                self.add_voxel_faces(
+                                    &mut meshes,
+                                    chunk,
+                                    neighbors,
+                                    (x, y, z),
+                                    voxel,
+                                    offset,
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+            MeshStrategy::Greedy => {
+                self.add_greedy_faces(&mut meshes, chunk, neighbors, offset, 1, ys);
+            }
         }
+
+        meshes
     }
-    pub fn generate_mesh(&self, chunk: &Chunk, chunk_pos: ChunkPos) -> ChunkMesh {
-        let mut mesh = ChunkMesh::new();
 
-        let offset_x = (chunk_pos.x * CHUNK_SIZE as i32) as f32;
-        let offset_y = (chunk_pos.y * CHUNK_SIZE as i32) as f32;
-        let offset_z = (chunk_pos.z * CHUNK_SIZE as i32) as f32;
+    // Meshes a distant chunk at 1/`step` resolution (`step` must divide `CHUNK_SIZE`). Each
+    // `step`³ cell becomes one cube of its topmost opaque voxel, or of its topmost transparent
+    // one if it has no opaque voxels, so thin features still cover the silhouette. Faces are
+    // always merged greedily, and those on the chunk border are always drawn. Like
+    // `generate_mesh`, only covers the rows `ys`, whose ends must fall on cell boundaries.
+    pub fn generate_mesh_lod(&self, chunk: &Chunk, chunk_pos: ChunkPos, step: usize, ys: Range<usize>) -> ChunkMeshes {
+        debug_assert!(step > 0 && CHUNK_SIZE.is_multiple_of(step), "LOD step {} doesn't divide the chunk", step);
+        debug_assert!(ys.start.is_multiple_of(step) && ys.end.is_multiple_of(step), "rows {:?} split LOD cells", ys);
+
+        let mut meshes = ChunkMeshes::new();
+
+        if chunk.is_uniform() == Some(VoxelType::Air) {
+            return meshes;
+        }
 
+        // The cells are laid out as a smaller chunk in the corner of a full-size one. The rest
+        // stays air at full light, so the border faces are drawn fully lit.
+        let cells = CHUNK_SIZE / step;
+        let mut coarse = Chunk::new();
         for z in 0..CHUNK_SIZE {
             for y in 0..CHUNK_SIZE {
                 for x in 0..CHUNK_SIZE {
-                    if let Some(voxel) = chunk.get_voxel(x, y, z) {
-                        if let VoxelType::Air = voxel {
-                            continue;
-                        }
+                    coarse.set_light(x, y, z, MAX_LIGHT);
+                }
+            }
+        }
+
+        let cell_ys = ys.start / step..ys.end / step;
+        for cz in 0..cells {
+            for cy in cell_ys.clone() {
+                for cx in 0..cells {
+                    let mut opaque = None;
+                    let mut transparent = None;
+                    let mut light = 0;
 
-                        self.add_voxel_faces(
-                            &mut mesh,
-                            chunk,
-                            x,
-                            y,
-                            z,
-                            voxel,
-                            offset_x,
-                            offset_y,
-                            offset_z,
-                        );
+                    for y in (cy * step..(cy + 1) * step).rev() {
+                        for z in cz * step..(cz + 1) * step {
+                            for x in cx * step..(cx + 1) * step {
+                                light = light.max(chunk.get_light(x, y, z));
+                                match chunk.get_voxel(x, y, z) {
+                                    None | Some(VoxelType::Air) => {}
+                                    Some(voxel) if voxel.is_transparent() => { transparent.get_or_insert(voxel); }
+                                    Some(voxel) => { opaque.get_or_insert(voxel); }
+                                }
+                            }
+                        }
                     }
+
+                    coarse.set_voxel(cx, cy, cz, opaque.or(transparent).unwrap_or(VoxelType::Air));
+                    coarse.set_light(cx, cy, cz, light);
                 }
             }
         }
 
-        mesh
+        let offset = [
+            (chunk_pos.x * CHUNK_SIZE as i32) as f32,
+            (chunk_pos.y * CHUNK_SIZE as i32) as f32,
+            (chunk_pos.z * CHUNK_SIZE as i32) as f32,
+        ];
+
+        self.add_greedy_faces(&mut meshes, &coarse, &ChunkNeighbors::default(), offset, step, cell_ys);
+
+        meshes
     }
 
     fn add_voxel_faces(
         &self,
-        mesh: &mut ChunkMesh,
+        meshes: &mut ChunkMeshes,
         chunk: &Chunk,
-        x: usize,
-        y: usize,
-        z: usize,
+        neighbors: &ChunkNeighbors,
+        (x, y, z): (usize, usize, usize),
         voxel: VoxelType,
-        offset_x: f32,
-        offset_y: f32,
-        offset_z: f32,
+        offset: [f32; 3],
     ) {
-        let faces = [
-            (FaceDirection::North, (x, y, z + 1)),
-            (FaceDirection::South, (x, y, z.wrapping_sub(1))),
-            (FaceDirection::East, (x + 1, y, z)),
-            (FaceDirection::West, (x.wrapping_sub(1), y, z)),
-            (FaceDirection::Top, (x, y + 1, z)),
-            (FaceDirection::Bottom, (x, y.wrapping_sub(1), z)),
-        ];
+        let lowered = Self::is_liquid_surface(chunk, neighbors, (x, y, z), voxel);
 
-        for (direction, neighbor_pos) in faces {
-            if Self::should_render_face(chunk, neighbor_pos) {
+        for direction in FaceDirection::ALL {
+            if self.strategy == MeshStrategy::Naive || Self::is_face_visible(chunk, neighbors, (x, y, z), direction) {
+                let key = FaceKey {
+                    voxel,
+                    lighting: Self::face_lighting(chunk, neighbors, (x, y, z), direction),
+                    lowered,
+                };
                 self.add_face(
-                    mesh,
-                    x as f32 + offset_x,
-                    y as f32 + offset_y,
-                    z as f32 + offset_z,
+                    meshes,
+                    [x as f32 + offset[0], y as f32 + offset[1], z as f32 + offset[2]],
+                    [1.0, 1.0, 1.0],
                     direction,
-                    voxel,
+                    key,
                 );
             }
         }
     }
 
-    fn should_render_face(chunk: &Chunk, neighbor_pos: (usize, usize, usize)) -> bool {
-        let (x, y, z) = neighbor_pos;
+    // For each face direction, sweeps the chunk slice by slice along its axis, building a mask
+    // of visible faces and covering it with as few rectangles of the same block and light level
+    // as possible. Each voxel of `chunk` is emitted as a cube `scale` blocks wide. Only voxels
+    // in the rows `ys` get faces.
+    fn add_greedy_faces(
+        &self,
+        meshes: &mut ChunkMeshes,
+        chunk: &Chunk,
+        neighbors: &ChunkNeighbors,
+        offset: [f32; 3],
+        scale: usize,
+        ys: Range<usize>,
+    ) {
+        for direction in FaceDirection::ALL {
+            let axis = direction.axis();
+            let u_axis = (axis + 1) % 3;
+            let v_axis = (axis + 2) % 3;
+
+            // Lowered liquid surfaces only merge with each other, so a merged side face can drop
+            // its whole top edge.
+            let mut mask: [Option<FaceKey>; CHUNK_SIZE * CHUNK_SIZE] = [None; CHUNK_SIZE * CHUNK_SIZE];
+
+            for slice in 0..CHUNK_SIZE {
+                if axis == 1 && !ys.contains(&slice) {
+                    continue;
+                }
+
+                for v in 0..CHUNK_SIZE {
+                    for u in 0..CHUNK_SIZE {
+                        let mut pos = [0; 3];
+                        pos[axis] = slice;
+                        pos[u_axis] = u;
+                        pos[v_axis] = v;
 
-        if x >= CHUNK_SIZE || y >= CHUNK_SIZE || z >= CHUNK_SIZE {
-            return true;
+                        mask[u + v * CHUNK_SIZE] = chunk.get_voxel(pos[0], pos[1], pos[2])
+                            .filter(|_| ys.contains(&pos[1]))
+                            .filter(|voxel| *voxel != VoxelType::Air)
+                            .filter(|_| Self::is_face_visible(chunk, neighbors, (pos[0], pos[1], pos[2]), direction))
+                            .map(|voxel| FaceKey {
+                                voxel,
+                                lighting: Self::face_lighting(chunk, neighbors, (pos[0], pos[1], pos[2]), direction),
+                                lowered: Self::is_liquid_surface(chunk, neighbors, (pos[0], pos[1], pos[2]), voxel),
+                            });
+                    }
+                }
+
+                for v in 0..CHUNK_SIZE {
+                    let mut u = 0;
+                    while u < CHUNK_SIZE {
+                        let Some(key) = mask[u + v * CHUNK_SIZE] else {
+                            u += 1;
+                            continue;
+                        };
+
+                        let mut width = 1;
+                        while u + width < CHUNK_SIZE && mask[u + width + v * CHUNK_SIZE] == Some(key) {
+                            width += 1;
+                        }
+
+                        let mut height = 1;
+                        'grow: while v + height < CHUNK_SIZE {
+                            for du in 0..width {
+                                if mask[u + du + (v + height) * CHUNK_SIZE] != Some(key) {
+                                    break 'grow;
+                                }
+                            }
+                            height += 1;
+                        }
+
+                        for dv in 0..height {
+                            for du in 0..width {
+                                mask[u + du + (v + dv) * CHUNK_SIZE] = None;
+                            }
+                        }
+
+                        let scale = scale as f32;
+                        let mut min = [0.0; 3];
+                        min[axis] = slice as f32 * scale + offset[axis];
+                        min[u_axis] = u as f32 * scale + offset[u_axis];
+                        min[v_axis] = v as f32 * scale + offset[v_axis];
+
+                        let mut size = [scale; 3];
+                        size[u_axis] = width as f32 * scale;
+                        size[v_axis] = height as f32 * scale;
+
+                        self.add_face(meshes, min, size, direction, key);
+
+                        u += width;
+                    }
+                }
+            }
+        }
+    }
+
+    // Faces on the chunk border are hidden by the block across it in the neighboring chunk.
+    // Faces looking into a chunk that isn't loaded are drawn.
+    fn is_face_visible(
+        chunk: &Chunk,
+        neighbors: &ChunkNeighbors,
+        (x, y, z): (usize, usize, usize),
+        direction: FaceDirection,
+    ) -> bool {
+        let (dx, dy, dz) = direction.offset();
+        let size = CHUNK_SIZE as i32;
+        let (nx, ny, nz) = (x as i32 + dx, y as i32 + dy, z as i32 + dz);
+
+        let neighbor = if [nx, ny, nz].iter().all(|c| (0..size).contains(c)) {
+            chunk.get_voxel(nx as usize, ny as usize, nz as usize)
+        } else {
+            let Some(neighbor_chunk) = neighbors.get(direction) else {
+                return true;
+            };
+            neighbor_chunk.get_voxel(
+                nx.rem_euclid(size) as usize,
+                ny.rem_euclid(size) as usize,
+                nz.rem_euclid(size) as usize,
+            )
+        };
+
+        Self::should_render_face(chunk.get_voxel(x, y, z), neighbor)
+    }
+
+    // Faces are lit by the voxel in front of them. Faces looking into a chunk that isn't loaded
+    // are treated as fully lit and marked as seams.
+    fn face_lighting(
+        chunk: &Chunk,
+        neighbors: &ChunkNeighbors,
+        (x, y, z): (usize, usize, usize),
+        direction: FaceDirection,
+    ) -> FaceLighting {
+        let (dx, dy, dz) = direction.offset();
+        let size = CHUNK_SIZE as i32;
+        let (nx, ny, nz) = (x as i32 + dx, y as i32 + dy, z as i32 + dz);
+
+        if [nx, ny, nz].iter().all(|c| (0..size).contains(c)) {
+            let light = chunk.get_light(nx as usize, ny as usize, nz as usize);
+            return FaceLighting { light, seam: false };
         }
 
-        match chunk.get_voxel(x, y, z) {
-            Some(VoxelType::Air) => true,
-            None => true,
-            _ => false,
+        match neighbors.get(direction) {
+            Some(neighbor) => FaceLighting {
+                light: neighbor.get_light(
+                    nx.rem_euclid(size) as usize,
+                    ny.rem_euclid(size) as usize,
+                    nz.rem_euclid(size) as usize,
+                ),
+                seam: false,
+            },
+            None => FaceLighting { light: MAX_LIGHT, seam: true },
         }
     }
 
+    // Whether `voxel` is a liquid with no more of the same liquid above it. Liquid under an
+    // unloaded chunk counts as a surface.
+    fn is_liquid_surface(chunk: &Chunk, neighbors: &ChunkNeighbors, (x, y, z): (usize, usize, usize), voxel: VoxelType) -> bool {
+        if !voxel.is_liquid() {
+            return false;
+        }
+
+        let above = if y + 1 < CHUNK_SIZE {
+            chunk.get_voxel(x, y + 1, z)
+        } else {
+            neighbors.get(FaceDirection::Top).and_then(|neighbor| neighbor.get_voxel(x, 0, z))
+        };
+        above != Some(voxel)
+    }
+
+    fn should_render_face(voxel: Option<VoxelType>, neighbor: Option<VoxelType>) -> bool {
+        // Faces between two blocks of the same transparent type (e.g. glass panes) are hidden.
+        neighbor.is_none_or(|neighbor| neighbor.is_transparent() && Some(neighbor) != voxel)
+    }
+
     fn add_face(
         &self,
-        mesh: &mut ChunkMesh,
-        x: f32,
-        y: f32,
-        z: f32,
+        meshes: &mut ChunkMeshes,
+        min: [f32; 3],
+        size: [f32; 3],
         direction: FaceDirection,
-        voxel: VoxelType,
+        FaceKey { voxel, lighting, lowered }: FaceKey,
     ) {
-        let positions = direction.vertices(x, y, z);
-        let uvs = self.texture_atlas.get_uvs(voxel, direction);
+        let mut positions = direction.vertices(min[0], min[1], min[2], size);
+        // Texture coordinates are left as they are; the slight squash isn't noticeable on liquids.
+        if lowered {
+            let top = min[1] + size[1];
+            for position in positions.iter_mut().filter(|position| position[1] == top) {
+                position[1] -= LIQUID_DROP;
+            }
+        }
+        let (width, height) = direction.uv_extent(size);
+        let tile = self.texture_atlas.get_tile_origin(voxel, direction);
         let normal = direction.normal();
+        let mesh = meshes.for_voxel(voxel);
+        let block_flags = if voxel.is_solid() { 0 } else { BLOCK_NON_SOLID }
+            | if voxel.is_transparent() { BLOCK_TRANSPARENT } else { 0 };
+
+        // Texture coordinates are in tiles so the shader can repeat the tile across merged faces.
+        let tex_coords = [
+            [0.0, height],   // Bottom-left
+            [width, height], // Bottom-right
+            [width, 0.0],    // Top-right
+            [0.0, height],   // Bottom-left
+            [width, 0.0],    // Top-right
+            [0.0, 0.0],      // Top-left
+        ];
 
         for i in 0..6 {
             mesh.vertices.push(Vertex {
                 position: positions[i],
-                tex_coords: uvs[i],
+                tex_coords: tex_coords[i],
                 normal,
+                tile,
+                light: lighting.light as f32 / MAX_LIGHT as f32,
+                seam: if lighting.seam { 1.0 } else { 0.0 },
+                block_flags,
             });
         }
     }
@@ -257,28 +747,392 @@ impl ChunkMesher {
 pub struct ChunkMeshBuffer {
     pub vertex_buffer: wgpu::Buffer,
     pub vertex_count: u32,
+    // Compact buffers end with the chunk's origin, starting at this offset.
+    origin_offset: Option<wgpu::BufferAddress>,
+    // Identifies everything the mesh was built from (see `ChunkRenderer::mesh_hash`), so an
+    // unchanged chunk isn't meshed again.
+    pub content_hash: u64,
 }
 
 impl ChunkMeshBuffer {
-    pub fn from_mesh(device: &wgpu::Device, mesh: &ChunkMesh) -> Option<Self> {
+    pub fn from_mesh(device: &wgpu::Device, mesh: &ChunkMesh, format: VertexFormat, chunk_pos: ChunkPos) -> Option<Self> {
         if mesh.is_empty() {
             return None;
         }
 
+        let (contents, origin_offset) = match format {
+            VertexFormat::Standard => (bytemuck::cast_slice(&mesh.vertices).to_vec(), None),
+            VertexFormat::Compact => {
+                let origin = [chunk_pos.x, chunk_pos.y, chunk_pos.z].map(|c| (c * CHUNK_SIZE as i32) as f32);
+                let vertices = mesh.vertices.iter()
+                    .map(|vertex| CompactVertex::pack(vertex, origin))
+                    .collect::<Vec<_>>();
+
+                let mut contents = bytemuck::cast_slice(&vertices).to_vec();
+                let origin_offset = contents.len() as wgpu::BufferAddress;
+                contents.extend_from_slice(bytemuck::cast_slice(&origin));
+                (contents, Some(origin_offset))
+            }
+        };
+
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Chunk Vertex Buffer"),
-            contents: bytemuck::cast_slice(&mesh.vertices),
-            usage: wgpu::BufferUsages::VERTEX,
+            contents: &contents,
+            // Copied from into the chunk batches.
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_SRC,
         });
 
         Some(Self {
             vertex_buffer,
             vertex_count: mesh.vertex_count(),
+            origin_offset,
+            content_hash: 0,
         })
     }
 
+    // Size of the buffer in bytes.
+    pub fn size(&self) -> u64 {
+        self.vertex_buffer.size()
+    }
+
+    // Bytes of vertices at the start of the buffer, not counting a compact buffer's origin.
+    pub fn vertex_bytes(&self) -> wgpu::BufferAddress {
+        self.origin_offset.unwrap_or(self.vertex_buffer.size())
+    }
+
+    // Where a compact buffer's `[f32; 3]` chunk origin starts.
+    pub fn origin_offset(&self) -> Option<wgpu::BufferAddress> {
+        self.origin_offset
+    }
+
     pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        match self.origin_offset {
+            Some(offset) => {
+                render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..offset));
+                render_pass.set_vertex_buffer(1, self.vertex_buffer.slice(offset..));
+            }
+            None => render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..)),
+        }
         render_pass.draw(0..self.vertex_count, 0..1);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap};
+    use super::*;
+    use crate::game::world::{GenerationFeatures, World};
+    use crate::game::chunk::{SectionPos, ALL_SECTIONS};
+
+    // One unit square of rendered surface: the voxel it belongs to, its outward normal, and
+    // what it looks like (atlas tile and light, scaled to integers so they can be compared).
+    type SurfaceCell = ((i32, i32, i32), (i32, i32, i32), (i32, i32), i32, bool);
+
+    // Splits every quad into the unit squares it covers, so meshes that tile the same surface
+    // with differently sized quads compare equal.
+    fn surface_cells(meshes: &ChunkMeshes) -> Vec<SurfaceCell> {
+        let mut cells = Vec::new();
+
+        for (mesh, transparent) in [(&meshes.opaque, false), (&meshes.transparent, true)] {
+            for quad in mesh.vertices.chunks(6) {
+                let normal = quad[0].normal.map(|n| n as i32);
+                let axis = normal.iter().position(|n| *n != 0).unwrap();
+                let u_axis = (axis + 1) % 3;
+                let v_axis = (axis + 2) % 3;
+
+                let min = std::array::from_fn::<i32, 3, _>(|i| quad.iter().map(|v| v.position[i] as i32).min().unwrap());
+                let max = std::array::from_fn::<i32, 3, _>(|i| quad.iter().map(|v| v.position[i] as i32).max().unwrap());
+
+                let tile = ((quad[0].tile[0] * 16.0).round() as i32, (quad[0].tile[1] * 16.0).round() as i32);
+                let light = (quad[0].light * MAX_LIGHT as f32).round() as i32;
+
+                for u in min[u_axis]..max[u_axis] {
+                    for v in min[v_axis]..max[v_axis] {
+                        let mut voxel = [0; 3];
+                        voxel[axis] = if normal[axis] > 0 { min[axis] - 1 } else { min[axis] };
+                        voxel[u_axis] = u;
+                        voxel[v_axis] = v;
+
+                        cells.push((
+                            (voxel[0], voxel[1], voxel[2]),
+                            (normal[0], normal[1], normal[2]),
+                            tile,
+                            light,
+                            transparent,
+                        ));
+                    }
+                }
+            }
+        }
+
+        cells
+    }
+
+    // Drops cells the naive mesher emits for faces hidden behind a neighboring block, using
+    // the same rule as the culled mesher: only opaque blocks and identical transparent blocks
+    // hide a face.
+    fn visible_cells(chunk: &Chunk, cells: Vec<SurfaceCell>) -> BTreeSet<SurfaceCell> {
+        cells.into_iter()
+            .filter(|((x, y, z), (dx, dy, dz), ..)| {
+                let voxel = chunk.get_voxel(*x as usize, *y as usize, *z as usize);
+                let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+                let size = CHUNK_SIZE as i32;
+
+                if [nx, ny, nz].iter().any(|c| !(0..size).contains(c)) {
+                    return true;
+                }
+
+                chunk.get_voxel(nx as usize, ny as usize, nz as usize)
+                    .is_none_or(|neighbor| neighbor.is_transparent() && Some(neighbor) != voxel)
+            })
+            .collect()
+    }
+
+    fn test_chunks() -> Vec<(&'static str, Chunk)> {
+        let mut chunks = vec![
+            ("empty", Chunk::new()),
+            ("solid", Chunk::filled(VoxelType::Stone)),
+        ];
+
+        let mut terrain = Chunk::new();
+        let mut checkerboard = Chunk::new();
+        let mut mixed = Chunk::new();
+        let mut seed = 0x2545_f491_u32;
+
+        for z in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    let layer = match y {
+                        0..4 => VoxelType::Stone,
+                        4..7 => VoxelType::Dirt,
+                        7 => VoxelType::Grass,
+                        _ => VoxelType::Air,
+                    };
+                    terrain.set_voxel(x, y, z, layer);
+
+                    if (x + y + z) % 2 == 0 {
+                        checkerboard.set_voxel(x, y, z, VoxelType::Dirt);
+                    }
+
+                    // xorshift, so the "random" chunk is the same on every run.
+                    seed ^= seed << 13;
+                    seed ^= seed >> 17;
+                    seed ^= seed << 5;
+                    let voxel = [VoxelType::Air, VoxelType::Air, VoxelType::Grass, VoxelType::Stone, VoxelType::Glass][seed as usize % 5];
+                    mixed.set_voxel(x, y, z, voxel);
+                }
+            }
+        }
+
+        // Vary the light so greedy meshing has to split quads by light level too.
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                terrain.set_light(x, 8, z, (x % 4) as u8 * 5);
+            }
+        }
+
+        chunks.push(("terrain", terrain));
+        chunks.push(("checkerboard", checkerboard));
+        chunks.push(("mixed", mixed));
+        chunks
+    }
+
+    #[test]
+    fn strategies_render_the_same_surface() {
+        let neighbors = ChunkNeighbors::default();
+        let pos = ChunkPos::new(0, 0, 0);
+
+        for (name, chunk) in test_chunks() {
+            let mesh = |strategy| ChunkMesher::with_strategy(strategy).generate_mesh(&chunk, &neighbors, pos, 0..CHUNK_SIZE);
+
+            let naive = visible_cells(&chunk, surface_cells(&mesh(MeshStrategy::Naive)));
+
+            let culled_cells = surface_cells(&mesh(MeshStrategy::Culled));
+            let culled = culled_cells.iter().copied().collect::<BTreeSet<_>>();
+            assert_eq!(culled.len(), culled_cells.len(), "{}: culled mesh has overlapping faces", name);
+
+            let greedy_cells = surface_cells(&mesh(MeshStrategy::Greedy));
+            let greedy = greedy_cells.iter().copied().collect::<BTreeSet<_>>();
+            assert_eq!(greedy.len(), greedy_cells.len(), "{}: greedy mesh has overlapping faces", name);
+
+            assert_eq!(naive, culled, "{}: naive and culled surfaces differ", name);
+            assert_eq!(culled, greedy, "{}: culled and greedy surfaces differ", name);
+
+            // Meshing a section at a time covers the same surface, without faces spilling over.
+            let sectioned = SectionPos::in_mask(pos, ALL_SECTIONS)
+                .flat_map(|section| {
+                    let meshes = ChunkMesher::with_strategy(MeshStrategy::Greedy).generate_mesh(&chunk, &neighbors, pos, section.ys());
+                    surface_cells(&meshes)
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(sectioned.len(), greedy_cells.len(), "{}: sections overlap", name);
+            assert_eq!(sectioned.into_iter().collect::<BTreeSet<_>>(), greedy, "{}: sectioned surface differs", name);
+        }
+    }
+
+    #[test]
+    fn floating_blocks_render_every_face() {
+        let neighbors = ChunkNeighbors::default();
+        let pos = ChunkPos::new(0, 0, 0);
+
+        // A lone floating block, and a 3x3 overhang slab with air between it and the ground.
+        let mut chunk = Chunk::new();
+        chunk.set_voxel(3, 8, 3, VoxelType::Stone);
+        for x in 8..11 {
+            for z in 8..11 {
+                chunk.set_voxel(x, 0, z, VoxelType::Dirt);
+                chunk.set_voxel(x, 6, z, VoxelType::Stone);
+            }
+        }
+
+        for strategy in [MeshStrategy::Naive, MeshStrategy::Culled, MeshStrategy::Greedy] {
+            let cells = surface_cells(&ChunkMesher::with_strategy(strategy).generate_mesh(&chunk, &neighbors, pos, 0..CHUNK_SIZE));
+            let faces_of = |voxel| cells.iter()
+                .filter(|(cell_voxel, ..)| *cell_voxel == voxel)
+                .map(|(_, normal, ..)| *normal)
+                .collect::<BTreeSet<_>>();
+
+            let all_faces = FaceDirection::ALL.iter().map(|face| face.offset()).collect::<BTreeSet<_>>();
+            assert_eq!(faces_of((3, 8, 3)), all_faces, "{:?}: floating block is missing faces", strategy);
+
+            for x in 8..11 {
+                for z in 8..11 {
+                    assert!(faces_of((x, 6, z)).contains(&(0, -1, 0)), "{:?}: overhang underside missing at {}, {}", strategy, x, z);
+                    assert!(faces_of((x, 0, z)).contains(&(0, 1, 0)), "{:?}: ground under overhang missing at {}, {}", strategy, x, z);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn coarser_lods_cover_the_silhouette_with_fewer_vertices() {
+        // Bumpy terrain: stone columns between 3 and 10 blocks tall, capped with grass.
+        let height = |x: usize, z: usize| 3 + (x * 7 + z * 3 + x * z) % 8;
+        let mut chunk = Chunk::new();
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                for y in 0..height(x, z) {
+                    chunk.set_voxel(x, y, z, VoxelType::Stone);
+                }
+                chunk.set_voxel(x, height(x, z), z, VoxelType::Grass);
+            }
+        }
+
+        let mesher = ChunkMesher::new();
+        let mut previous_count = usize::MAX;
+        for step in [1, 2, 4] {
+            let meshes = mesher.generate_mesh_lod(&chunk, ChunkPos::new(0, 0, 0), step, 0..CHUNK_SIZE);
+            let count = meshes.opaque.vertices.len() + meshes.transparent.vertices.len();
+            assert!(count < previous_count, "step {} has {} vertices, no fewer than the step before", step, count);
+            previous_count = count;
+
+            let up_quads = meshes.opaque.vertices.chunks(6)
+                .filter(|quad| quad[0].normal == [0.0, 1.0, 0.0])
+                .collect::<Vec<_>>();
+            for z in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    // The highest upward face over the column's middle.
+                    let (cx, cz) = (x as f32 + 0.5, z as f32 + 0.5);
+                    let top = up_quads.iter()
+                        .filter(|quad| {
+                            let xs = quad.iter().map(|v| v.position[0]);
+                            let zs = quad.iter().map(|v| v.position[2]);
+                            xs.clone().fold(f32::MAX, f32::min) < cx && cx < xs.fold(f32::MIN, f32::max)
+                                && zs.clone().fold(f32::MAX, f32::min) < cz && cz < zs.fold(f32::MIN, f32::max)
+                        })
+                        .map(|quad| quad[0].position[1])
+                        .fold(f32::MIN, f32::max);
+
+                    // Each cell is as tall as its tallest column, rounded up to whole cells.
+                    let (cell_x, cell_z) = (x / step * step, z / step * step);
+                    let tallest = (cell_x..cell_x + step)
+                        .flat_map(|x| (cell_z..cell_z + step).map(move |z| height(x, z) + 1))
+                        .max()
+                        .unwrap();
+                    let expected = tallest.div_ceil(step) * step;
+                    assert!(top >= (height(x, z) + 1) as f32, "step {}: column {}, {} is uncovered", step, x, z);
+                    assert_eq!(top, expected as f32, "step {}: column {}, {}", step, x, z);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn faces_carry_their_blocks_solidity_and_transparency() {
+        let mut chunk = Chunk::new();
+        chunk.set_voxel(1, 1, 1, VoxelType::Stone);
+        chunk.set_voxel(5, 1, 1, VoxelType::Glass);
+        chunk.set_voxel(9, 1, 1, VoxelType::Water);
+
+        let meshes = ChunkMesher::new().generate_mesh(&chunk, &ChunkNeighbors::default(), ChunkPos::new(0, 0, 0), 0..CHUNK_SIZE);
+        for vertex in meshes.opaque.vertices.iter().chain(&meshes.transparent.vertices) {
+            let expected = match vertex.position[0] as usize {
+                1..=2 => 0,
+                5..=6 => BLOCK_TRANSPARENT,
+                _ => BLOCK_NON_SOLID | BLOCK_TRANSPARENT,
+            };
+            assert_eq!(vertex.block_flags, expected, "vertex at {:?}", vertex.position);
+
+            let packed = CompactVertex::pack(vertex, [0.0; 3]).packed[1];
+            assert_eq!(packed >> 12 & 0b11, expected, "packed vertex at {:?}", vertex.position);
+        }
+    }
+
+    // Remeshes only the sections the world reports dirty, as the renderer does, and returns the
+    // surface of all the meshes together.
+    fn remesh_dirty(world: &mut World, meshes: &mut HashMap<SectionPos, ChunkMeshes>, mesher: &ChunkMesher) -> Vec<SurfaceCell> {
+        for (pos, sections) in world.take_dirty_chunks() {
+            let chunk = world.get_chunk(pos).unwrap();
+            for section in SectionPos::in_mask(pos, sections) {
+                meshes.insert(section, mesher.generate_mesh(chunk, &world.neighbors(pos), pos, section.ys()));
+            }
+        }
+        meshes.values().flat_map(surface_cells).collect()
+    }
+
+    #[test]
+    fn editing_across_a_seam_keeps_the_mesh_watertight() {
+        let last = CHUNK_SIZE as i32 - 1;
+        let (left, right) = ((last, 5, 5), (last + 1, 5, 5));
+
+        // The naive mesher draws hidden faces too, so it isn't expected to be watertight.
+        for strategy in [MeshStrategy::Culled, MeshStrategy::Greedy] {
+            let mesher = ChunkMesher::with_strategy(strategy);
+            let mut world = World::new();
+            world.set_generation_features(GenerationFeatures { noise_terrain: false, ..Default::default() });
+            world.load_region(ChunkPos::new(0, 0, 0), ChunkPos::new(1, 0, 0));
+            let mut meshes = HashMap::new();
+            remesh_dirty(&mut world, &mut meshes, &mesher);
+
+            // Each edit touches a block on the border between the two chunks.
+            for (edit, voxel, expected_faces) in [(left, VoxelType::Stone, 6), (right, VoxelType::Stone, 10), (left, VoxelType::Air, 6)] {
+                world.set_voxel(edit.0, edit.1, edit.2, voxel);
+                let cells = remesh_dirty(&mut world, &mut meshes, &mesher);
+
+                let unique = cells.iter().copied().collect::<BTreeSet<_>>();
+                assert_eq!(unique.len(), cells.len(), "{:?}: doubled faces after setting {:?}", strategy, edit);
+                assert_eq!(cells.len(), expected_faces, "{:?}: wrong face count after setting {:?}", strategy, edit);
+
+                // Blocks on either side of the seam hide each other's faces, and only each other's.
+                let faces_of = |voxel| unique.iter()
+                    .filter(|(cell_voxel, ..)| *cell_voxel == voxel)
+                    .map(|(_, normal, ..)| *normal)
+                    .collect::<BTreeSet<_>>();
+                let both = world.get_voxel(left.0, left.1, left.2) == Some(VoxelType::Stone)
+                    && world.get_voxel(right.0, right.1, right.2) == Some(VoxelType::Stone);
+                for (block, toward_seam) in [(left, (1, 0, 0)), (right, (-1, 0, 0))] {
+                    if world.get_voxel(block.0, block.1, block.2) == Some(VoxelType::Stone) {
+                        assert_eq!(faces_of(block).len(), if both { 5 } else { 6 }, "{:?}: faces of {:?}", strategy, block);
+                        assert_eq!(faces_of(block).contains(&toward_seam), !both, "{:?}: seam face of {:?}", strategy, block);
+                    }
+                }
+
+                // Nothing left stale: meshing everything from scratch gives the same surface.
+                let fresh = world.chunk_positions()
+                    .flat_map(|pos| surface_cells(&mesher.generate_mesh(world.get_chunk(pos).unwrap(), &world.neighbors(pos), pos, 0..CHUNK_SIZE)))
+                    .collect::<BTreeSet<_>>();
+                assert_eq!(unique, fresh, "{:?}: stale faces after setting {:?}", strategy, edit);
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use crate::game::chunk::{Chunk, ChunkNeighbors, SectionPos};
+use crate::rendering::mesh::{ChunkMeshes, ChunkMesher};
+
+pub struct MeshResult {
+    pub pos: SectionPos,
+    pub meshes: ChunkMeshes,
+    // Passed through from `dispatch`.
+    pub content_hash: u64,
+    generation: u64,
+}
+
+// Generates chunk section meshes on a background thread pool. Jobs get an immutable snapshot of
+// the chunk and its neighbors, and finished meshes are handed back through a channel for the main thread to upload.
+pub struct MeshWorkerPool {
+    pool: rayon::ThreadPool,
+    mesher: Arc<ChunkMesher>,
+    sender: Sender<MeshResult>,
+    receiver: Receiver<MeshResult>,
+
+    // Latest generation dispatched per section, so results from superseded jobs can be dropped.
+    pending: HashMap<SectionPos, u64>,
+    next_generation: u64,
+}
+
+impl MeshWorkerPool {
+    pub fn new(mesher: ChunkMesher) -> Self {
+        let threads = std::thread::available_parallelism()
+            .map(|n| n.get().saturating_sub(1).max(1))
+            .unwrap_or(1);
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("mesh-worker-{}", i))
+            .build()
+            .expect("Failed to create mesh worker pool");
+
+        let (sender, receiver) = channel();
+
+        Self {
+            pool,
+            mesher: Arc::new(mesher),
+            sender,
+            receiver,
+            pending: HashMap::new(),
+            next_generation: 0,
+        }
+    }
+
+    pub fn mesher(&self) -> &ChunkMesher {
+        &self.mesher
+    }
+
+    // Jobs already running keep the mesher they were started with.
+    pub fn set_mesher(&mut self, mesher: ChunkMesher) {
+        self.mesher = Arc::new(mesher);
+    }
+
+    // Meshes one section of `chunk`. A `lod_step` above 1 meshes it at reduced resolution; see
+    // `generate_mesh_lod`.
+    pub fn dispatch(&mut self, pos: SectionPos, chunk: Arc<Chunk>, neighbors: ChunkNeighbors, lod_step: usize, content_hash: u64) {
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        self.pending.insert(pos, generation);
+
+        let mesher = self.mesher.clone();
+        let sender = self.sender.clone();
+
+        self.pool.spawn(move || {
+            let meshes = if lod_step > 1 {
+                mesher.generate_mesh_lod(&chunk, pos.chunk, lod_step, pos.ys())
+            } else {
+                mesher.generate_mesh(&chunk, &neighbors, pos.chunk, pos.ys())
+            };
+            // The receiver only goes away when the renderer is dropped.
+            let _ = sender.send(MeshResult { pos, meshes, content_hash, generation });
+        });
+    }
+
+    pub fn is_pending(&self, pos: SectionPos) -> bool {
+        self.pending.contains_key(&pos)
+    }
+
+    // Forgets all in-flight jobs; their results will be discarded when they arrive.
+    pub fn cancel_all(&mut self) {
+        self.pending.clear();
+    }
+
+    // Returns up to `max` finished meshes, skipping any that a newer dispatch has superseded.
+    pub fn collect(&mut self, max: usize) -> Vec<MeshResult> {
+        let mut results = Vec::new();
+
+        while results.len() < max {
+            let Ok(result) = self.receiver.try_recv() else {
+                break;
+            };
+
+            if self.pending.get(&result.pos) == Some(&result.generation) {
+                self.pending.remove(&result.pos);
+                results.push(result);
+            }
+        }
+
+        results
+    }
+}
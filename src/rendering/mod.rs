@@ -1,11 +1,18 @@
 pub mod texture_atlas;
+mod chunk_batch;
 mod chunk_renderer;
+mod depth_view;
+pub mod frustum;
+mod mesh_worker;
+mod occlusion;
+pub mod outline_renderer;
 pub mod mesh;
 pub mod texture;
 pub mod projection;
 pub mod geometry_renderer;
 pub mod shared_resources;
 pub mod gpu_context;
+pub mod lighting;
 
 pub use geometry_renderer::GeometryRenderer;
 pub use shared_resources::SharedResources;
\ No newline at end of file
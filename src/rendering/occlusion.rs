@@ -0,0 +1,353 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock};
+use wgpu::util::DeviceExt;
+use wgpu::{BindGroup, RenderPipeline};
+use crate::game::chunk::SectionPos;
+use crate::rendering::outline_renderer::OutlineBox;
+use crate::rendering::texture::Texture;
+
+const MAX_QUERIES: u32 = wgpu::QUERY_SET_MAX_QUERIES;
+const QUERY_RESULT_SIZE: wgpu::BufferAddress = std::mem::size_of::<u64>() as wgpu::BufferAddress;
+const BOX_VERTEX_COUNT: u32 = 36;
+// Sections this close to the camera are always drawn: their bounding box may be clipped by the
+// near plane and wrongly come back as hidden.
+const NEAR_MARGIN: f32 = 2.0;
+
+// Where the results of the last recorded queries are on their way back to the CPU.
+enum Readback {
+    Idle,
+    // Queries were resolved into this frame's command buffer; mapping starts once it's submitted.
+    Recorded,
+    // Set to whether the mapping succeeded once the readback buffer is ready.
+    Mapping(Arc<OnceLock<bool>>),
+}
+
+// Hides chunk sections that were completely behind other geometry, using GPU occlusion queries.
+// Visible sections are drawn inside a query to find out whether they are still visible; hidden
+// sections only draw their bounding box, without writing color or depth, to find out whether
+// they have come back into view. Results are read back asynchronously, so visibility lags a
+// frame or two behind and queries are only recorded while no readback is in flight.
+pub struct OcclusionCuller {
+    pipeline: RenderPipeline,
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    cube_buffer: wgpu::Buffer,
+    box_buffer: wgpu::Buffer,
+
+    // Section each query index belongs to, for the queries awaiting readback.
+    queried: Vec<SectionPos>,
+    readback: Readback,
+    occluded: HashSet<SectionPos>,
+
+    // This frame's queries: visible sections drawn inside a query, and the query index of each
+    // bounding box in `box_buffer`.
+    draw_queries: HashMap<SectionPos, u32>,
+    box_queries: Vec<u32>,
+}
+
+impl OcclusionCuller {
+    // Returns `None` if the device can't create occlusion queries.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Option<Self> {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Occlusion Query Set"),
+            ty: wgpu::QueryType::Occlusion,
+            count: MAX_QUERIES,
+        });
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            log::warn!("Occlusion queries unavailable, drawing every chunk: {}", error);
+            return None;
+        }
+
+        // The bounding boxes use the outline shader's box transform; its color never lands
+        // since the pipeline writes no color or depth.
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Occlusion Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../../resources/shaders/outline.wgsl").into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Occlusion Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Occlusion Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+                    },
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<OutlineBox>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &wgpu::vertex_attr_array![1 => Float32x3, 2 => Float32x3],
+                    },
+                ],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::empty(),
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        let results_size = MAX_QUERIES as wgpu::BufferAddress * QUERY_RESULT_SIZE;
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Occlusion Resolve Buffer"),
+            size: results_size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Occlusion Readback Buffer"),
+            size: results_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let cube_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Occlusion Cube Buffer"),
+            contents: bytemuck::cast_slice(&Self::unit_cube_triangles()),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let box_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Occlusion Box Buffer"),
+            size: MAX_QUERIES as wgpu::BufferAddress * std::mem::size_of::<OutlineBox>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            pipeline,
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            cube_buffer,
+            box_buffer,
+            queried: Vec::new(),
+            readback: Readback::Idle,
+            occluded: HashSet::new(),
+            draw_queries: HashMap::new(),
+            box_queries: Vec::new(),
+        })
+    }
+
+    fn unit_cube_triangles() -> [[f32; 3]; BOX_VERTEX_COUNT as usize] {
+        let corner = |i: usize| [(i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32];
+
+        // Same corner numbering as the outline edges. Winding doesn't matter as nothing is culled.
+        let mut vertices = [[0.0; 3]; BOX_VERTEX_COUNT as usize];
+        let mut i = 0;
+        for axis in 0..3 {
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            for side in 0..2 {
+                let face = |a: usize, b: usize| corner(side << axis | a << u | b << v);
+                for c in [face(0, 0), face(1, 0), face(1, 1), face(0, 0), face(1, 1), face(0, 1)] {
+                    vertices[i] = c;
+                    i += 1;
+                }
+            }
+        }
+        vertices
+    }
+
+    pub fn is_occluded(&self, pos: &SectionPos) -> bool {
+        self.occluded.contains(pos)
+    }
+
+    // Forgets all visibility, e.g. when culling is switched off.
+    pub fn reset(&mut self) {
+        self.occluded.clear();
+        self.draw_queries.clear();
+        self.box_queries.clear();
+    }
+
+    // Picks up finished query results and plans this frame's queries for the given sections,
+    // each with whether it has opaque geometry to draw inside a query.
+    pub fn begin_frame(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sections: &[(SectionPos, bool)],
+        camera_position: cgmath::Point3<f32>,
+    ) {
+        self.collect_results(device);
+
+        self.draw_queries.clear();
+        self.box_queries.clear();
+
+        let loaded = sections.iter().map(|(pos, _)| *pos).collect::<HashSet<_>>();
+        self.occluded.retain(|pos| loaded.contains(pos));
+
+        if !matches!(self.readback, Readback::Idle) {
+            return;
+        }
+
+        self.queried.clear();
+        let mut boxes = Vec::new();
+
+        for &(pos, has_opaque) in sections {
+            if self.queried.len() as u32 == MAX_QUERIES {
+                break;
+            }
+
+            let min = pos.min_corner();
+            let max = [0, 1, 2].map(|i| min[i] + SectionPos::size()[i]);
+            let camera = [camera_position.x, camera_position.y, camera_position.z];
+            let is_near = (0..3).all(|i| camera[i] > min[i] - NEAR_MARGIN && camera[i] < max[i] + NEAR_MARGIN);
+            if is_near {
+                self.occluded.remove(&pos);
+                continue;
+            }
+
+            let index = self.queried.len() as u32;
+            self.queried.push(pos);
+
+            // Sections with only transparent geometry have nothing to draw in the opaque pass, so
+            // they are tested with their bounding box too.
+            if has_opaque && !self.occluded.contains(&pos) {
+                self.draw_queries.insert(pos, index);
+            } else {
+                boxes.push(OutlineBox { min, max });
+                self.box_queries.push(index);
+            }
+        }
+
+        if !boxes.is_empty() {
+            queue.write_buffer(&self.box_buffer, 0, bytemuck::cast_slice(&boxes));
+        }
+    }
+
+    fn collect_results(&mut self, device: &wgpu::Device) {
+        if matches!(self.readback, Readback::Recorded) {
+            let result = Arc::new(OnceLock::new());
+            let callback_result = result.clone();
+            self.readback_buffer.slice(..).map_async(wgpu::MapMode::Read, move |mapped| {
+                let _ = callback_result.set(mapped.is_ok());
+            });
+            self.readback = Readback::Mapping(result);
+        }
+
+        let Readback::Mapping(result) = &self.readback else {
+            return;
+        };
+
+        let _ = device.poll(wgpu::PollType::Poll);
+        let Some(&mapped) = result.get() else {
+            return;
+        };
+
+        if mapped {
+            {
+                let data = self.readback_buffer.slice(..).get_mapped_range();
+                let samples = bytemuck::cast_slice::<u8, u64>(&data);
+                for (pos, &passed) in self.queried.iter().zip(samples) {
+                    if passed == 0 {
+                        self.occluded.insert(*pos);
+                    } else {
+                        self.occluded.remove(pos);
+                    }
+                }
+            }
+            self.readback_buffer.unmap();
+        } else {
+            log::warn!("Failed to read back occlusion query results");
+        }
+
+        self.readback = Readback::Idle;
+    }
+
+    // Set on the render pass while this frame records queries.
+    pub fn query_set(&self) -> Option<&wgpu::QuerySet> {
+        let recording = !self.draw_queries.is_empty() || !self.box_queries.is_empty();
+        recording.then_some(&self.query_set)
+    }
+
+    // The query to wrap around drawing this section's opaque geometry, if any.
+    pub fn draw_query(&self, pos: &SectionPos) -> Option<u32> {
+        self.draw_queries.get(pos).copied()
+    }
+
+    // Tests the bounding boxes against the depth buffer; must come after all opaque geometry.
+    // Leaves the occlusion pipeline bound with the camera in group 0.
+    pub fn render_boxes<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a BindGroup) {
+        if self.box_queries.is_empty() {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.cube_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.box_buffer.slice(..));
+
+        for (instance, &query) in self.box_queries.iter().enumerate() {
+            let instance = instance as u32;
+            render_pass.begin_occlusion_query(query);
+            render_pass.draw(0..BOX_VERTEX_COUNT, instance..instance + 1);
+            render_pass.end_occlusion_query();
+        }
+    }
+
+    // Copies this frame's results towards the readback buffer, after the render pass ends.
+    // Any further frames drawn before the next `begin_frame` record no queries.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.query_set().is_none() {
+            return;
+        }
+
+        let count = self.queried.len() as u32;
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            count as wgpu::BufferAddress * QUERY_RESULT_SIZE,
+        );
+        self.readback = Readback::Recorded;
+        self.draw_queries.clear();
+        self.box_queries.clear();
+    }
+}
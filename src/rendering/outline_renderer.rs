@@ -0,0 +1,311 @@
+use wgpu::util::DeviceExt;
+use wgpu::{BindGroup, RenderPipeline};
+use crate::rendering::frustum::Frustum;
+use crate::rendering::texture::Texture;
+
+// Pushes outlines just outside the voxels so they don't z-fight with the blocks' faces.
+const OUTLINE_OFFSET: f32 = 0.002;
+const EDGE_VERTEX_COUNT: u32 = 24;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct OutlineUniform {
+    depth_bias: f32,
+    _padding: [f32; 3],
+}
+
+// An axis-aligned box to outline, in world coordinates.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct OutlineBox {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl OutlineBox {
+    pub fn voxel(pos: (i32, i32, i32)) -> Self {
+        Self::region(pos, pos)
+    }
+
+    // Covers every voxel between the two corners, inclusive.
+    pub fn region(a: (i32, i32, i32), b: (i32, i32, i32)) -> Self {
+        let min = [a.0.min(b.0), a.1.min(b.1), a.2.min(b.2)];
+        let max = [a.0.max(b.0), a.1.max(b.1), a.2.max(b.2)];
+
+        Self {
+            min: min.map(|c| c as f32 - OUTLINE_OFFSET),
+            max: max.map(|c| (c + 1) as f32 + OUTLINE_OFFSET),
+        }
+    }
+}
+
+// Draws the 12 edges of any number of boxes as instanced line lists: one shared unit-cube edge
+// list, stretched per instance to each box. A frustum shares the cube's edge layout, so it is
+// drawn as its own world-space edge list through a single unit-box instance.
+pub struct OutlineRenderer {
+    pipeline: RenderPipeline,
+    edge_buffer: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+    instance_count: u32,
+    frustum_buffer: wgpu::Buffer,
+    unit_box_buffer: wgpu::Buffer,
+    show_frustum: bool,
+    // A single world-space line segment, also drawn through the unit-box instance.
+    line_buffer: wgpu::Buffer,
+    show_line: bool,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: BindGroup,
+}
+
+impl OutlineRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Outline Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../../resources/shaders/outline.wgsl").into()),
+        });
+
+        let uniform_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("outline_bind_group_layout"),
+        });
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Outline Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[OutlineUniform { depth_bias: 0.0, _padding: [0.0; 3] }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                }
+            ],
+            label: Some("outline_bind_group"),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Outline Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &uniform_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Outline Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_outline"),
+                buffers: &[
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+                    },
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<OutlineBox>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &wgpu::vertex_attr_array![1 => Float32x3, 2 => Float32x3],
+                    },
+                ],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        let edge_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Outline Edge Buffer"),
+            contents: bytemuck::cast_slice(&Self::unit_cube_edges()),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let instance_capacity = 1;
+        let instance_buffer = Self::create_instance_buffer(device, instance_capacity);
+
+        let frustum_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Outline Frustum Buffer"),
+            size: std::mem::size_of::<[[f32; 3]; EDGE_VERTEX_COUNT as usize]>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let line_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Outline Line Buffer"),
+            size: std::mem::size_of::<[[f32; 3]; 2]>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let unit_box_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Outline Unit Box Buffer"),
+            contents: bytemuck::cast_slice(&[OutlineBox { min: [0.0; 3], max: [1.0; 3] }]),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        Self {
+            pipeline,
+            edge_buffer,
+            instance_buffer,
+            instance_capacity,
+            instance_count: 0,
+            frustum_buffer,
+            unit_box_buffer,
+            show_frustum: false,
+            line_buffer,
+            show_line: false,
+            uniform_buffer,
+            uniform_bind_group,
+        }
+    }
+
+    // How far outlines are pulled towards the camera, in normalized depth (0 to 1 across the
+    // view), to keep them from z-fighting with the faces they sit on.
+    pub fn set_depth_bias(&self, queue: &wgpu::Queue, depth_bias: f32) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[OutlineUniform { depth_bias, _padding: [0.0; 3] }]));
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Outline Instance Buffer"),
+            size: (capacity * std::mem::size_of::<OutlineBox>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    // Replaces the outlined boxes. An empty slice draws nothing.
+    pub fn set_boxes(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, boxes: &[OutlineBox]) {
+        if boxes.len() > self.instance_capacity {
+            self.instance_capacity = boxes.len().next_power_of_two();
+            self.instance_buffer = Self::create_instance_buffer(device, self.instance_capacity);
+        }
+
+        if !boxes.is_empty() {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(boxes));
+        }
+        self.instance_count = boxes.len() as u32;
+    }
+
+    // Replaces the outlined frustum. `None` hides it.
+    pub fn set_frustum(&mut self, queue: &wgpu::Queue, frustum: Option<&Frustum>) {
+        self.show_frustum = frustum.is_some();
+        if let Some(frustum) = frustum {
+            let corners = frustum.corners();
+            let edges = Self::cube_edges(|i| corners[i].into());
+            queue.write_buffer(&self.frustum_buffer, 0, bytemuck::cast_slice(&edges));
+        }
+    }
+
+    // Replaces the outlined line segment. `None` hides it.
+    pub fn set_line(&mut self, queue: &wgpu::Queue, line: Option<[cgmath::Point3<f32>; 2]>) {
+        self.show_line = line.is_some();
+        if let Some([start, end]) = line {
+            let vertices: [[f32; 3]; 2] = [start.into(), end.into()];
+            queue.write_buffer(&self.line_buffer, 0, bytemuck::cast_slice(&vertices));
+        }
+    }
+
+    fn unit_cube_edges() -> [[f32; 3]; EDGE_VERTEX_COUNT as usize] {
+        Self::cube_edges(|i| [(i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32])
+    }
+
+    fn cube_edges(corner: impl Fn(usize) -> [f32; 3]) -> [[f32; 3]; EDGE_VERTEX_COUNT as usize] {
+        // Corners are numbered by which axes are at their max (bit 0 = x, 1 = y, 2 = z); each
+        // edge joins two corners differing in exactly one bit.
+        let edges = [
+            (0, 1), (2, 3), (4, 5), (6, 7),
+            (0, 2), (1, 3), (4, 6), (5, 7),
+            (0, 4), (1, 5), (2, 6), (3, 7),
+        ];
+
+        let mut vertices = [[0.0; 3]; EDGE_VERTEX_COUNT as usize];
+        for (i, (a, b)) in edges.into_iter().enumerate() {
+            vertices[i * 2] = corner(a);
+            vertices[i * 2 + 1] = corner(b);
+        }
+        vertices
+    }
+
+    pub fn draw_call_count(&self) -> usize {
+        (self.instance_count > 0) as usize + self.show_frustum as usize + self.show_line as usize
+    }
+
+    pub fn render<'rpass>(&'rpass self, render_pass: &mut wgpu::RenderPass<'rpass>, camera_bind_group: &'rpass BindGroup) {
+        if self.instance_count == 0 && !self.show_frustum && !self.show_line {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+
+        if self.instance_count > 0 {
+            render_pass.set_vertex_buffer(0, self.edge_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+            render_pass.draw(0..EDGE_VERTEX_COUNT, 0..self.instance_count);
+        }
+
+        if self.show_frustum {
+            render_pass.set_vertex_buffer(0, self.frustum_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, self.unit_box_buffer.slice(..));
+            render_pass.draw(0..EDGE_VERTEX_COUNT, 0..1);
+        }
+
+        if self.show_line {
+            render_pass.set_vertex_buffer(0, self.line_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, self.unit_box_buffer.slice(..));
+            render_pass.draw(0..2, 0..1);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use crate::game::camera::Camera;
 
 #[rustfmt::skip]
@@ -8,8 +9,24 @@ const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::from_cols(
     cgmath::Vector4::new(0.0, 0.0, 0.5, 1.0),
 );
 
+// Which way the field of view is measured, and so stays the same when the window's aspect
+// ratio changes.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FovAxis {
+    // Wider windows see more to the sides.
+    #[default]
+    Vertical,
+    // Narrower windows see more above and below instead of less to the sides.
+    Horizontal,
+}
+
 pub struct Projection {
     pub aspect: f32,
+    // `fovy` is the current FOV, in degrees along `fov_axis`; effects like sprinting move it
+    // away from `base_fovy`.
+    pub fov_axis: FovAxis,
+    pub base_fovy: f32,
     pub fovy: f32,
     pub znear: f32,
     pub zfar: f32,
@@ -19,6 +36,8 @@ impl Projection {
     pub fn new(width: u32, height: u32) -> Self {
         Self {
             aspect: width as f32 / height as f32,
+            fov_axis: FovAxis::Vertical,
+            base_fovy: 68.0,
             fovy: 68.0,
             znear: 0.1,
             zfar: 1000.0,
@@ -29,11 +48,62 @@ impl Projection {
         self.aspect = width as f32 / height as f32;
     }
 
+    // The vertical FOV in degrees, worked out from the aspect ratio when the horizontal one
+    // is fixed.
+    pub fn vertical_fov(&self) -> f32 {
+        match self.fov_axis {
+            FovAxis::Vertical => self.fovy,
+            FovAxis::Horizontal => {
+                let half_width = (self.fovy.to_radians() / 2.0).tan();
+                (2.0 * (half_width / self.aspect).atan()).to_degrees()
+            }
+        }
+    }
+
     pub fn get_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-        cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar)
+        cgmath::perspective(cgmath::Deg(self.vertical_fov()), self.aspect, self.znear, self.zfar)
     }
 
     pub fn get_view_projection_matrix(&self, camera: &Camera) -> [[f32; 4]; 4] {
         (OPENGL_TO_WGPU_MATRIX * self.get_projection_matrix() * camera.get_view_matrix()).into()
     }
-}
\ No newline at end of file
+}
+
+// Remembers the last view-projection matrix along with the camera and projection values it
+// was built from, so the camera uniform is only rebuilt and re-uploaded when one of them
+// changes. With `enabled` off every call counts as a change.
+pub struct ViewProjectionCache {
+    pub enabled: bool,
+    inputs: Option<[f32; 9]>,
+}
+
+impl ViewProjectionCache {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            inputs: None,
+        }
+    }
+
+    // Returns the new matrix if it differs from the last one returned.
+    pub fn update(&mut self, projection: &Projection, camera: &Camera) -> Option<[[f32; 4]; 4]> {
+        let inputs = [
+            camera.position.x,
+            camera.position.y,
+            camera.position.z,
+            camera.yaw,
+            camera.pitch,
+            projection.aspect,
+            projection.vertical_fov(),
+            projection.znear,
+            projection.zfar,
+        ];
+
+        if self.enabled && self.inputs == Some(inputs) {
+            return None;
+        }
+
+        self.inputs = Some(inputs);
+        Some(projection.get_view_projection_matrix(camera))
+    }
+}
@@ -1,4 +1,8 @@
 use crate::rendering::texture::Texture;
+use crate::rendering::texture_atlas::TextureAtlas;
+
+// Must match the tile count passed to TextureAtlas::new.
+const ATLAS_TILES: u32 = 16;
 
 pub struct SharedResources {
     pub voxel_texture: Texture,
@@ -12,12 +16,15 @@ impl SharedResources {
         texture_bind_group_layout: &wgpu::BindGroupLayout,
     ) -> Self {
         let diffuse_bytes = include_bytes!("../../resources/textures/voxel_textures.png");
+        let atlas = image::load_from_memory(diffuse_bytes).unwrap().to_rgba8();
+        let padded = TextureAtlas::pad_tiles(&atlas, ATLAS_TILES);
+        let mip_level_count = TextureAtlas::mip_level_count(&padded, ATLAS_TILES);
         let voxel_texture =
-            Texture::from_bytes(&device, &queue, diffuse_bytes, "happy-tree.png").unwrap();
+            Texture::from_image(device, queue, &padded.into(), Some("voxel_textures.png"), mip_level_count).unwrap();
 
         let voxel_bind_group = device.create_bind_group(
             &wgpu::BindGroupDescriptor {
-                layout: &texture_bind_group_layout,
+                layout: texture_bind_group_layout,
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
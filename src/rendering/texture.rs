@@ -1,6 +1,25 @@
 use image::GenericImageView;
 use anyhow::*;
 
+// Format and size of the color target frames are drawn into, whether that is a window's
+// surface or an offscreen texture.
+#[derive(Copy, Clone, Debug)]
+pub struct RenderTargetDesc {
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl From<&wgpu::SurfaceConfiguration> for RenderTargetDesc {
+    fn from(config: &wgpu::SurfaceConfiguration) -> Self {
+        Self {
+            format: config.format,
+            width: config.width,
+            height: config.height,
+        }
+    }
+}
+
 pub struct Texture {
     #[allow(unused)]
     pub texture: wgpu::Texture,
@@ -11,20 +30,26 @@ pub struct Texture {
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
-    pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, label: &str) -> Self {
+    pub fn create_depth_texture(
+        device: &wgpu::Device,
+        target: RenderTargetDesc,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
         let size = wgpu::Extent3d { // 2.
-            width: config.width.max(1),
-            height: config.height.max(1),
+            width: target.width.max(1),
+            height: target.height.max(1),
             depth_or_array_layers: 1,
         };
         let desc = wgpu::TextureDescriptor {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT // 3.
+                // Read by the depth debug view.
                 | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[],
         };
@@ -48,24 +73,66 @@ impl Texture {
 
         Self { texture, view, sampler }
     }
-    
-    pub fn from_bytes(
+
+    // Color target to render into with MSAA; it is resolved into the surface texture.
+    pub fn create_multisampled_texture(
         device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        bytes: &[u8],
-        label: &str
-    ) -> Result<Self> {
-        let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+        target: RenderTargetDesc,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: target.width.max(1),
+                height: target.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: target.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+        Self { texture, view, sampler }
     }
 
+    // Color target for rendering without a window, which can be copied out for readback.
+    pub fn create_offscreen_target(device: &wgpu::Device, target: RenderTargetDesc, label: &str) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: target.width.max(1),
+                height: target.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: target.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+        Self { texture, view, sampler }
+    }
+
+    // Uploads the image along with `mip_level_count` levels of mipmaps made by halving it.
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
-        label: Option<&str>
+        label: Option<&str>,
+        mip_level_count: u32,
     ) -> Result<Self> {
-        let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
 
         let size = wgpu::Extent3d {
@@ -77,7 +144,7 @@ impl Texture {
             &wgpu::TextureDescriptor {
                 label,
                 size,
-                mip_level_count: 1,
+                mip_level_count,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
                 format: wgpu::TextureFormat::Rgba8UnormSrgb,
@@ -86,21 +153,28 @@ impl Texture {
             }
         );
 
-        queue.write_texture(
-            wgpu::TexelCopyTextureInfo {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &rgba,
-            wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            size,
-        );
+        let mut level = img.to_rgba8();
+        for mip_level in 0..mip_level_count {
+            if mip_level > 0 {
+                level = Self::downsample(&level);
+            }
+
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &level,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * level.width()),
+                    rows_per_image: Some(level.height()),
+                },
+                size.mip_level_size(mip_level, wgpu::TextureDimension::D2),
+            );
+        }
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = device.create_sampler(
@@ -108,13 +182,39 @@ impl Texture {
                 address_mode_u: wgpu::AddressMode::ClampToEdge,
                 address_mode_v: wgpu::AddressMode::ClampToEdge,
                 address_mode_w: wgpu::AddressMode::ClampToEdge,
+                // Crisp texels up close, smoothly filtered ones in the distance so they don't
+                // shimmer as the camera moves.
                 mag_filter: wgpu::FilterMode::Nearest,
-                min_filter: wgpu::FilterMode::Nearest,
-                mipmap_filter: wgpu::FilterMode::Nearest,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Linear,
                 ..Default::default()
             }
         );
 
         Ok(Self { texture, view, sampler })
     }
+
+    // Halves an image, averaging each 2x2 block. Colors are weighted by alpha so that fully
+    // transparent texels don't darken what's next to them.
+    fn downsample(image: &image::RgbaImage) -> image::RgbaImage {
+        let (width, height) = image.dimensions();
+
+        image::RgbaImage::from_fn((width / 2).max(1), (height / 2).max(1), |x, y| {
+            let mut rgb = [0; 3];
+            let mut alpha = 0;
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let pixel = image.get_pixel((x * 2 + dx).min(width - 1), (y * 2 + dy).min(height - 1));
+                for (sum, channel) in rgb.iter_mut().zip(&pixel.0) {
+                    *sum += *channel as u32 * pixel[3] as u32;
+                }
+                alpha += pixel[3] as u32;
+            }
+
+            if alpha == 0 {
+                return image::Rgba([0, 0, 0, 0]);
+            }
+            let [r, g, b] = rgb.map(|sum| ((sum + alpha / 2) / alpha) as u8);
+            image::Rgba([r, g, b, ((alpha + 2) / 4) as u8])
+        })
+    }
 }
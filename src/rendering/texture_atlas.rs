@@ -1,53 +1,62 @@
+use image::RgbaImage;
 use crate::game::chunk::VoxelType;
 
+// Each tile is stored in the middle of a cell twice its size, surrounded by copies of its own
+// opposite edges as if it repeated. Filtering and lower mip levels then only ever blend a tile
+// with itself, never with its neighbors in the atlas.
 pub struct TextureAtlas {
+    // Width and height of one cell in UV units.
     tile_size: f32,
 }
 
 impl TextureAtlas {
-    pub fn new(atlas_size: u32, tile_count: u32) -> Self {
+    pub fn new(_atlas_size: u32, tile_count: u32) -> Self {
         Self {
             tile_size: 1.0 / tile_count as f32,
         }
     }
 
-    pub fn get_uvs(&self, voxel: VoxelType, face: FaceDirection) -> [[f32; 2]; 6] {
+    // UV of the top-left corner of the cell holding the tile used for this face.
+    pub fn get_tile_origin(&self, voxel: VoxelType, face: FaceDirection) -> [f32; 2] {
         let (u, v) = self.get_tile_coords(voxel, face);
+        [u * self.tile_size, v * self.tile_size]
+    }
 
-        let u_min = u * self.tile_size;
-        let u_max = (u + 1.0) * self.tile_size;
-        let v_min = v * self.tile_size;
-        let v_max = (v + 1.0) * self.tile_size;
-
-        // 6 vertices (2 triangles) for the quad
-        [
-            [u_min, v_max], // Bottom-left
-            [u_max, v_max], // Bottom-right
-            [u_max, v_min], // Top-right
-            [u_min, v_max], // Bottom-left
-            [u_max, v_min], // Top-right
-            [u_min, v_min], // Top-left
-        ]
+    // UV of the top-left corner of the tile itself, inside its padding, and its width.
+    pub fn get_tile_uv(&self, voxel: VoxelType, face: FaceDirection) -> ([f32; 2], f32) {
+        let [u, v] = self.get_tile_origin(voxel, face);
+        let padding = self.tile_size / 4.0;
+        ([u + padding, v + padding], self.tile_size / 2.0)
     }
 
     // Get tile position in the atlas (in tiles, not UV coords)
     fn get_tile_coords(&self, voxel: VoxelType, face: FaceDirection) -> (f32, f32) {
-        match voxel {
-            VoxelType::Air => (0.0, 0.0), // Shouldn't be rendered
-            VoxelType::Stone => (2.0, 0.0),
-            VoxelType::Dirt => (1.0, 0.0),
-            VoxelType::Grass => {
-                match face {
-                    FaceDirection::Top => (0.0, 1.0),    // Grass top
-                    FaceDirection::Bottom => (1.0, 0.0), // Dirt bottom
-                    _ => (0.0, 0.0), // Grass side
-                }
-            },
-        }
+        let [u, v] = voxel.def().atlas_coords(face);
+        (u as f32, v as f32)
+    }
+
+    // Lays out an atlas of `tile_count` by `tile_count` tiles with the padding described above,
+    // doubling its size.
+    pub fn pad_tiles(atlas: &RgbaImage, tile_count: u32) -> RgbaImage {
+        let tile = atlas.width() / tile_count;
+        let cell = tile * 2;
+
+        RgbaImage::from_fn(atlas.width() * 2, atlas.height() * 2, |x, y| {
+            // Cell-local coordinates shifted by the padding and wrapped onto the tile.
+            let source_x = x / cell * tile + (x % cell + tile / 2) % tile;
+            let source_y = y / cell * tile + (y % cell + tile / 2) % tile;
+            *atlas.get_pixel(source_x, source_y)
+        })
+    }
+
+    // Mip levels of a padded atlas down to one texel per cell. Smaller levels would blend tiles
+    // together.
+    pub fn mip_level_count(padded: &RgbaImage, tile_count: u32) -> u32 {
+        (padded.width() / tile_count).ilog2() + 1
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum FaceDirection {
     North,
     South,
@@ -55,4 +64,33 @@ pub enum FaceDirection {
     West,
     Top,
     Bottom,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padding_keeps_each_tile_in_its_own_cell() {
+        // Two by two tiles, four texels wide, each with its own color and a marked corner.
+        let tile_color = |tx: u32, ty: u32| [tx as u8 * 100, ty as u8 * 100, 50];
+        let atlas = RgbaImage::from_fn(8, 8, |x, y| {
+            let [r, g, b] = tile_color(x / 4, y / 4);
+            let corner = x % 4 == 0 && y % 4 == 0;
+            image::Rgba([r, g, if corner { 255 } else { b }, 255])
+        });
+
+        let padded = TextureAtlas::pad_tiles(&atlas, 2);
+        assert_eq!(padded.dimensions(), (16, 16));
+        assert_eq!(TextureAtlas::mip_level_count(&padded, 2), 4);
+
+        for (x, y, pixel) in padded.enumerate_pixels() {
+            let [r, g, _] = tile_color(x / 8, y / 8);
+            assert_eq!([pixel[0], pixel[1]], [r, g], "texel {}, {} is from another tile", x, y);
+
+            // The tile starts a quarter of the way into its cell and repeats from there.
+            let corner = (x % 8) % 4 == 2 && (y % 8) % 4 == 2;
+            assert_eq!(pixel[2] == 255, corner, "texel {}, {} is off the tile's grid", x, y);
+        }
+    }
+}
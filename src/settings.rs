@@ -0,0 +1,183 @@
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::game::brush::{BrushShape, MAX_BRUSH_RADIUS};
+use crate::game::chunk::VoxelType;
+use crate::game::game_mode::GameMode;
+use crate::game::ores::OreVeins;
+use crate::game::player::FallDamage;
+use crate::game::world::GenerationFeatures;
+use crate::input::player_controller::GamepadSettings;
+use crate::rendering::gpu_context::AdapterOptions;
+use crate::rendering::mesh::VertexFormat;
+use crate::rendering::projection::FovAxis;
+
+pub const SETTINGS_PATH: &str = "settings.json";
+
+pub const MOUSE_SENSITIVITY_RANGE: RangeInclusive<f32> = 0.0005..=0.01;
+pub const AXIS_SENSITIVITY_RANGE: RangeInclusive<f32> = 0.25..=4.0;
+pub const MOUSE_SMOOTHING_RANGE: RangeInclusive<f32> = 0.0..=0.2;
+pub const FOV_RANGE: RangeInclusive<f32> = 30.0..=110.0;
+pub const MAX_RENDER_DISTANCE: u32 = 8;
+
+// User-tunable options read from `settings.json` at startup. Missing fields fall back to
+// their defaults, so the file only needs to list what differs.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Draw the inside faces of water and glass too, so the far walls of a glass box show when
+    // looking out of it.
+    pub transparent_backfaces: bool,
+    pub held_block: VoxelType,
+    pub hotbar: Vec<VoxelType>,
+    // Jump on walking into a one-block step. Off by default, since a `step_height` of 1.0
+    // climbs those without jumping.
+    pub auto_jump: bool,
+    // Only re-upload the camera matrix when the camera or projection changed.
+    pub cache_view_projection: bool,
+    // Furthest the player can look up or down, in degrees (at most 90).
+    pub max_pitch: f32,
+    // Whether noclip spectating can pitch past vertical and all the way around.
+    pub spectator_free_pitch: bool,
+    pub generation: GenerationFeatures,
+    // How much of each ore generates, and at what depths.
+    pub ore_veins: OreVeins,
+    pub adapter: AdapterOptions,
+    // Pull the fog in while moving fast (sprinting, falling) to exaggerate the speed.
+    pub speed_fog: bool,
+    // 4x multisample anti-aliasing, where supported. Turn off on weak GPUs.
+    pub msaa: bool,
+    pub game_mode: GameMode,
+    // "compact" packs chunk vertices into 8 bytes instead of 48, for much less VRAM.
+    pub vertex_format: VertexFormat,
+    // Radians the camera turns per pixel of mouse movement.
+    pub mouse_sensitivity: f32,
+    // Multipliers on the mouse sensitivity for horizontal and vertical look.
+    pub sensitivity_x: f32,
+    pub sensitivity_y: f32,
+    pub invert_y: bool,
+    // Ease mouse movement into the look over `mouse_smoothing` seconds instead of applying it
+    // raw, which damps jitter at low frame rates.
+    pub smooth_mouse: bool,
+    pub mouse_smoothing: f32,
+    // Stick deadzones and look speed, for builds with the `gamepad` feature.
+    pub gamepad: GamepadSettings,
+    // Field of view in degrees along `fov_axis`, before effects like sprinting widen it.
+    pub fov: f32,
+    // Whether the vertical or horizontal FOV stays fixed when the window is resized.
+    pub fov_axis: FovAxis,
+    // Chunks loaded and drawn around the player in each horizontal direction.
+    pub render_distance: u32,
+    // Aim through transparent blocks (glass) at the block behind them, for both the selection
+    // outline and breaking/placing.
+    pub reach_through_transparent: bool,
+    // Tallest ledge the player walks up without jumping, in blocks. Full blocks need 1.0;
+    // 0 turns stepping off.
+    pub step_height: f32,
+    pub fall_damage: FallDamage,
+    // Meters walked between footsteps. 0 turns footsteps off. Unused until there is audio.
+    pub footstep_distance: f32,
+    // Block place/break sounds play at up to this fraction above or below normal pitch (0.1 =
+    // ±10%), varying from block to block. 0 turns it off. Unused until there is audio.
+    pub block_sound_pitch_variation: f32,
+    // Moves the block targeting ray's origin away from the camera, in blocks along the view's
+    // right, up and forward axes. Only useful for debugging targeting.
+    pub ray_origin_offset: [f32; 3],
+    // Pulls block outlines towards the camera so they don't flicker against the block's faces,
+    // in normalized depth. The same bias covers more distance farther away, where depth
+    // precision is worse. 0 turns it off.
+    pub outline_depth_bias: f32,
+    // Which blocks around the targeted one breaking and placing affect, and how far the brush
+    // reaches from it (at most MAX_BRUSH_RADIUS).
+    pub brush: BrushShape,
+    pub brush_radius: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            transparent_backfaces: false,
+            held_block: VoxelType::Stone,
+            hotbar: vec![VoxelType::Grass, VoxelType::Dirt, VoxelType::Stone, VoxelType::Glass, VoxelType::Glowstone],
+            auto_jump: false,
+            cache_view_projection: true,
+            max_pitch: 89.0,
+            spectator_free_pitch: true,
+            generation: GenerationFeatures::default(),
+            ore_veins: OreVeins::default(),
+            adapter: AdapterOptions::default(),
+            speed_fog: false,
+            msaa: true,
+            game_mode: GameMode::default(),
+            vertex_format: VertexFormat::default(),
+            mouse_sensitivity: 0.003,
+            sensitivity_x: 1.0,
+            sensitivity_y: 1.0,
+            invert_y: false,
+            smooth_mouse: false,
+            mouse_smoothing: 0.03,
+            gamepad: GamepadSettings::default(),
+            fov: 68.0,
+            fov_axis: FovAxis::Vertical,
+            render_distance: 4,
+            reach_through_transparent: false,
+            step_height: 0.6,
+            fall_damage: FallDamage::default(),
+            footstep_distance: 1.7,
+            block_sound_pitch_variation: 0.1,
+            ray_origin_offset: [0.0; 3],
+            outline_depth_bias: 0.00001,
+            brush: BrushShape::Single,
+            brush_radius: 1,
+        }
+    }
+}
+
+impl Settings {
+    // Reads settings from `path`, using defaults if the file is missing or invalid.
+    pub fn load(path: &Path) -> Self {
+        let mut settings = match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                log::warn!("Invalid settings file {}: {}", path.display(), e);
+                Settings::default()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Settings::default(),
+            Err(e) => {
+                log::warn!("Failed to read settings file {}: {}", path.display(), e);
+                Settings::default()
+            }
+        };
+
+        settings.hotbar.retain(|voxel| *voxel != VoxelType::Air);
+        if settings.hotbar.is_empty() {
+            settings.hotbar = Settings::default().hotbar;
+        }
+
+        settings.max_pitch = settings.max_pitch.clamp(0.0, 90.0);
+        settings.mouse_sensitivity = settings.mouse_sensitivity.clamp(*MOUSE_SENSITIVITY_RANGE.start(), *MOUSE_SENSITIVITY_RANGE.end());
+        settings.sensitivity_x = settings.sensitivity_x.clamp(*AXIS_SENSITIVITY_RANGE.start(), *AXIS_SENSITIVITY_RANGE.end());
+        settings.sensitivity_y = settings.sensitivity_y.clamp(*AXIS_SENSITIVITY_RANGE.start(), *AXIS_SENSITIVITY_RANGE.end());
+        settings.mouse_smoothing = settings.mouse_smoothing.clamp(*MOUSE_SMOOTHING_RANGE.start(), *MOUSE_SMOOTHING_RANGE.end());
+        settings.gamepad.move_deadzone = settings.gamepad.move_deadzone.clamp(0.0, 0.9);
+        settings.gamepad.look_deadzone = settings.gamepad.look_deadzone.clamp(0.0, 0.9);
+        settings.gamepad.look_speed = settings.gamepad.look_speed.max(0.0);
+        settings.fov = settings.fov.clamp(*FOV_RANGE.start(), *FOV_RANGE.end());
+        settings.render_distance = settings.render_distance.min(MAX_RENDER_DISTANCE);
+        settings.brush_radius = settings.brush_radius.min(MAX_BRUSH_RADIUS);
+        settings.step_height = settings.step_height.clamp(0.0, 1.5);
+        settings.outline_depth_bias = settings.outline_depth_bias.clamp(0.0, 0.01);
+        settings.footstep_distance = settings.footstep_distance.max(0.0);
+        settings.block_sound_pitch_variation = settings.block_sound_pitch_variation.clamp(0.0, 1.0);
+        settings.fall_damage.safe_distance = settings.fall_damage.safe_distance.max(0.0);
+        settings.fall_damage.damage_per_block = settings.fall_damage.damage_per_block.max(0.0);
+
+        settings
+    }
+
+    // Writes every setting to `path`, so changes made in game are kept for the next run.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let contents = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        std::fs::write(path, contents)
+    }
+}
@@ -0,0 +1,51 @@
+use egui::{Color32, Context, Id, LayerId, Order, Rect, Vec2};
+
+// Sizes in points, so the crosshair scales with the window's scale factor.
+const ARM_LENGTH: f32 = 10.0;
+const THICKNESS: f32 = 2.0;
+const OUTLINE: f32 = 1.0;
+const TARGET_EXPANSION: f32 = 4.0;
+// How quickly the crosshair eases towards its targeted/untargeted size, in 1/s.
+const ANIMATION_RATE: f32 = 12.0;
+
+// A `+` in the middle of the screen whose arms grow a little while a block is targeted.
+pub struct Crosshair {
+    pub target_expansion: f32,
+    pub animation_rate: f32,
+    // 0 when nothing is targeted, easing to 1 while something is.
+    expansion: f32,
+}
+
+impl Crosshair {
+    pub fn new() -> Self {
+        Self {
+            target_expansion: TARGET_EXPANSION,
+            animation_rate: ANIMATION_RATE,
+            expansion: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, has_target: bool, dt: f32) {
+        let target = if has_target { 1.0 } else { 0.0 };
+        let t = (self.animation_rate * dt).min(1.0);
+        self.expansion += (target - self.expansion) * t;
+    }
+
+    // egui can't blend by inverting what's underneath, so a dark outline keeps the white cross
+    // readable over bright and dark blocks alike. Shapes are anti-aliased by egui.
+    pub fn draw(&self, ctx: &Context) {
+        let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("crosshair")));
+        let center = ctx.content_rect().center();
+        let arm_length = ARM_LENGTH + self.expansion * self.target_expansion;
+
+        let horizontal = Rect::from_center_size(center, Vec2::new(arm_length * 2.0, THICKNESS));
+        let vertical = Rect::from_center_size(center, Vec2::new(THICKNESS, arm_length * 2.0));
+
+        for rect in [horizontal, vertical] {
+            painter.rect_filled(rect.expand(OUTLINE), 0.0, Color32::from_black_alpha(160));
+        }
+        for rect in [horizontal, vertical] {
+            painter.rect_filled(rect, 0.0, Color32::WHITE);
+        }
+    }
+}
@@ -52,6 +52,11 @@ impl DebugUi {
         }
     }
 
+    // Makes a wgpu texture drawable from egui, e.g. for block icons from the atlas.
+    pub fn register_texture(&mut self, device: &Device, view: &TextureView) -> egui::TextureId {
+        self.renderer.register_native_texture(device, view, wgpu::FilterMode::Nearest)
+    }
+
     pub fn handle_input(&mut self, window: &Window, event: &WindowEvent) {
         let _ = self.state.on_window_event(window, event);
     }
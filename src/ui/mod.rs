@@ -1,2 +1,3 @@
+pub mod crosshair;
 pub mod debug_ui;
 pub mod panels;
\ No newline at end of file
@@ -0,0 +1,33 @@
+use egui::{Color32, RichText, Ui};
+use crate::console::Console;
+
+pub struct ConsolePanel;
+
+impl ConsolePanel {
+    // Returns the submitted line when Enter is pressed.
+    pub fn show(ui: &mut Ui, console: &mut Console) -> Option<String> {
+        ui.heading(RichText::new("Console").color(Color32::WHITE));
+        ui.separator();
+
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for line in console.lines() {
+                    ui.colored_label(Color32::WHITE, line);
+                }
+            });
+
+        // The toggle key's character would otherwise end up in the input.
+        console.input.retain(|c| c != '`');
+
+        let response = ui.text_edit_singleline(&mut console.input);
+        response.request_focus();
+
+        if ui.input(|i| i.key_pressed(egui::Key::Enter)) && !console.input.trim().is_empty() {
+            return Some(std::mem::take(&mut console.input));
+        }
+
+        None
+    }
+}
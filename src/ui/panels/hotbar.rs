@@ -0,0 +1,40 @@
+use egui::{Color32, Rect, Sense, Stroke, StrokeKind, TextureId, Ui, Vec2};
+use crate::game::chunk::VoxelType;
+use crate::rendering::texture_atlas::{FaceDirection, TextureAtlas};
+
+const SLOT_SIZE: f32 = 40.0;
+const ICON_PADDING: f32 = 4.0;
+
+pub struct HotbarPanel;
+
+impl HotbarPanel {
+    // Draws one slot per block, showing the block's side texture, with the selected slot
+    // outlined.
+    pub fn show(
+        ui: &mut Ui,
+        atlas_texture: TextureId,
+        atlas: &TextureAtlas,
+        blocks: &[VoxelType],
+        selected: usize,
+    ) {
+        ui.horizontal(|ui| {
+            for (slot, block) in blocks.iter().enumerate() {
+                let (rect, _) = ui.allocate_exact_size(Vec2::splat(SLOT_SIZE), Sense::hover());
+                let painter = ui.painter();
+
+                painter.rect_filled(rect, 2.0, Color32::from_black_alpha(160));
+
+                let ([u, v], tile_size) = atlas.get_tile_uv(*block, FaceDirection::North);
+                let uv = Rect::from_min_size(egui::pos2(u, v), Vec2::splat(tile_size));
+                painter.image(atlas_texture, rect.shrink(ICON_PADDING), uv, Color32::WHITE);
+
+                let stroke = if slot == selected {
+                    Stroke::new(2.0, Color32::WHITE)
+                } else {
+                    Stroke::new(1.0, Color32::from_gray(80))
+                };
+                painter.rect_stroke(rect, 2.0, stroke, StrokeKind::Inside);
+            }
+        });
+    }
+}
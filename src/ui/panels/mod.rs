@@ -1,3 +1,15 @@
+pub mod console;
+pub mod hotbar;
+pub mod pause_menu;
 pub mod player_info;
+pub mod render_stats;
+pub mod settings;
+pub mod time_of_day;
 
-pub use player_info::PlayerInfoPanel;
\ No newline at end of file
+pub use console::ConsolePanel;
+pub use hotbar::HotbarPanel;
+pub use pause_menu::{PauseChoice, PauseMenu};
+pub use player_info::PlayerInfoPanel;
+pub use render_stats::{RenderStats, RenderStatsPanel};
+pub use settings::SettingsPanel;
+pub use time_of_day::TimeOfDayPanel;
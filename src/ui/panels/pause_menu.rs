@@ -0,0 +1,26 @@
+use egui::{Button, Color32, RichText, Ui};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PauseChoice {
+    Resume,
+    Quit,
+}
+
+pub struct PauseMenu;
+
+impl PauseMenu {
+    // Returns the button clicked this frame, if any.
+    pub fn show(ui: &mut Ui) -> Option<PauseChoice> {
+        ui.heading(RichText::new("Paused").color(Color32::WHITE));
+        ui.separator();
+
+        let size = egui::vec2(160.0, 0.0);
+        if ui.add(Button::new("Resume").min_size(size)).clicked() {
+            return Some(PauseChoice::Resume);
+        }
+        if ui.add(Button::new("Quit").min_size(size)).clicked() {
+            return Some(PauseChoice::Quit);
+        }
+        None
+    }
+}
@@ -1,14 +1,24 @@
 use egui::{Color32, RichText, Ui};
-use cgmath::Point3;
+use crate::game::chunk::VoxelType;
+use crate::game::player::{Player, MAX_HEALTH};
+use crate::game::RaycastHit;
+use crate::game::terrain::Biome;
 
 pub struct PlayerInfoPanel;
 
 impl PlayerInfoPanel {
     pub fn show(
         ui: &mut Ui,
-        position: Point3<f32>,
-        velocity: cgmath::Vector3<f32>,
+        player: &Player,
+        held_block: VoxelType,
+        ground_block: Option<VoxelType>,
+        biome: Biome,
+        target: Option<&RaycastHit>,
+        break_progress: Option<f32>,
     ) {
+        let position = player.position;
+        let velocity = player.velocity;
+
         ui.heading(RichText::new("Player Info").color(Color32::WHITE));
         ui.separator();
 
@@ -30,5 +40,40 @@ impl PlayerInfoPanel {
                 velocity.z
             )
         );
+        ui.colored_label(
+            egui::Color32::WHITE,
+            format!("Health: {:.1} / {:.0}", player.health, MAX_HEALTH)
+        );
+        ui.horizontal(|ui| {
+            ui.colored_label(egui::Color32::WHITE, "Held block:");
+            ui.colored_label(held_block.ui_color(), &held_block.def().name);
+        });
+        ui.horizontal(|ui| {
+            ui.colored_label(egui::Color32::WHITE, "Standing on:");
+            match ground_block {
+                Some(voxel) => ui.colored_label(voxel.ui_color(), &voxel.def().name),
+                None => ui.colored_label(egui::Color32::GRAY, "nothing"),
+            };
+        });
+        ui.colored_label(egui::Color32::WHITE, format!("Biome: {:?}", biome));
+        match target {
+            Some(hit) => ui.colored_label(
+                egui::Color32::WHITE,
+                format!(
+                    "Target: {}, {}, {} ({:?} face at {:.2}, {:.2}, {:.2})",
+                    hit.position.0,
+                    hit.position.1,
+                    hit.position.2,
+                    hit.face,
+                    hit.hit_point.x,
+                    hit.hit_point.y,
+                    hit.hit_point.z
+                )
+            ),
+            None => ui.colored_label(egui::Color32::GRAY, "Target: nothing"),
+        };
+        if let Some(fraction) = break_progress {
+            ui.colored_label(egui::Color32::WHITE, format!("Breaking: {:.0}%", fraction * 100.0));
+        }
     }
-}
\ No newline at end of file
+}
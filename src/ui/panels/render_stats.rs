@@ -0,0 +1,40 @@
+use egui::{Color32, RichText, Ui};
+
+pub struct RenderStats {
+    pub fps: f32,
+    pub frame_time_ms: f32,
+    pub chunk_count: usize,
+    pub vertex_count: u64,
+    pub vertex_memory: u64,
+    pub draw_calls: usize,
+}
+
+pub struct RenderStatsPanel;
+
+impl RenderStatsPanel {
+    pub fn show(ui: &mut Ui, stats: &RenderStats) {
+        ui.heading(RichText::new("Render Stats").color(Color32::WHITE));
+        ui.separator();
+
+        ui.colored_label(
+            Color32::WHITE,
+            format!("FPS: {:.0} ({:.2} ms)", stats.fps, stats.frame_time_ms)
+        );
+        ui.colored_label(
+            Color32::WHITE,
+            format!("Chunks: {}", stats.chunk_count)
+        );
+        ui.colored_label(
+            Color32::WHITE,
+            format!("Vertices: {}", stats.vertex_count)
+        );
+        ui.colored_label(
+            Color32::WHITE,
+            format!("Vertex memory: {:.1} MiB", stats.vertex_memory as f64 / (1024.0 * 1024.0))
+        );
+        ui.colored_label(
+            Color32::WHITE,
+            format!("Draw calls: {}", stats.draw_calls)
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use egui::{Color32, ComboBox, RichText, Slider, Ui};
+use crate::game::brush::{BrushShape, MAX_BRUSH_RADIUS};
+use crate::rendering::projection::FovAxis;
+use crate::settings::{Settings, AXIS_SENSITIVITY_RANGE, FOV_RANGE, MAX_RENDER_DISTANCE, MOUSE_SENSITIVITY_RANGE, MOUSE_SMOOTHING_RANGE};
+
+pub struct SettingsPanel;
+
+impl SettingsPanel {
+    // Returns whether any setting was changed.
+    pub fn show(ui: &mut Ui, settings: &mut Settings) -> bool {
+        ui.heading(RichText::new("Settings").color(Color32::WHITE));
+        ui.separator();
+
+        let sensitivity = ui.add(
+            Slider::new(&mut settings.mouse_sensitivity, MOUSE_SENSITIVITY_RANGE)
+                .logarithmic(true)
+                .text("Mouse sensitivity")
+        );
+        let sensitivity_x = ui.add(Slider::new(&mut settings.sensitivity_x, AXIS_SENSITIVITY_RANGE).text("Horizontal sensitivity"));
+        let sensitivity_y = ui.add(Slider::new(&mut settings.sensitivity_y, AXIS_SENSITIVITY_RANGE).text("Vertical sensitivity"));
+        let invert_y = ui.checkbox(&mut settings.invert_y, "Invert Y");
+        let smooth_mouse = ui.checkbox(&mut settings.smooth_mouse, "Smooth mouse");
+        let smoothing = ui.add_enabled(
+            settings.smooth_mouse,
+            Slider::new(&mut settings.mouse_smoothing, MOUSE_SMOOTHING_RANGE).suffix(" s").text("Mouse smoothing"),
+        );
+        let fov = ui.add(Slider::new(&mut settings.fov, FOV_RANGE).suffix("°").text("FOV"));
+        let mut fov_axis_changed = false;
+        ComboBox::from_label("Fixed FOV axis")
+            .selected_text(format!("{:?}", settings.fov_axis))
+            .show_ui(ui, |ui| {
+                for axis in [FovAxis::Vertical, FovAxis::Horizontal] {
+                    fov_axis_changed |= ui.selectable_value(&mut settings.fov_axis, axis, format!("{:?}", axis)).changed();
+                }
+            });
+        let render_distance = ui.add(
+            Slider::new(&mut settings.render_distance, 0..=MAX_RENDER_DISTANCE).text("Render distance")
+        );
+
+        let reach_through = ui.checkbox(&mut settings.reach_through_transparent, "Aim through glass");
+
+        let mut brush_changed = false;
+        ComboBox::from_label("Brush")
+            .selected_text(format!("{:?}", settings.brush))
+            .show_ui(ui, |ui| {
+                for shape in BrushShape::ALL {
+                    brush_changed |= ui.selectable_value(&mut settings.brush, shape, format!("{:?}", shape)).changed();
+                }
+            });
+        let brush_radius = ui.add_enabled(
+            settings.brush != BrushShape::Single,
+            Slider::new(&mut settings.brush_radius, 0..=MAX_BRUSH_RADIUS).text("Brush radius"),
+        );
+
+        sensitivity.changed() || sensitivity_x.changed() || sensitivity_y.changed() || invert_y.changed()
+            || smooth_mouse.changed() || smoothing.changed() || fov.changed() || fov_axis_changed || render_distance.changed() || reach_through.changed()
+            || brush_changed || brush_radius.changed()
+    }
+}
@@ -0,0 +1,24 @@
+use egui::{Color32, RichText, Slider, Ui};
+use crate::game::time_of_day::TimeOfDay;
+
+pub struct TimeOfDayPanel;
+
+impl TimeOfDayPanel {
+    pub fn show(ui: &mut Ui, time_of_day: &mut TimeOfDay) {
+        ui.heading(RichText::new("Time of Day").color(Color32::WHITE));
+        ui.separator();
+
+        let minutes = (time_of_day.time() * 24.0 * 60.0) as u32;
+        ui.colored_label(
+            Color32::WHITE,
+            format!("Clock: {:02}:{:02}", minutes / 60, minutes % 60)
+        );
+
+        let mut time = time_of_day.time();
+        if ui.add(Slider::new(&mut time, 0.0..=1.0).text("Time")).changed() {
+            time_of_day.set_time(time);
+        }
+        ui.add(Slider::new(&mut time_of_day.speed, 0.0..=100.0).logarithmic(true).text("Speed"));
+        ui.checkbox(&mut time_of_day.lock_noon, RichText::new("Lock sun overhead").color(Color32::WHITE));
+    }
+}